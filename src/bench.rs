@@ -0,0 +1,132 @@
+use std::io::{self, Write};
+use std::time::Duration;
+use solvers::{Algorithm, Budget, Target};
+use tune::median;
+
+/// Parameters for a benchmark.
+#[derive(Debug,Clone)]
+pub struct BenchConfig {
+    pub solvers: Vec<Algorithm>,
+    pub targets: Vec<f64>,
+    /// Number of runs of each solver on each target.
+    pub repeats: usize,
+    /// The budget of every run.
+    pub budget: Budget,
+}
+
+impl Default for BenchConfig {
+    fn default() -> BenchConfig {
+        BenchConfig {
+            solvers: Algorithm::ALL.to_vec(),
+            targets: vec![17.0, 42.0, 100.0, 1234.0, 271828.0],
+            repeats: 5,
+            budget: Budget { evaluations: 100_000, time: None },
+        }
+    }
+}
+
+/// The outcome of one run of a benchmark.
+#[derive(Debug,Clone)]
+pub struct Run {
+    pub solver: Algorithm,
+    pub target: f64,
+    pub repeat: usize,
+    pub solved: bool,
+    pub generations: usize,
+    pub evaluations: usize,
+    pub elapsed: Duration,
+}
+
+/// How one solver fared over all its runs.
+#[derive(Debug,Clone)]
+pub struct Summary {
+    pub solver: Algorithm,
+    /// The fraction of runs that found a solution.
+    pub success_rate: f64,
+    pub median_generations: f64,
+    /// Median wall-clock time per run, in seconds.
+    pub median_seconds: f64,
+}
+
+/// Run every solver `config.repeats` times on every target.
+pub fn bench(config: &BenchConfig) -> Vec<Run> {
+    let mut runs = Vec::new();
+    for &solver in &config.solvers {
+        for &target in &config.targets {
+            for repeat in 0..config.repeats {
+                let result = solver.solver().solve(Target::Value(target), config.budget.clone());
+                runs.push(Run {
+                    solver,
+                    target,
+                    repeat,
+                    solved: result.solution.is_some(),
+                    generations: result.generations,
+                    evaluations: result.evaluations,
+                    elapsed: result.elapsed,
+                });
+            }
+        }
+    }
+    runs
+}
+
+/// Summarize `runs` per solver, in order of first appearance.
+pub fn summarize(runs: &[Run]) -> Vec<Summary> {
+    let mut solvers: Vec<Algorithm> = Vec::new();
+    for run in runs {
+        if !solvers.contains(&run.solver) {
+            solvers.push(run.solver);
+        }
+    }
+    solvers.into_iter().map(|solver| {
+        let runs = runs.iter().filter(|r| r.solver == solver).collect::<Vec<_>>();
+        let solved = runs.iter().filter(|r| r.solved).count();
+        Summary {
+            solver,
+            success_rate: solved as f64 / runs.len() as f64,
+            median_generations: median(&mut runs.iter().map(|r| r.generations as f64)
+                                                       .collect::<Vec<_>>()),
+            median_seconds: median(&mut runs.iter().map(|r| r.elapsed.as_secs_f64())
+                                                   .collect::<Vec<_>>()),
+        }
+    }).collect()
+}
+
+/// Write `runs` as CSV, with a header line.
+pub fn write_csv<W: Write>(runs: &[Run], out: &mut W) -> io::Result<()> {
+    writeln!(out, "solver,target,repeat,solved,generations,evaluations,seconds")?;
+    for r in runs {
+        writeln!(out, "{},{},{},{},{},{},{}", r.solver, r.target, r.repeat, r.solved,
+                 r.generations, r.evaluations, r.elapsed.as_secs_f64())?;
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_bench() {
+        let config = BenchConfig {
+            solvers: vec![Algorithm::RandomSearch, Algorithm::Exhaustive],
+            targets: vec![7.0, 42.0],
+            repeats: 2,
+            budget: Budget { evaluations: 1000, time: None },
+        };
+        let runs = bench(&config);
+        assert_eq!(runs.len(), 8);
+
+        let summaries = summarize(&runs);
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[1].solver, Algorithm::Exhaustive);
+        assert_eq!(summaries[1].success_rate, 1.0);
+
+        let mut csv = Vec::new();
+        write_csv(&runs, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().count(), 9);
+        assert!(csv.lines().nth(5).unwrap().starts_with("exhaustive,7,0,true,"));
+    }
+}
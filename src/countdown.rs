@@ -0,0 +1,107 @@
+use expr::{self, Tok};
+use genetic::{Fitness, SymbolTable};
+
+/// Return the symbol table of a Countdown game with the given numbers: each
+/// distinct number, then `+`, `-`, `*` and `/`. Decoded symbols are
+/// separated by spaces, so that adjacent numbers stay apart.
+pub fn symbol_table(numbers: &[u32]) -> SymbolTable {
+    let mut distinct = numbers.to_vec();
+    distinct.sort();
+    distinct.dedup();
+    let mut symbols = distinct.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+    symbols.extend(["+", "-", "*", "/"].iter().map(|s| s.to_string()));
+    SymbolTable::new(symbols).with_separator(" ")
+}
+
+/// The Countdown numbers game: reach `target` combining `numbers` with the
+/// four basic operators, using each number at most as many times as it is
+/// given.
+///
+/// Expressions score `1 / (1 + |v - target|)` like `Closeness`, divided by
+/// one plus the number of excess uses, so that an expression reusing numbers
+/// is never a solution. Expressions that are malformed, have two numbers in
+/// a row or use numbers that were not given score 0.
+#[derive(Debug,Clone)]
+pub struct Countdown {
+    pub numbers: Vec<u32>,
+    pub target: f64,
+}
+
+impl Countdown {
+    pub fn new(numbers: &[u32], target: f64) -> Countdown {
+        Countdown { numbers: numbers.to_vec(), target }
+    }
+
+    /// Return the numbers used by `e` in order, or None if `e` does not
+    /// alternate between numbers and operators or uses numbers not given.
+    fn used(&self, e: &str) -> Option<Vec<u32>> {
+        let toks = expr::tok(e).ok()?;
+        let mut used = Vec::new();
+        for (i, t) in toks.iter().enumerate() {
+            match *t {
+                Tok::Num(n) if i % 2 == 0 => {
+                    used.push(*self.numbers.iter().find(|&&m| m as f64 == n)?);
+                }
+                Tok::Op(_) if i % 2 == 1 => {}
+                _ => return None,
+            }
+        }
+        Some(used)
+    }
+
+    /// Return the number of uses in `used` beyond the multiplicity of each
+    /// number in the game.
+    fn reuses(&self, used: &[u32]) -> usize {
+        let mut available = self.numbers.clone();
+        used.iter().filter(|n| {
+            match available.iter().position(|m| m == *n) {
+                Some(i) => { available.swap_remove(i); false }
+                None => true,
+            }
+        }).count()
+    }
+}
+
+impl Fitness for Countdown {
+    fn fitness(&self, e: &str) -> f64 {
+        let used = match self.used(e) {
+            Some(used) => used,
+            None => return 0f64,
+        };
+        match expr::eval(e) {
+            Ok(v) if !v.is_nan() => {
+                1f64 / (1f64 + (v - self.target).abs()) / (1 + self.reuses(&used)) as f64
+            }
+            _ => 0f64,
+        }
+    }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_symbol_table() {
+        let table = symbol_table(&[25, 50, 3, 3]);
+        assert_eq!(table.symbols(), &["3", "25", "50", "+", "-", "*", "/"]);
+        assert_eq!(table.concat(&[1, 5, 0]), "25 * 3");
+        assert_eq!(table.encode("25*3"), Ok(vec![1, 5, 0]));
+    }
+
+    #[test]
+    pub fn test_countdown() {
+        let game = Countdown::new(&[25, 50, 3, 7, 8, 9], 952.0);
+        assert_eq!(game.fitness("9 * 7"), 1f64 / (1f64 + 889f64));
+        assert_eq!(Countdown { target: 75.0, ..game.clone() }.fitness("25 * 3"), 1f64);
+        assert_eq!(game.fitness("50 * 50"), 1f64 / (1f64 + 1548f64) / 2f64);
+        assert_eq!(game.fitness("25 3"), 0f64);
+        assert_eq!(game.fitness("4 + 3"), 0f64);
+        assert_eq!(game.fitness("3 +"), 0f64);
+
+        let game = Countdown::new(&[3, 3, 7], 13.0);
+        assert_eq!(game.fitness("3 + 3 + 7"), 1f64);
+        assert!(game.fitness("3 + 3 + 3 + 7 - 3") < 0.5);
+    }
+}
@@ -0,0 +1,97 @@
+use genetic::{Fitness, SymbolTable};
+
+/// Limits on the digits an expression may use, e.g., only 1 through 6,
+/// each at most twice.
+#[derive(Debug,Clone,PartialEq)]
+pub struct DigitLimits {
+    /// The digits allowed.
+    pub allowed: Vec<char>,
+    /// If set, how many times each digit may appear.
+    pub max_uses: Option<usize>,
+}
+
+impl Default for DigitLimits {
+    fn default() -> DigitLimits {
+        DigitLimits { allowed: "0123456789".chars().collect(), max_uses: None }
+    }
+}
+
+impl DigitLimits {
+    /// Return the number of digit uses in `e` beyond `max_uses`, or None if
+    /// `e` uses a digit that is not allowed.
+    pub fn excess(&self, e: &str) -> Option<usize> {
+        let mut uses = [0usize; 10];
+        for c in e.chars().filter(|c| c.is_ascii_digit()) {
+            if !self.allowed.contains(&c) {
+                return None;
+            }
+            uses[c.to_digit(10).unwrap() as usize] += 1;
+        }
+        let max_uses = self.max_uses.unwrap_or(usize::MAX);
+        Some(uses.iter().map(|&n| n.saturating_sub(max_uses)).sum())
+    }
+
+    /// Return a copy of `symbols` without the symbols using digits that are
+    /// not allowed.
+    pub fn symbols(&self, symbols: &SymbolTable) -> SymbolTable {
+        let unwanted = symbols.symbols().iter()
+                              .filter(|s| s.chars().any(|c| {
+                                  c.is_ascii_digit() && !self.allowed.contains(&c)
+                              }))
+                              .map(|s| &s[..])
+                              .collect::<Vec<_>>();
+        symbols.without(&unwanted)
+    }
+}
+
+/// A fitness function subject to digit limits: expressions using digits
+/// that are not allowed score 0, and those using digits too often have their
+/// score divided by one plus the number of excess uses, so that they are
+/// never a solution.
+pub struct Limited<F: Fitness + ?Sized> {
+    pub fitness: Box<F>,
+    pub limits: DigitLimits,
+}
+
+impl<F: Fitness + ?Sized> Fitness for Limited<F> {
+    fn fitness(&self, e: &str) -> f64 {
+        match self.limits.excess(e) {
+            Some(excess) => self.fitness.fitness(e) / (1 + excess) as f64,
+            None => 0f64,
+        }
+    }
+
+    fn cases(&self, e: &str) -> Vec<f64> {
+        let cases = self.fitness.cases(e);
+        match self.limits.excess(e) {
+            Some(excess) => cases.into_iter().map(|c| c / (1 + excess) as f64).collect(),
+            None => vec![0f64; cases.len()],
+        }
+    }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use genetic::Closeness;
+
+    #[test]
+    pub fn test_digit_limits() {
+        let limits = DigitLimits { allowed: "123456".chars().collect(), max_uses: Some(2) };
+        assert_eq!(limits.excess("6*6+1"), Some(0));
+        assert_eq!(limits.excess("66*6+1111"), Some(3));
+        assert_eq!(limits.excess("7*6"), None);
+        assert_eq!(DigitLimits::default().excess("7777"), Some(0));
+
+        let symbols = limits.symbols(&SymbolTable::default());
+        assert_eq!(symbols.len(), 12);
+        assert_eq!(symbols.encode("12"), Ok(vec![0, 1]));
+
+        let f = Limited { fitness: Box::new(Closeness::new(42.0)), limits };
+        assert_eq!(f.fitness("6*6+4+2"), 1f64);
+        assert_eq!(f.fitness("6*6+3+3"), 1f64);
+        assert_eq!(f.fitness("6*6+6"), 0.5);
+        assert_eq!(f.fitness("6*7"), 0f64);
+    }
+}
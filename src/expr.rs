@@ -1,15 +1,226 @@
+use std::collections::HashMap;
+use std::error;
+use std::f64::consts;
+use std::fmt;
+use std::iter;
 use std::result;
-use num;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
-pub type Result<T> = result::Result<T, String>;
+pub mod algebra;
+pub mod ast;
+pub mod exact;
+pub mod interval;
+mod pratt;
+pub mod program;
+pub mod random;
+pub mod registry;
+pub mod rewrite;
+pub mod serial;
+pub mod visit;
 
-#[derive(Debug,Clone)]
+pub use self::algebra::{derive, equivalent, simplify, simplify_with};
+pub use self::ast::{metrics, Metrics};
+pub use self::exact::{eval_big, eval_exact, eval_i64};
+pub use self::interval::{eval_interval, Interval};
+pub use self::program::{compile, Program};
+pub use self::random::{random_expr, SymbolPolicy};
+pub use self::registry::Registry;
+pub use self::rewrite::{Rewriter, Rule};
+pub use self::visit::{ExprTransformer, ExprVisitor};
+
+pub type Result<T> = result::Result<T, ExprError>;
+
+/// What can go wrong parsing or evaluating an expression.
+#[derive(Debug,Clone,PartialEq)]
+pub enum ExprError {
+    /// A character that starts no token, at this character offset.
+    InvalidToken { pos: usize, found: char },
+    InvalidNumber { pos: usize, literal: String, reason: String },
+    InvalidOperator { pos: usize, op: String },
+    UnbalancedParen,
+    /// A `?` without a `:`, or the other way round, or a branch that is not
+    /// an expression.
+    MalformedConditional,
+    /// A missing operand, where this character offset is.
+    ExpectedOperand { pos: usize },
+    /// Operands without an operator between them, the second one at this
+    /// character offset.
+    ExpectedOperator { pos: usize },
+    /// An operator short of operands.
+    StackUnderflow,
+    /// An operator applied to operands it does not take, like unary minus
+    /// to two of them.
+    MisusedOperator(String),
+    /// Operands without an operator between them.
+    MissingOperator,
+    Empty,
+    UnboundVariable(String),
+    /// A call to a function that is neither built in nor registered.
+    UnknownFunction(String),
+    /// A call to a registered function with a number of arguments other
+    /// than its arity.
+    WrongArity { name: String, expected: usize, found: usize },
+    /// A registered function, whose derivative is unknown, called with
+    /// arguments depending on the variable of differentiation.
+    NotDifferentiable(String),
+    /// A comma outside the arguments of a call, or without an argument on
+    /// either side.
+    MisplacedComma,
+    /// A name that cannot be that of a variable or constant.
+    InvalidName(String),
+    DivisionByZero,
+    /// An operation outside its domain, like the square root of a negative
+    /// number.
+    DomainError(String),
+    /// An operation without an exact result, in exact arithmetic.
+    Inexact(String),
+    /// A number too large to compute, in exact arithmetic.
+    TooLarge,
+    /// A result that should have been a whole number.
+    NotWhole(String),
+    /// An operation whose result is too large for its type, in integer
+    /// arithmetic.
+    Overflow(String),
+    /// An expression over one of the `Limits` it is checked against, of
+    /// which this is the maximum.
+    LimitExceeded { limit: Limit, max: usize },
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ExprError::InvalidToken { pos, found } => {
+                write!(f, "Unexpected character '{}' at position {}", found, pos)
+            }
+            ExprError::InvalidNumber { pos, ref literal, ref reason } => {
+                write!(f, "Invalid number {} at position {}: {}", literal, pos, reason)
+            }
+            ExprError::InvalidOperator { pos, ref op } => {
+                write!(f, "Invalid operator {} at position {}", op, pos)
+            }
+            ExprError::ExpectedOperand { pos } => {
+                write!(f, "Expected an operand at position {}", pos)
+            }
+            ExprError::ExpectedOperator { pos } => {
+                write!(f, "Expected an operator at position {}", pos)
+            }
+            ExprError::UnbalancedParen => write!(f, "Unbalanced parentheses"),
+            ExprError::MalformedConditional => write!(f, "Malformed conditional"),
+            ExprError::StackUnderflow => write!(f, "Operator without enough operands"),
+            ExprError::MisusedOperator(ref op) => {
+                write!(f, "Operator {} does not take these operands", op)
+            }
+            ExprError::MissingOperator => {
+                write!(f, "Operands without an operator between them")
+            }
+            ExprError::Empty => write!(f, "Empty expression"),
+            ExprError::UnboundVariable(ref var) => write!(f, "Unbound variable {}", var),
+            ExprError::UnknownFunction(ref name) => write!(f, "Unknown function {}", name),
+            ExprError::WrongArity { ref name, expected, found } => {
+                write!(f, "{} takes {} arguments, not {}", name, expected, found)
+            }
+            ExprError::NotDifferentiable(ref name) => {
+                write!(f, "{} has no known derivative", name)
+            }
+            ExprError::MisplacedComma => write!(f, "Misplaced comma"),
+            ExprError::InvalidName(ref name) => write!(f, "Invalid name {}", name),
+            ExprError::DivisionByZero => write!(f, "Division by zero"),
+            ExprError::DomainError(ref what) => write!(f, "{} is undefined", what),
+            ExprError::Inexact(ref what) => write!(f, "{} has no exact value", what),
+            ExprError::TooLarge => write!(f, "Number too large to compute"),
+            ExprError::NotWhole(ref v) => write!(f, "{} is not a whole number", v),
+            ExprError::Overflow(ref what) => write!(f, "{} overflows", what),
+            ExprError::LimitExceeded { limit, max } => {
+                let what = match limit {
+                    Limit::Length => "characters",
+                    Limit::Tokens => "tokens",
+                    Limit::Depth => "levels of nested parentheses",
+                };
+                write!(f, "Expression has more than {} {}", max, what)
+            }
+        }
+    }
+}
+
+impl ExprError {
+    /// Return true for the errors of well-formed expressions without a
+    /// value, like `1/0`.
+    pub fn is_undefined(&self) -> bool {
+        matches!(*self, ExprError::DivisionByZero | ExprError::DomainError(_))
+    }
+
+    /// Return the character offset in the expression at which a tokenizer
+    /// error occurred, if this is one.
+    pub fn position(&self) -> Option<usize> {
+        match *self {
+            ExprError::InvalidToken { pos, .. } |
+            ExprError::InvalidNumber { pos, .. } |
+            ExprError::InvalidOperator { pos, .. } |
+            ExprError::ExpectedOperand { pos } |
+            ExprError::ExpectedOperator { pos } => Some(pos),
+            _ => None,
+        }
+    }
+
+    /// Return this error moved `offset` characters to the right.
+    fn shifted(mut self, offset: usize) -> ExprError {
+        match self {
+            ExprError::InvalidToken { ref mut pos, .. } |
+            ExprError::InvalidNumber { ref mut pos, .. } |
+            ExprError::InvalidOperator { ref mut pos, .. } |
+            ExprError::ExpectedOperand { ref mut pos } |
+            ExprError::ExpectedOperator { ref mut pos } => *pos += offset,
+            _ => {}
+        }
+        self
+    }
+
+    /// Return the message for this error in the expression `e`, followed, if
+    /// it has a position, by `e` with a caret under that position:
+    ///
+    /// ```text
+    /// Unexpected character '~' at position 6
+    ///     1 + 2 ~ 3
+    ///           ^
+    /// ```
+    pub fn diagnostic(&self, e: &str) -> String {
+        match self.position() {
+            Some(pos) => format!("{}\n    {}\n    {}^", self, e, " ".repeat(pos)),
+            None => self.to_string(),
+        }
+    }
+}
+
+impl error::Error for ExprError {}
+
+/// Let functions reporting errors as strings use `?` on expression errors.
+impl From<ExprError> for String {
+    fn from(e: ExprError) -> String { e.to_string() }
+}
+
+#[derive(Debug,Clone,PartialEq,Eq,Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Op {
     Add,
     Sub,
     Div,
     Mul,
     Exp,
+    Mod,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Or,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
     UnNeg
 }
 
@@ -21,235 +232,1003 @@ impl Op {
             "/"  => Some(Op::Div),
             "*"  => Some(Op::Mul),
             "**" => Some(Op::Exp),
+            "%"  => Some(Op::Mod),
+            "<"  => Some(Op::Lt),
+            ">"  => Some(Op::Gt),
+            "<=" => Some(Op::Le),
+            ">=" => Some(Op::Ge),
+            "==" => Some(Op::Eq),
+            "!=" => Some(Op::Ne),
+            "&&" => Some(Op::And),
+            "||" => Some(Op::Or),
+            "&"  => Some(Op::BitAnd),
+            "|"  => Some(Op::BitOr),
+            "^"  => Some(Op::BitXor),
+            "<<" => Some(Op::Shl),
+            ">>" => Some(Op::Shr),
             _    => None,
         }
     }
 
+    /// Return the symbol for this operator.
+    pub fn symbol(&self) -> &'static str {
+        match *self {
+            Op::Add   => "+",
+            Op::Sub   => "-",
+            Op::Div   => "/",
+            Op::Mul   => "*",
+            Op::Exp   => "**",
+            Op::Mod   => "%",
+            Op::Lt    => "<",
+            Op::Gt    => ">",
+            Op::Le    => "<=",
+            Op::Ge    => ">=",
+            Op::Eq    => "==",
+            Op::Ne    => "!=",
+            Op::And   => "&&",
+            Op::Or    => "||",
+            Op::BitAnd => "&",
+            Op::BitOr  => "|",
+            Op::BitXor => "^",
+            Op::Shl    => "<<",
+            Op::Shr    => ">>",
+            Op::UnNeg => "-",
+        }
+    }
+
+    /// Return the precedence of this operator. Bitwise operators and shifts
+    /// rank as in C: `1 | 6 & 3` is `1 | (6 & 3)`, and `1 << 2 + 1` is
+    /// `1 << (2 + 1)`.
     fn precedence(&self) -> u8 {
         match *self {
-            Op::Add   => 0,
-            Op::Sub   => 0,
-            Op::Div   => 1,
-            Op::Mul   => 1,
-            Op::Exp   => 2,
-            Op::UnNeg => 3,
+            Op::Or    => 0,
+            Op::And   => 1,
+            Op::BitOr  => 2,
+            Op::BitXor => 3,
+            Op::BitAnd => 4,
+            Op::Eq    => 5,
+            Op::Ne    => 5,
+            Op::Lt    => 6,
+            Op::Gt    => 6,
+            Op::Le    => 6,
+            Op::Ge    => 6,
+            Op::Shl   => 7,
+            Op::Shr   => 7,
+            Op::Add   => 8,
+            Op::Sub   => 8,
+            Op::Div   => 9,
+            Op::Mul   => 9,
+            Op::Mod   => 9,
+            Op::Exp   => 10,
+            Op::UnNeg => 11,
+        }
+    }
+
+    /// Return true for the bitwise operators and shifts.
+    fn is_bitwise(&self) -> bool {
+        matches!(*self, Op::BitAnd | Op::BitOr | Op::BitXor | Op::Shl | Op::Shr)
+    }
+
+    /// Apply a bitwise operator or shift to the integers `a` and `b`. Shifts
+    /// by less than 0 or more than 63 bits are an error.
+    fn apply_bitwise(&self, a: i64, b: i64) -> Result<i64> {
+        let shift = || {
+            Some(b).filter(|b| (0..64).contains(b)).map(|b| b as u32).ok_or_else(|| {
+                ExprError::DomainError(format!("{} {} {}", a, self.symbol(), b))
+            })
+        };
+        match *self {
+            Op::BitAnd => Ok(a & b),
+            Op::BitOr  => Ok(a | b),
+            Op::BitXor => Ok(a ^ b),
+            Op::Shl    => Ok(a << shift()?),
+            Op::Shr    => Ok(a >> shift()?),
+            _ => Err(ExprError::MisusedOperator(self.symbol().to_string())),
         }
     }
 
     fn apply_binary(&self, a: f64, b: f64) -> Result<f64> {
         match *self {
+            Op::Div | Op::Mod if b == 0f64 => Err(ExprError::DivisionByZero),
+            Op::Exp if a == 0f64 && b < 0f64 => Err(ExprError::DivisionByZero),
+            Op::Exp if a < 0f64 && b.fract() != 0f64 && b.is_finite() => {
+                Err(ExprError::DomainError(format!("{}**{}", a, b)))
+            }
             Op::Add   => Ok(a + b),
             Op::Sub   => Ok(a - b),
             Op::Div   => Ok(a / b),
             Op::Mul   => Ok(a * b),
-            Op::Exp   => Ok(num::pow(a, b as usize)),
-            Op::UnNeg => Err("Not a binary operation".to_string()),
+            Op::Exp   => Ok(a.powf(b)),
+            // The remainder has the sign of `a`, as with `%` in Rust and C.
+            Op::Mod   => Ok(a % b),
+            // Comparisons and boolean operators yield 1 for true and 0 for
+            // false; any nonzero operand is true.
+            Op::Lt    => Ok(truth(a < b)),
+            Op::Gt    => Ok(truth(a > b)),
+            Op::Le    => Ok(truth(a <= b)),
+            Op::Ge    => Ok(truth(a >= b)),
+            Op::Eq    => Ok(truth(a == b)),
+            Op::Ne    => Ok(truth(a != b)),
+            Op::And   => Ok(truth(a != 0f64 && b != 0f64)),
+            Op::Or    => Ok(truth(a != 0f64 || b != 0f64)),
+            // Bitwise operators and shifts act on the integer parts.
+            _ if self.is_bitwise() => {
+                Ok(self.apply_bitwise(integer_part(a)?, integer_part(b)?)? as f64)
+            }
+            _ => Err(ExprError::MisusedOperator(self.symbol().to_string())),
         }
     }
 }
 
+/// Return the integer part of `a`, or an error if it does not fit an `i64`.
+fn integer_part(a: f64) -> Result<i64> {
+    if a.is_finite() && a.abs() < i64::MAX as f64 {
+        Ok(a.trunc() as i64)
+    } else {
+        Err(ExprError::DomainError(format!("The integer part of {}", a)))
+    }
+}
+
 
-fn is_operator_char(c: &char) -> bool {
-    match *c {
-        '+' | '-' | '/' | '*' => true,
-                            _ => false
+/// Return 1 for true and 0 for false.
+fn truth(b: bool) -> f64 { if b { 1f64 } else { 0f64 } }
+
+
+/// A built-in function of one argument, called as in `sqrt(2)`.
+#[derive(Debug,Clone,Copy,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Func {
+    Sqrt,
+    Sin,
+    Cos,
+    /// The natural logarithm.
+    Log,
+    Abs,
+}
+
+impl Func {
+    fn from_str(name: &str) -> Option<Func> {
+        match name {
+            "sqrt" => Some(Func::Sqrt),
+            "sin"  => Some(Func::Sin),
+            "cos"  => Some(Func::Cos),
+            "log"  => Some(Func::Log),
+            "abs"  => Some(Func::Abs),
+            _      => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match *self {
+            Func::Sqrt => "sqrt",
+            Func::Sin  => "sin",
+            Func::Cos  => "cos",
+            Func::Log  => "log",
+            Func::Abs  => "abs",
+        }
+    }
+
+    fn apply(&self, a: f64) -> Result<f64> {
+        match *self {
+            Func::Sqrt | Func::Log if a < 0f64 => {
+                Err(ExprError::DomainError(format!("{}({})", self.name(), a)))
+            }
+            Func::Log if a == 0f64 => Err(ExprError::DomainError("log(0)".to_string())),
+            Func::Sqrt => Ok(a.sqrt()),
+            Func::Sin  => Ok(a.sin()),
+            Func::Cos  => Ok(a.cos()),
+            Func::Log  => Ok(a.ln()),
+            Func::Abs  => Ok(a.abs()),
+        }
+    }
+
+    /// Apply this function to `a`, taking angles in `unit`.
+    fn apply_in(&self, a: f64, unit: AngleUnit) -> Result<f64> {
+        match (*self, unit) {
+            (Func::Sin, AngleUnit::Degrees) => Ok(sin_degrees(a)),
+            (Func::Cos, AngleUnit::Degrees) => Ok(sin_degrees(a + 90f64)),
+            _ => self.apply(a),
+        }
+    }
+}
+
+/// Return the sine of `d` degrees, exactly at multiples of 30 degrees, so
+/// that `sin(180)` is 0 and `sin(30)` is 0.5, as on a calculator.
+fn sin_degrees(d: f64) -> f64 {
+    let d = d.rem_euclid(360f64);
+    match d {
+        0f64 | 180f64 => 0f64,
+        90f64 => 1f64,
+        270f64 => -1f64,
+        30f64 | 150f64 => 0.5,
+        210f64 | 330f64 => -0.5,
+        _ => d.to_radians().sin(),
+    }
+}
+
+/// The unit in which the trigonometric functions `sin` and `cos` take
+/// angles.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub enum AngleUnit {
+    #[default]
+    Radians,
+    Degrees,
+}
+
+impl AngleUnit {
+    /// Evaluate `s`, which may use the constants `pi` and `e`, with angles
+    /// in this unit.
+    pub fn eval(&self, s: &str) -> Result<f64> { self.eval_with(s, &HashMap::new()) }
+
+    /// Evaluate `s` with the variables bound in `env`, as `eval_with` does,
+    /// with angles in this unit.
+    pub fn eval_with(&self, s: &str, env: &HashMap<String, f64>) -> Result<f64> {
+        let lookup = |var: &str| env.get(var).cloned().or_else(|| builtin_constant(var));
+        ast::parse(s)?.eval_in(&lookup, *self)
     }
 }
+
+
+fn is_operator_char(c: &char) -> bool {
+    matches!(*c, '+' | '-' | '/' | '*' | '%' | '<' | '>' | '=' | '!' | '&' | '|' | '^')
+}
            
 
-#[derive(Debug,Clone)]
+#[derive(Debug,Clone,PartialEq)]
 pub enum Tok {
     Num(f64),
     Op(Op),
     Var(String),
+    Func(Func),
+    /// A call to a registered function, with its number of arguments, which
+    /// is only known in postfix form.
+    Call(String, usize),
+    /// The comma between the arguments of a call.
+    Comma,
     RParen,
-    LParen
+    LParen,
+    /// The `?` of a conditional `c ? a : b`.
+    Then,
+    /// The `:` of a conditional `c ? a : b`.
+    Else,
+    /// The end of a conditional, which only appears in postfix form.
+    EndIf,
+    /// A percent sign, as in `15%`, which divides the operand before it by
+    /// 100. It only appears in infix form, becoming `100 /` in postfix form.
+    Percent,
 }
 
-pub fn get_number<'a>(stream: &'a [char]) -> Option<(Tok, &'a [char])> {
-    let mut i = 0;
-    let n = stream.len();
-    while i < n && stream[i].is_whitespace() {
-        i += 1;
+/// Read a number at the start of `s`, either a run of digits or a decimal
+/// like `3.5`, `.5` or `3.`, optionally followed by an exponent as in `1e3`
+/// or `2.5E-2`, or a whole number in another base (see `radix_number`); a
+/// number with more than one decimal point is an error. Tokens are returned
+/// with their length in bytes, and errors with positions relative to `s`.
+fn number(s: &str) -> Option<Result<(Tok, usize)>> {
+    let b = s.as_bytes();
+    let digit = |i: usize| b.get(i).is_some_and(u8::is_ascii_digit);
+    if !(digit(0) || (b.first() == Some(&b'.') && digit(1))) {
+        return None;
+    }
+    if let Some(r) = radix_number(s) {
+        return Some(r);
     }
-    let mut found = false;
-    let mut number = 0f64;
-    while i < n && stream[i].is_digit(10) {
-        found = true;
-        let d = stream[i].to_digit(10).expect("Invalid digit") as f64;
-        number = number * 10f64 + d;
-        i += 1
+    let mut i = b.iter().position(|&c| !c.is_ascii_digit() && c != b'.').unwrap_or(b.len());
+    // An `e` only starts an exponent if digits follow it, so that `2e` is
+    // still the number 2 followed by the variable `e`.
+    if matches!(b.get(i), Some(&b'e') | Some(&b'E')) {
+        let j = if matches!(b.get(i + 1), Some(&b'+') | Some(&b'-')) { i + 2 } else { i + 1 };
+        if digit(j) {
+            i = j + b[j..].iter().position(|c| !c.is_ascii_digit()).unwrap_or(b.len() - j);
+        }
+    }
+    let literal = &s[..i];
+    if literal.matches('.').count() > 1 {
+        return Some(Err(ExprError::InvalidNumber {
+            pos: 0, literal: literal.to_string(), reason: "more than one decimal point".to_string()
+        }));
     }
-    if found { Some((Tok::Num(number), &stream[i..n])) } else { None }
+    Some(literal.parse::<f64>()
+                .map(|v| (Tok::Num(v), i))
+                .map_err(|e| ExprError::InvalidNumber {
+                    pos: 0, literal: literal.to_string(), reason: e.to_string()
+                }))
 }
 
-pub fn get_operator<'a>(stream: &'a [char]) -> Option<Result<(Tok, &'a [char])>> {
-    let mut i = 0;
-    let n = stream.len();
-    while i < n && stream[i].is_whitespace() {
-        i += 1;
+/// Read a hexadecimal, octal or binary number like `0x1F`, `0o17` or
+/// `0b1010`, if `s` starts with one. Without digits after the prefix, as in
+/// `0x`, it is not one.
+fn radix_number(s: &str) -> Option<Result<(Tok, usize)>> {
+    let radix = match s.get(..2) {
+        Some("0x") | Some("0X") => 16,
+        Some("0o") | Some("0O") => 8,
+        Some("0b") | Some("0B") => 2,
+        _ => return None,
+    };
+    let n = 2 + s[2..].find(|c: char| !c.is_digit(radix)).unwrap_or(s.len() - 2);
+    if n == 2 {
+        return None;
     }
-    let mut opstr = String::new();
-    while i < n && is_operator_char(&stream[i]) {
-        opstr.push(stream[i]);
-        i += 1;
+    Some(u64::from_str_radix(&s[2..n], radix)
+            .map(|v| (Tok::Num(v as f64), n))
+            .map_err(|e| ExprError::InvalidNumber {
+                pos: 0, literal: s[..n].to_string(), reason: e.to_string()
+            }))
+}
+
+/// Read a single operator, so that a run of operator characters like `*-`
+/// yields several tokens; two-character operators like `**` and `<=` are
+/// read as one.
+fn operator(s: &str) -> Option<Result<(Tok, usize)>> {
+    let c = s.chars().next().filter(is_operator_char)?;
+    if let Some(op) = s.get(..2).and_then(Op::from_str) {
+        return Some(Ok((Tok::Op(op), 2)));
     }
-    if !opstr.is_empty() {
-        Some(Op::from_str(&opstr)
-                .map(|v| (Tok::Op(v), &stream[i..n]))
-                .ok_or(format!("Invalid operator sequence {:?}", opstr)))
-    } else {
-        None
+    Some(Op::from_str(&s[..1])
+            .map(|op| (Tok::Op(op), 1))
+            .ok_or(ExprError::InvalidOperator { pos: 0, op: c.to_string() }))
+}
+
+/// Return true if a `%` followed by `rest` is a percent sign rather than
+/// modulo, i.e., if it ends the expression or comes before `)`, `,`, `?` or
+/// `:`. Before anything else, a minus sign included, it is modulo: `7 % -3`
+/// is 1, and `(15%) - 5` subtracts from a percentage.
+fn is_percent(rest: &str) -> bool {
+    matches!(rest.trim_start().chars().next(), None | Some(')' | ',' | '?' | ':'))
+}
+
+fn conditional(s: &str) -> Option<(Tok, usize)> {
+    match s.as_bytes().first() {
+        Some(&b'?') => Some((Tok::Then, 1)),
+        Some(&b':') => Some((Tok::Else, 1)),
+        _ => None,
     }
 }
 
-pub fn get_paren<'a>(stream: &'a [char]) -> Option<(Tok, &'a [char])> {
-    let stream = skip_whitespace(stream);
-    let n = stream.len();
-    if n > 0 {
-        match stream[0] {
-            '(' => Some(Tok::LParen),
-            ')' => Some(Tok::RParen),
-            _   => None
-        }
-    } else {
-        None
-    }.map(|x| (x, &stream[1..n]))
+fn paren(s: &str) -> Option<(Tok, usize)> {
+    match s.as_bytes().first() {
+        Some(&b'(') => Some((Tok::LParen, 1)),
+        Some(&b')') => Some((Tok::RParen, 1)),
+        Some(&b',') => Some((Tok::Comma, 1)),
+        _ => None,
+    }
 }
 
-pub fn get_var<'a>(stream: &'a [char]) -> Option<(Tok, &'a [char])> {
-    let stream = skip_whitespace(stream);
-    let n = stream.len();
-    let mut var = String::new();
-    let mut i = 0;
-    while i < n && (stream[i].is_alphabetic() || stream[i] == '_') {
-        var.push(stream[i]);
-        i += 1;
+/// Read a variable, whose name is made of letters, underscores and digits
+/// but does not start with a digit, or a function if the name of a built-in
+/// function, or of one in `functions`, is followed by an opening parenthesis.
+fn var(s: &str, functions: Option<&Registry>) -> Option<(Tok, usize)> {
+    let n = s.char_indices()
+             .find(|&(i, c)| !(c.is_alphabetic() || c == '_' || (i > 0 && c.is_ascii_digit())))
+             .map_or(s.len(), |(i, _)| i);
+    if n == 0 {
+        return None;
     }
-    if !var.is_empty() {
-        Some((Tok::Var(var), &stream[i..n]))
-    } else {
-        None
+    let call = s[n..].trim_start().starts_with('(');
+    match Func::from_str(&s[..n]).filter(|_| call) {
+        Some(func) => Some((Tok::Func(func), n)),
+        None if call && functions.is_some_and(|f| f.contains(&s[..n])) => {
+            Some((Tok::Call(s[..n].to_string(), 0), n))
+        }
+        None => Some((Tok::Var(s[..n].to_string()), n)),
     }
 }
 
-pub fn skip_whitespace<'a>(stream: &'a [char]) -> &'a [char] {
-    let mut i = 0;
-    while i < stream.len() && stream[i].is_whitespace() {
-        i += 1;
+/// What kind of token came last, which decides how a minus sign or an
+/// operand after it is read.
+#[derive(Debug,Clone,Copy,PartialEq)]
+enum Last {
+    /// Nothing, or a token after which an operand is expected, so that a
+    /// minus sign is unary.
+    Prefix,
+    Func,
+    /// A number or variable.
+    Operand,
+    RParen,
+}
+
+/// The tokens of an expression, each with the character offset at which it
+/// starts, read in place as they are asked for. Nothing is allocated except
+/// the names of variables. After an error, no more tokens are read.
+///
+/// An operand right after another, as in `2(3+4)`, `(1+2)(3+4)` or `3x`, is
+/// multiplied by it: a `*` is read in between, at the position of the second.
+/// Two numbers in a row, as in `2 3`, are still an error.
+pub struct Tokens<'a> {
+    s: &'a str,
+    /// The byte offset of the next token.
+    i: usize,
+    /// The character offset of the next token.
+    pos: usize,
+    last: Last,
+    /// A token read after an implicit `*`, to be returned next.
+    pending: Option<(Tok, usize)>,
+    failed: bool,
+    functions: Option<&'a Registry>,
+}
+
+impl<'a> Tokens<'a> {
+    pub fn new(s: &'a str) -> Tokens<'a> {
+        Tokens { s, i: 0, pos: 0, last: Last::Prefix, pending: None, failed: false,
+                 functions: None }
+    }
+
+    /// Return the tokens of `s`, where the functions in `functions` may be
+    /// called besides the built-in ones.
+    pub fn with_registry(s: &'a str, functions: &'a Registry) -> Tokens<'a> {
+        Tokens { functions: Some(functions), ..Tokens::new(s) }
+    }
+
+    /// Move `n` bytes forward.
+    fn advance(&mut self, n: usize) {
+        self.pos += self.s[self.i..self.i + n].chars().count();
+        self.i += n;
     }
-    &stream[i..stream.len()]
 }
 
-pub fn tok(s: &str) -> Result<Vec<Tok>> {
-    let mut ret = Vec::new();
-    let mut t: &[char] = &s.chars().collect::<Vec<_>>();
-    while t.len() != 0 {
-        t = skip_whitespace(t);
-        let mut found = false;
-        if let Some((tok, u)) = get_number(t) {
-            ret.push(tok);
-            t = u;
-            found = true;
-        }
-        if let Some(r) = get_operator(t) {
-            let (tok, u) = try!(r);
-            ret.push(tok);
-            t = u;
-            found = true;
-        }
-        if let Some((tok, u)) = get_paren(t) {
-            ret.push(tok);
-            t = u;
-            found = true;
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Result<(Tok, usize)>;
+
+    fn next(&mut self) -> Option<Result<(Tok, usize)>> {
+        if self.failed {
+            return None;
         }
-        if let Some((tok, u)) = get_var(t) {
-            ret.push(tok);
-            t = u;
-            found = true;
+        if let Some(token) = self.pending.take() {
+            return Some(Ok(token));
         }
-        if !found {
-            return Err(format!("Stuck tokenizing: {:?}", t));
+        let rest = &self.s[self.i..];
+        self.advance(rest.len() - rest.trim_start().len());
+        let rest = &self.s[self.i..];
+        let c = rest.chars().next()?;
+        let read = number(rest)
+            .or_else(|| operator(rest))
+            .or_else(|| {
+                conditional(rest).or_else(|| paren(rest))
+                                 .or_else(|| var(rest, self.functions))
+                                 .map(Ok)
+            })
+            .unwrap_or(Err(ExprError::InvalidToken { pos: 0, found: c }));
+        let pos = self.pos;
+        match read {
+            Ok((tok, n)) => {
+                self.advance(n);
+                let unary = tok == Tok::Op(Op::Sub) && self.last == Last::Prefix;
+                let percent = tok == Tok::Op(Op::Mod) && self.last != Last::Prefix &&
+                              is_percent(&self.s[self.i..]);
+                let tok = match tok {
+                    _ if unary => Tok::Op(Op::UnNeg),
+                    _ if percent => Tok::Percent,
+                    tok => tok,
+                };
+                let implicit = match tok {
+                    Tok::LParen | Tok::Var(_) | Tok::Func(_) | Tok::Call(..) => {
+                        matches!(self.last, Last::Operand | Last::RParen)
+                    }
+                    Tok::Num(_) => self.last == Last::RParen,
+                    _ => false,
+                };
+                self.last = match tok {
+                    Tok::Num(_) | Tok::Var(_) => Last::Operand,
+                    Tok::RParen | Tok::Percent => Last::RParen,
+                    Tok::Func(_) | Tok::Call(..) => Last::Func,
+                    _ => Last::Prefix,
+                };
+                if implicit {
+                    self.pending = Some((tok, pos));
+                    return Some(Ok((Tok::Op(Op::Mul), pos)));
+                }
+                Some(Ok((tok, pos)))
+            }
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e.shifted(pos)))
+            }
         }
     }
-    Ok(ret)
 }
 
+pub fn tok(s: &str) -> Result<Vec<Tok>> {
+    Tokens::new(s).map(|r| r.map(|(tok, _)| tok)).collect()
+}
+
+/// Return the tokens of `s`, each with the character offset at which it
+/// starts.
+pub fn tok_positions(s: &str) -> Result<Vec<(Tok, usize)>> {
+    Tokens::new(s).collect()
+}
 
-// TODO: this is ugly; most likely can be written more idiomatically.
+/// Return the tokens of `e` in postfix order. A conditional `c ? a : b`
+/// becomes `c ? a : b EndIf`, so that the end of the `else` branch is
+/// marked.
+///
+/// A call to a registered function comes after its arguments, with their
+/// number: `f(1, 2)` becomes `1 2 f/2`.
 pub fn postfix(e: &str) -> Result<Vec<Tok>> {
-    let mut tokens = try!(tok(e));
+    postfix_tokens(Tokens::new(e), None)
+}
+
+/// Return the tokens of `e` in postfix order, as `postfix` does, with the
+/// precedence and associativity of operators taken from `table`.
+pub fn postfix_with(e: &str, table: &PrecedenceTable) -> Result<Vec<Tok>> {
+    postfix_tokens(Tokens::new(e), Some(table))
+}
+
+/// Return `tokens` in postfix order, as `postfix` does, with the built-in
+/// precedences unless a `table` is given.
+fn postfix_tokens(tokens: Tokens, table: Option<&PrecedenceTable>) -> Result<Vec<Tok>> {
+    pratt::parse(tokens, table)
+}
+
+/// Return the tokens of `e` in postfix order by the shunting-yard algorithm,
+/// which `postfix` used before the Pratt parser. Well-formed expressions
+/// come out the same either way; malformed ones may fail with other errors,
+/// or, like `1 2 +`, not at all.
+pub fn postfix_compat(e: &str) -> Result<Vec<Tok>> {
+    shunting_yard(Tokens::new(e), None)
+}
+
+fn shunting_yard(tokens: Tokens, table: Option<&PrecedenceTable>) -> Result<Vec<Tok>> {
+    let level = |op: &Op| table.map_or((op.precedence(), Assoc::Right), |t| t.level(op));
+    let tokens = tokens.map(|r| r.map(|(tok, _)| tok));
     let mut post: Vec<Tok> = Vec::new();
     let mut stack: Vec<Tok> = Vec::new();
     stack.push(Tok::LParen);
-    tokens.push(Tok::RParen);
-    
-    for token in &tokens {
+    // The number of arguments so far of each call whose parentheses are
+    // open, and whether the last token opened a parenthesis or was a comma.
+    let mut args: Vec<usize> = Vec::new();
+    let (mut opened, mut comma) = (false, false);
+
+    for token in tokens.chain(iter::once(Ok(Tok::RParen))) {
+        let token = &token?;
+        // A comma needs an argument on either side.
+        if (*token == Tok::Comma && (opened || comma)) || (*token == Tok::RParen && comma) {
+            return Err(ExprError::MisplacedComma);
+        }
+        let empty = opened && *token == Tok::RParen;
+        opened = *token == Tok::LParen;
+        comma = *token == Tok::Comma;
         match *token {
-            Tok::Num(n) => post.push(token.clone()),
+            Tok::Num(_) | Tok::Var(_) => post.push(token.clone()),
+            // A percent sign binds tighter than any operator, so the operand
+            // before it is complete.
+            Tok::Percent => post.extend(vec![Tok::Num(100f64), Tok::Op(Op::Div)]),
+            // A prefix operator has no operand to its left yet, so it
+            // cannot take any operators off the stack.
+            Tok::Op(Op::UnNeg) | Tok::Func(_) | Tok::Call(..) => stack.push(token.clone()),
+            // Only operators of strictly higher precedence are taken off the
+            // stack, unless associativity says otherwise, so that by default
+            // operators of equal precedence, `**` in particular, group to the
+            // right: `2**3**2` is `2**(3**2)`.
             Tok::Op(ref op) => {
-                while !stack.is_empty() {
-                    if stack.last().map_or(false, |t| -> bool {
-                        if let Tok::Op(ref pp) = *t {
-                            pp.precedence() > op.precedence()
-                        } else {
-                            false
-                        }
-                    }) { post.push(stack.pop().unwrap()); }
-                    else { break; }
+                let (precedence, assoc) = level(op);
+                while let Some(Tok::Op(top)) = stack.last() {
+                    let above = level(top).0;
+                    if above > precedence || (above == precedence && assoc == Assoc::Left) {
+                        post.push(stack.pop().unwrap());
+                    } else {
+                        break;
+                    }
                 }
                 stack.push(token.clone());
             },
             Tok::LParen => {
+                if let Some(&Tok::Call(..)) = stack.last() {
+                    args.push(1);
+                }
                 stack.push(token.clone());
             },
-            Tok::RParen => {
+            Tok::Comma => {
+                pop_to_paren(&mut stack, &mut post)?;
+                match (stack.last(), args.last_mut()) {
+                    (Some(&Tok::Call(..)), Some(n)) => *n += 1,
+                    _ => return Err(ExprError::MisplacedComma),
+                }
+                stack.push(Tok::LParen);
+            },
+            // A conditional binds looser than any operator, and groups to
+            // the right: `c ? a : d ? b : e` is `c ? a : (d ? b : e)`.
+            Tok::Then => {
+                while let Some(&Tok::Op(_)) = stack.last() {
+                    post.push(stack.pop().unwrap());
+                }
+                post.push(Tok::Then);
+                stack.push(Tok::Then);
+            },
+            Tok::Else => {
                 loop {
-                    let top = stack.pop();
-                    if top.is_none() {
-                        return Err("Syntax error".to_string());
+                    match stack.pop() {
+                        Some(Tok::Then) => break,
+                        Some(Tok::Else) => post.push(Tok::EndIf),
+                        Some(Tok::Op(op)) => post.push(Tok::Op(op)),
+                        _ => return Err(ExprError::MalformedConditional),
                     }
-                    if let Some(Tok::LParen) = top {
-                        break;
+                }
+                post.push(Tok::Else);
+                stack.push(Tok::Else);
+            },
+            Tok::EndIf => return Err(ExprError::MalformedConditional),
+            Tok::RParen => {
+                pop_to_paren(&mut stack, &mut post)?;
+                // The parentheses held the argument of a function call.
+                match stack.last() {
+                    Some(&Tok::Func(_)) => post.push(stack.pop().unwrap()),
+                    Some(&Tok::Call(..)) => {
+                        let n = args.pop().unwrap_or(0);
+                        if let Some(Tok::Call(name, _)) = stack.pop() {
+                            post.push(Tok::Call(name, if empty { 0 } else { n }));
+                        }
                     }
-                    post.push(top.unwrap());
+                    _ => {}
                 }
-
             },
-            _ => {}
         }
     }
+    // Anything left on the stack is inside a parenthesis never closed.
+    if !stack.is_empty() {
+        return Err(ExprError::UnbalancedParen);
+    }
     Ok(post)
 }
 
 
+/// Move the operators on `stack` down to the innermost opening parenthesis
+/// to `post`, and drop the parenthesis.
+fn pop_to_paren(stack: &mut Vec<Tok>, post: &mut Vec<Tok>) -> Result<()> {
+    loop {
+        match stack.pop() {
+            None => return Err(ExprError::UnbalancedParen),
+            Some(Tok::LParen) => return Ok(()),
+            Some(Tok::Then) => return Err(ExprError::MalformedConditional),
+            Some(Tok::Else) => post.push(Tok::EndIf),
+            Some(top) => post.push(top),
+        }
+    }
+}
+
+/// Return `s` in canonical form: its tokens without whitespace, and numbers
+/// without leading zeros, so that trivially different spellings of the same
+/// expression compare equal.
+pub fn canonical(s: &str) -> Result<String> {
+    let tokens = tok(s)?;
+    Ok(tokens.iter().map(|t| match *t {
+        Tok::Num(n) => n.to_string(),
+        Tok::Op(ref op) => op.symbol().to_string(),
+        Tok::Var(ref var) => var.clone(),
+        Tok::Func(ref func) => func.name().to_string(),
+        Tok::Call(ref name, _) => name.clone(),
+        Tok::Comma => ",".to_string(),
+        Tok::LParen => "(".to_string(),
+        Tok::RParen => ")".to_string(),
+        Tok::Then => "?".to_string(),
+        Tok::Else => ":".to_string(),
+        Tok::EndIf => String::new(),
+        Tok::Percent => "%".to_string(),
+    }).collect())
+}
+
+/// An expression in canonical form (see `canonical()`), to key hash maps
+/// and sets by, so that spellings of the same expression, like `6 * 07` and
+/// `6*7`, get the same entry. Number literals are rewritten too, so it is no
+/// key for what depends on the spelling, like the fitness of
+/// `digits::Limited`. A malformed expression is kept as it is.
+#[derive(Debug,Clone,PartialEq,Eq,Hash,PartialOrd,Ord)]
+pub struct Canonical(String);
+
+impl Canonical {
+    pub fn new(s: &str) -> Canonical {
+        match validate(s).and_then(|_| canonical(s)) {
+            Ok(form) => Canonical(form),
+            Err(_) => Canonical(s.to_string()),
+        }
+    }
+
+    pub fn as_str(&self) -> &str { &self.0 }
+}
+
+impl fmt::Display for Canonical {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "{}", self.0) }
+}
+
+/// Whether operators of equal precedence group to the left, as in
+/// `(1 - 2) - 3`, or to the right, as in `1 - (2 - 3)`.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Assoc {
+    Left,
+    Right,
+}
+
+/// Every operator, for building a `PrecedenceTable`.
+const OPERATORS: [Op; 20] = [Op::Add, Op::Sub, Op::Div, Op::Mul, Op::Exp, Op::Mod, Op::Lt, Op::Gt,
+                             Op::Le, Op::Ge, Op::Eq, Op::Ne, Op::And, Op::Or, Op::BitAnd,
+                             Op::BitOr, Op::BitXor, Op::Shl, Op::Shr, Op::UnNeg];
+
+/// The precedence and associativity of each operator, as data, for parsing
+/// by other rules than the built-in ones, which the default table holds:
+/// every operator groups to the right. Higher precedences bind tighter, and
+/// a negation binds tighter than a binary operator of lower precedence, so
+/// that, e.g., giving `-` a lower precedence than `**` makes `-2**2` be
+/// `-(2**2)`.
+#[derive(Debug,Clone,PartialEq)]
+pub struct PrecedenceTable {
+    levels: HashMap<Op, (u8, Assoc)>,
+}
+
+impl Default for PrecedenceTable {
+    fn default() -> PrecedenceTable {
+        PrecedenceTable {
+            levels: OPERATORS.iter().map(|op| (op.clone(), (op.precedence(), Assoc::Right)))
+                                    .collect(),
+        }
+    }
+}
+
+impl PrecedenceTable {
+    /// Give `op` the precedence `precedence` and the associativity `assoc`.
+    pub fn set(&mut self, op: Op, precedence: u8, assoc: Assoc) {
+        self.levels.insert(op, (precedence, assoc));
+    }
+
+    /// Return the precedence and associativity of `op`.
+    pub fn level(&self, op: &Op) -> (u8, Assoc) {
+        self.levels.get(op).cloned().unwrap_or((op.precedence(), Assoc::Right))
+    }
+
+    /// Parse `s` into an expression tree by this table.
+    pub fn parse(&self, s: &str) -> Result<ast::Expr> {
+        ast::from_postfix(postfix_with(s, self)?)
+    }
+
+    /// Evaluate `s`, parsed by this table, with the constants `pi` and `e`.
+    pub fn eval(&self, s: &str) -> Result<f64> { self.parse(s)?.eval(&builtin_constant) }
+}
+
+/// The constants every expression may use.
+const BUILTIN_CONSTANTS: [(&str, f64); 2] = [("pi", consts::PI), ("e", consts::E)];
+
+/// Return the value of the built-in constant `name`, if there is one.
+fn builtin_constant(name: &str) -> Option<f64> {
+    BUILTIN_CONSTANTS.iter().find(|&&(c, _)| c == name).map(|&(_, v)| v)
+}
+
+/// A registry of named constants that expressions may use like variables:
+/// `pi` and `e`, and any defined by the user.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Constants {
+    values: HashMap<String, f64>,
+}
+
+impl Default for Constants {
+    fn default() -> Constants {
+        Constants {
+            values: BUILTIN_CONSTANTS.iter().map(|&(c, v)| (c.to_string(), v)).collect(),
+        }
+    }
+}
+
+impl Constants {
+    /// Define the constant `name`, replacing any previous value, or return
+    /// an error if `name` is not a valid variable name.
+    pub fn define(&mut self, name: &str, value: f64) -> Result<()> {
+        if tok(name).ok() != Some(vec![Tok::Var(name.to_string())]) {
+            return Err(ExprError::InvalidName(name.to_string()));
+        }
+        self.values.insert(name.to_string(), value);
+        Ok(())
+    }
+
+    /// Return the value of the constant `name`, if defined.
+    pub fn get(&self, name: &str) -> Option<f64> { self.values.get(name).cloned() }
+
+    /// Evaluate `s` with these constants.
+    pub fn eval(&self, s: &str) -> Result<f64> { self.eval_with(s, &HashMap::new()) }
+
+    /// Evaluate `s` with these constants and the variables bound in `env`,
+    /// which take precedence over constants of the same name.
+    pub fn eval_with(&self, s: &str, env: &HashMap<String, f64>) -> Result<f64> {
+        evaluate(s, |var| env.get(var).cloned().or_else(|| self.get(var)))
+    }
+}
+
+/// Which of the `Limits` an expression is over.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Limit {
+    Length,
+    Tokens,
+    Depth,
+}
+
+/// Limits on the size of expressions, for parsing and evaluating ones from
+/// untrusted sources, whose parsing and evaluation otherwise take time and
+/// stack in proportion to their size. The default limits are generous for
+/// any expression typed by hand.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct Limits {
+    /// The most characters in an expression.
+    pub max_length: usize,
+    /// The most tokens in an expression, counting the `*` of an implicit
+    /// multiplication.
+    pub max_tokens: usize,
+    /// The most parentheses an expression may be nested in, counting those
+    /// of calls.
+    pub max_depth: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Limits { Limits { max_length: 10000, max_tokens: 1000, max_depth: 64 } }
+}
+
+impl Limits {
+    /// Check that `s` is within these limits, without parsing it.
+    pub fn check(&self, s: &str) -> Result<()> {
+        let exceeded = |limit, max| Err(ExprError::LimitExceeded { limit, max });
+        if s.len() > self.max_length && s.chars().count() > self.max_length {
+            return exceeded(Limit::Length, self.max_length);
+        }
+        let mut depth = 0usize;
+        for (n, token) in Tokens::new(s).enumerate() {
+            if n == self.max_tokens {
+                return exceeded(Limit::Tokens, self.max_tokens);
+            }
+            match token?.0 {
+                Tok::LParen => depth += 1,
+                Tok::RParen => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            if depth > self.max_depth {
+                return exceeded(Limit::Depth, self.max_depth);
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse `s` into an expression tree, if it is within these limits.
+    pub fn parse(&self, s: &str) -> Result<ast::Expr> {
+        self.check(s)?;
+        ast::parse(s)
+    }
+
+    /// Evaluate `s`, which may use the constants `pi` and `e`, if it is
+    /// within these limits.
+    pub fn eval(&self, s: &str) -> Result<f64> { self.parse(s)?.eval(&builtin_constant) }
+
+    /// Evaluate `s` with the variables bound in `env`, as `eval_with` does,
+    /// if it is within these limits.
+    pub fn eval_with(&self, s: &str, env: &HashMap<String, f64>) -> Result<f64> {
+        self.parse(s)?.eval(&|var: &str| env.get(var).cloned().or_else(|| builtin_constant(var)))
+    }
+}
+
+/// Evaluate `s`, which may use the constants `pi` and `e`. An expression
+/// from an untrusted source should be evaluated within `Limits` instead.
 pub fn eval(s: &str) -> Result<f64> {
-    let post = try!(postfix(s));
-    let mut stack = Vec::new();
-    for token in &post {
+    evaluate(s, builtin_constant)
+}
+
+/// How to evaluate an expression.
+#[derive(Debug,Clone,Copy,PartialEq,Default)]
+pub enum EvalMode {
+    /// In floating point.
+    #[default]
+    Float,
+    /// In exact rational arithmetic, as `eval_exact` does.
+    Exact,
+    /// In checked 64-bit integer arithmetic, as `eval_i64` does.
+    Integer,
+}
+
+/// Evaluate `s` in `mode`, returning the floating-point value closest to an
+/// exact result.
+pub fn eval_in(s: &str, mode: EvalMode) -> Result<f64> {
+    match mode {
+        EvalMode::Float => eval(s),
+        EvalMode::Exact => eval_exact(s).map(|r| exact::to_f64(&r)),
+        EvalMode::Integer => eval_i64(s).map(|n| n as f64),
+    }
+}
+
+/// Evaluate `s` with the variable `x` standing for `x`.
+pub fn eval_at(s: &str, x: f64) -> Result<f64> {
+    evaluate(s, |var| if var == "x" { Some(x) } else { builtin_constant(var) })
+}
+
+/// Evaluate `s` with the variables bound in `env`, which take precedence
+/// over the constants `pi` and `e`; any other variable is an error.
+pub fn eval_with(s: &str, env: &HashMap<String, f64>) -> Result<f64> {
+    evaluate(s, |var| env.get(var).cloned().or_else(|| builtin_constant(var)))
+}
+
+/// Evaluate `s` in reverse Polish notation, e.g., `3 4 + 2 *` for
+/// `(3 + 4) * 2`, which may use the constants `pi` and `e`. Numbers,
+/// operators, functions and variables are separated by whitespace; a minus
+/// sign is always binary, except as part of a negative number like `-3`.
+pub fn eval_postfix_str(s: &str) -> Result<f64> {
+    ast::from_postfix(postfix_words(s)?)?.eval(&builtin_constant)
+}
+
+/// Return the tokens of `s`, which is in reverse Polish notation.
+fn postfix_words(s: &str) -> Result<Vec<Tok>> {
+    s.split_whitespace().map(|word| {
+        let pos = s[..word.as_ptr() as usize - s.as_ptr() as usize].chars().count();
+        let (sign, digits) = match word.strip_prefix('-') {
+            Some(digits) if number(digits).is_some() => (-1f64, digits),
+            _ => (1f64, word),
+        };
+        let whole = |n: usize| n == digits.len();
+        let token = match number(digits) {
+            Some(Ok((Tok::Num(n), len))) if whole(len) => Tok::Num(sign * n),
+            Some(Err(e)) => return Err(e.shifted(pos)),
+            _ => match (Op::from_str(word), Func::from_str(word), var(word, None)) {
+                (Some(op), _, _) => Tok::Op(op),
+                (_, Some(func), _) => Tok::Func(func),
+                (_, _, Some((tok, len))) if len == word.len() => tok,
+                _ => {
+                    let found = word.chars().next().unwrap_or(' ');
+                    return Err(ExprError::InvalidToken { pos, found });
+                }
+            },
+        };
+        Ok(token)
+    }).collect()
+}
+
+/// Check that `s` is well-formed, i.e., that it would parse, without building
+/// its tree or evaluating it, so that a malformed expression can be told
+/// apart from one without a value, like `1/0`.
+pub fn validate(s: &str) -> Result<()> {
+    postfix(s).map(|_| ())
+}
+
+/// Evaluate `s`, looking up the value of each variable with `lookup`.
+fn evaluate<F: Fn(&str) -> Option<f64>>(s: &str, lookup: F) -> Result<f64> {
+    ast::parse(s)?.eval(&lookup)
+}
+
+/// Check that each branch of each conditional in `post` yields exactly one
+/// value, which the stack of `ast::parse` alone would not catch, e.g., in
+/// `1 ? : 2 3`.
+fn check_branches(post: &[Tok]) -> Result<()> {
+    let mut depth = 0usize;
+    let mut branches = Vec::new();
+    for token in post {
         match *token {
-            Tok::Num(n) => stack.push(n),
-            Tok::Op(ref op) => {
-                let b = try!(stack.pop().ok_or("Premature stack end".to_string()));
-                let a = try!(stack.pop().ok_or("Premature stack end".to_string()));
-                let r = try!(op.apply_binary(a, b));
-                stack.push(r);
+            Tok::Num(_) | Tok::Var(_) => depth += 1,
+            Tok::Op(Op::UnNeg) | Tok::Func(_) => {}
+            Tok::Op(_) => depth = depth.saturating_sub(1),
+            Tok::Call(_, n) => depth = depth.saturating_sub(n) + 1,
+            Tok::Then => {
+                depth = depth.saturating_sub(1);
+                branches.push(depth);
+            }
+            // Each branch leaves one value on top of what was there before
+            // the condition; the `then` value makes way for the `else` one.
+            Tok::Else | Tok::EndIf => {
+                let base = if *token == Tok::Else {
+                    branches.last().cloned()
+                } else {
+                    branches.pop()
+                };
+                if base.map(|base| base + 1) != Some(depth) {
+                    return Err(ExprError::MalformedConditional);
+                }
+                if *token == Tok::Else {
+                    depth -= 1;
+                }
             }
             _ => {}
         }
     }
-    stack.pop().ok_or("No result".to_string())
+    Ok(())
 }
 
 
-#[cfg(tests)]
+#[cfg(test)]
 pub mod tests {
     use super::*;
+    use std::collections::HashSet;
 
     #[test]
     pub fn test_tokenize() {
         let expr = "1 + 2 - 5 + (7 +8)";
-        let toks = tokenize(expr);
+        let toks = tok(expr).unwrap();
         let expected = vec![Tok::Num(1f64),
                             Tok::Op(Op::Add),
                             Tok::Num(2f64),
@@ -265,5 +1244,479 @@ pub mod tests {
         assert_eq!(toks, expected);
     }
 
+    #[test]
+    pub fn test_errors() {
+        assert_eq!(tok("1 + 2 ~ 3"), Err(ExprError::InvalidToken { pos: 6, found: '~' }));
+        assert_eq!(tok_positions("2 * (x+1)").unwrap().iter().map(|t| t.1).collect::<Vec<_>>(),
+                   vec![0, 2, 4, 5, 6, 7, 8]);
+        assert_eq!(tok("1 + 2.3.4"), Err(ExprError::InvalidNumber {
+            pos: 4, literal: "2.3.4".to_string(), reason: "more than one decimal point".to_string()
+        }));
+        assert_eq!(tok("1 ! 2"), Err(ExprError::InvalidOperator { pos: 2, op: "!".to_string() }));
+        assert_eq!(tok("1 + 2 ~ 3").unwrap_err().diagnostic("1 + 2 ~ 3"),
+                   "Unexpected character '~' at position 6\n    1 + 2 ~ 3\n          ^");
+        assert_eq!(ExprError::Empty.diagnostic(""), "Empty expression");
+
+        let mut tokens = Tokens::new("π·2 ~ (");
+        assert_eq!(tokens.next(), Some(Ok((Tok::Var("π".to_string()), 0))));
+        assert_eq!(tokens.next(), Some(Err(ExprError::InvalidToken { pos: 1, found: '·' })));
+        assert_eq!(tokens.next(), None);
+        assert_eq!(tok("ä ~"), Err(ExprError::InvalidToken { pos: 2, found: '~' }));
+        assert_eq!(tok("\t1e-3 - -.5"),
+                   Ok(vec![Tok::Num(1e-3), Tok::Op(Op::Sub), Tok::Op(Op::UnNeg), Tok::Num(0.5)]));
+        assert_eq!(tok("1 + 2 "), tok("1 + 2"));
+        assert_eq!(eval("(1+2"), Err(ExprError::UnbalancedParen));
+        assert_eq!(eval("sin(0"), Err(ExprError::UnbalancedParen));
+        assert_eq!(Op::UnNeg.apply_binary(1f64, 2f64),
+                   Err(ExprError::MisusedOperator("-".to_string())));
+        assert_eq!(eval("1+2)"), Err(ExprError::UnbalancedParen));
+        assert_eq!(eval("1 ? 2"), Err(ExprError::MalformedConditional));
+        assert_eq!(eval("1 +"), Err(ExprError::ExpectedOperand { pos: 3 }));
+        assert_eq!(eval("1 2"), Err(ExprError::ExpectedOperator { pos: 2 }));
+        assert_eq!(eval("1 2 +"), Err(ExprError::ExpectedOperator { pos: 2 }));
+        assert_eq!(eval("+ 1 2"), Err(ExprError::ExpectedOperand { pos: 0 }));
+        assert_eq!(eval("2 * () + 1"), Err(ExprError::ExpectedOperand { pos: 5 }));
+        assert_eq!(eval("1 ? : 2"), Err(ExprError::ExpectedOperand { pos: 4 }));
+        assert_eq!(eval("(1 ? 2) : 3"), Err(ExprError::MalformedConditional));
+        assert_eq!(eval("1 : 2"), Err(ExprError::MalformedConditional));
+        assert_eq!(validate("2 * (3 +").unwrap_err().diagnostic("2 * (3 +"),
+                   "Expected an operand at position 8\n    2 * (3 +\n            ^");
+        assert_eq!(eval(""), Err(ExprError::Empty));
+        assert_eq!(ExprError::UnboundVariable("y".to_string()).to_string(),
+                   "Unbound variable y");
+        let e: Box<dyn error::Error> = Box::new(ExprError::DivisionByZero);
+        assert_eq!(e.to_string(), "Division by zero");
+    }
+
+    #[test]
+    pub fn test_unary_negation() {
+        assert_eq!(tok("-3").unwrap(), vec![Tok::Op(Op::UnNeg), Tok::Num(3f64)]);
+        assert_eq!(tok("3-3").unwrap()[1], Tok::Op(Op::Sub));
+        assert_eq!(tok("3*-3").unwrap()[2], Tok::Op(Op::UnNeg));
+        assert_eq!(eval("-3+5"), Ok(2f64));
+        assert_eq!(eval("-(2+3)"), Ok(-5f64));
+        assert_eq!(eval("2*-3+1"), Ok(-5f64));
+        assert_eq!(eval("3--2"), Ok(5f64));
+        assert_eq!(eval("(-2)*(-(-4))"), Ok(-8f64));
+        assert_eq!(eval_at("--x", 2f64), Ok(2f64));
+        assert_eq!(eval_at("-x*x", 3f64), Ok(-9f64));
+        assert!(eval("3-").is_err());
+        assert!(eval("-").is_err());
+        assert!(eval("6***7").is_err());
+    }
+
+    #[test]
+    pub fn test_canonical() {
+        assert_eq!(canonical(" 072 - 22*x ** 01"), Ok("72-22*x**1".to_string()));
+        assert_eq!(canonical("(6*7)"), Ok("(6*7)".to_string()));
+        assert_eq!(canonical("-6*-7"), Ok("-6*-7".to_string()));
+        assert!(canonical("6$7").is_err());
+    }
+
+    #[test]
+    pub fn test_eval_at() {
+        assert_eq!(eval_at("x*x+1", 3f64), Ok(10f64));
+        assert_eq!(eval_at("2*(x-1)", 0.5), Ok(-1f64));
+        assert_eq!(eval_at("7", 3f64), Ok(7f64));
+        assert!(eval_at("y+1", 3f64).is_err());
+        assert!(eval("x+1").is_err());
+        assert_eq!(eval_at("2x", 3f64), Ok(6f64));
+    }
+
+    #[test]
+    pub fn test_implicit_multiplication() {
+        assert_eq!(tok("3x").unwrap(),
+                   vec![Tok::Num(3f64), Tok::Op(Op::Mul), Tok::Var("x".to_string())]);
+        assert_eq!(eval("2(3+4)"), Ok(14f64));
+        assert_eq!(eval("(1+2)(3+4)"), Ok(21f64));
+        assert_eq!(eval("(1+2)4"), Ok(12f64));
+        assert_eq!(eval("2sqrt(9)"), Ok(6f64));
+        assert_eq!(eval("2pi"), eval("2*pi"));
+        assert_eq!(eval_at("3x**2 - x(1+1)", 2f64), Ok(8f64));
+        assert_eq!(eval_at("-2x", 3f64), Ok(-6f64));
+        assert_eq!(canonical("2 (x)"), Ok("2*(x)".to_string()));
+        assert_eq!(tok_positions("2(1)").unwrap()[1], (Tok::Op(Op::Mul), 1));
+        assert_eq!(eval("2 3"), Err(ExprError::ExpectedOperator { pos: 2 }));
+        assert_eq!(eval("sqrt 2"), Err(ExprError::ExpectedOperator { pos: 5 }));
+    }
+
+    #[test]
+    pub fn test_canonical_key() {
+        assert_eq!(Canonical::new("6 * 07"), Canonical::new("6*7"));
+        assert_eq!(Canonical::new("6 * 07").as_str(), "6*7");
+        assert_ne!(Canonical::new("6*7"), Canonical::new("7*6"));
+        assert_ne!(Canonical::new("072+"), Canonical::new("72+"));
+        let mut seen = HashSet::new();
+        assert!(seen.insert(Canonical::new("sqrt( 4 )")));
+        assert!(!seen.insert(Canonical::new("sqrt(4)")));
+    }
+
+    #[test]
+    pub fn test_eval_postfix_str() {
+        assert_eq!(eval_postfix_str("3 4 + 2 *"), Ok(14f64));
+        assert_eq!(eval_postfix_str("1 2 3 - -"), Ok(2f64));
+        assert_eq!(eval_postfix_str("1 2 - 3 -"), Ok(-4f64));
+        assert_eq!(eval_postfix_str("-3 2 **  1e1 +"), Ok(19f64));
+        assert_eq!(eval_postfix_str("9 sqrt pi *"), Ok(3f64 * ::std::f64::consts::PI));
+        assert_eq!(eval_postfix_str("0x10 2 <<"), Ok(64f64));
+        assert_eq!(eval_postfix_str("1 +"), Err(ExprError::StackUnderflow));
+        assert_eq!(eval_postfix_str("1 2"), Err(ExprError::MissingOperator));
+        assert_eq!(eval_postfix_str("1 x +"), Err(ExprError::UnboundVariable("x".to_string())));
+        assert_eq!(eval_postfix_str("1 2 +3"), Err(ExprError::InvalidToken { pos: 4, found: '+' }));
+        assert!(eval_postfix_str("1.2.3 1 +").is_err());
+        assert_eq!(eval_postfix_str(""), Err(ExprError::Empty));
+    }
+
+    #[test]
+    pub fn test_validate() {
+        let cases = ["1+2", "1+", "1 2", "", "-", "sqrt()", "sqrt(1)", "(1", "1)", "1 ? 2 : 3",
+                     "1 ? : 2 3", "1 ? 2", "1/0", "x**y", "1 ? 2 : 3 ? 4 : 5", "()", "+1"];
+        for e in &cases {
+            assert_eq!(validate(e), ast::parse(e).map(|_| ()), "{}", e);
+        }
+        assert_eq!(validate("1/0"), Ok(()));
+        assert_eq!(validate("sqrt(-1) + y"), Ok(()));
+        assert_eq!(validate("1 +"), Err(ExprError::ExpectedOperand { pos: 3 }));
+    }
+
+    #[test]
+    pub fn test_postfix_compat() {
+        // The Pratt parser puts well-formed expressions in the same order as
+        // the shunting-yard algorithm did, and rejects the malformed ones.
+        let cases = ["1+2*3", "2**3**2", "-2**2", "-x*-(y+1)", "1-2-3", "1 < 2 == 3 && 4 | 5",
+                     "c ? a : d ? b : e", "c ? d ? 1 : 2 : 3", "-1 ? 2 + 3 : 4 * 5", "(1+2",
+                     "sqrt(4) + abs(-x) * log(2(3+4))", "1 ? (2 ? 3 : 4) : 5", "2x(y)"];
+        for e in &cases {
+            assert_eq!(postfix(e).is_ok(), postfix_compat(e).is_ok(), "{}", e);
+            if let Ok(post) = postfix(e) {
+                assert_eq!(Ok(post), postfix_compat(e), "{}", e);
+            }
+        }
+        let policy = SymbolPolicy {
+            operators: OPERATORS.to_vec(),
+            functions: vec![Func::Sqrt, Func::Abs],
+            variables: vec!["x".to_string()],
+            ..Default::default()
+        };
+        let mut table = PrecedenceTable::default();
+        table.set(Op::Sub, 8, Assoc::Left);
+        table.set(Op::UnNeg, 9, Assoc::Right);
+        let mut rng = ::rand::thread_rng();
+        for _ in 0..200 {
+            let e = random_expr(&mut rng, 5, &policy).to_string();
+            assert_eq!(postfix(&e), postfix_compat(&e), "{}", e);
+            assert_eq!(postfix_with(&e, &table), shunting_yard(Tokens::new(&e), Some(&table)));
+        }
+        for e in &["1 2 +", "+ 1 2", "1 ? : 2 3", "()", "sqrt()", "1 +"] {
+            assert!(postfix(e).is_err(), "{}", e);
+        }
+        assert_eq!(postfix_compat("1 2 +"), Ok(vec![Tok::Num(1f64), Tok::Num(2f64),
+                                                  Tok::Op(Op::Add)]));
+    }
+
+    #[test]
+    pub fn test_precedence_table() {
+        let mut table = PrecedenceTable::default();
+        assert_eq!(table.level(&Op::Sub), (8, Assoc::Right));
+        assert_eq!(postfix_with("1-2-3", &table), postfix("1-2-3"));
+        assert_eq!(table.eval("1-2-3"), Ok(2f64));
+        assert_eq!(table.eval("-2**2"), Ok(4f64));
+
+        table.set(Op::Sub, 8, Assoc::Left);
+        table.set(Op::Add, 8, Assoc::Left);
+        table.set(Op::Div, 9, Assoc::Left);
+        table.set(Op::UnNeg, 9, Assoc::Right);
+        assert_eq!(table.eval("1-2-3"), Ok(-4f64));
+        assert_eq!(table.eval("1-2+3"), Ok(2f64));
+        assert_eq!(table.eval("8/2/2"), Ok(2f64));
+        assert_eq!(table.eval("2**3**2"), Ok(512f64));
+        assert_eq!(table.eval("-2**2"), Ok(-4f64));
+        assert_eq!(table.eval("-2*3"), Ok(-6f64));
+        assert_eq!(table.parse("1-2-3").unwrap().to_string(), "(1 - 2) - 3");
+
+        table.set(Op::Mul, 4, Assoc::Right);
+        assert_eq!(table.eval("1+2*3"), Ok(9f64));
+    }
+
+    #[test]
+    pub fn test_limits() {
+        let limits = Limits { max_length: 12, max_tokens: 9, max_depth: 2 };
+        assert_eq!(limits.eval("((1+2))*3"), Ok(9f64));
+        assert_eq!(limits.eval("abs(((3)))"), Err(ExprError::LimitExceeded {
+            limit: Limit::Depth, max: 2
+        }));
+        assert_eq!(limits.eval("1+2+3+4+5"), Ok(15f64));
+        assert_eq!(limits.eval("1+2+3+4+5+6"), Err(ExprError::LimitExceeded {
+            limit: Limit::Tokens, max: 9
+        }));
+        let four = Limits { max_tokens: 4, ..limits };
+        assert_eq!(four.eval("2*3"), Ok(6f64));
+        assert_eq!(four.eval("2(3)"), Err(ExprError::LimitExceeded {
+            limit: Limit::Tokens, max: 4
+        }));
+        assert_eq!(limits.eval("1 +         2"), Err(ExprError::LimitExceeded {
+            limit: Limit::Length, max: 12
+        }));
+        assert_eq!(limits.check("1 ~ 2"), Err(ExprError::InvalidToken { pos: 2, found: '~' }));
+        let mut env = HashMap::new();
+        env.insert("x".to_string(), 3f64);
+        assert_eq!(limits.eval_with("x*pi", &env), Ok(3f64 * consts::PI));
+
+        // Nesting far beyond the default limit is refused before parsing.
+        let deep = format!("{}1{}", "(".repeat(100000), ")".repeat(100000));
+        assert_eq!(Limits { max_length: 1000000, ..Limits::default() }.eval(&deep),
+                   Err(ExprError::LimitExceeded { limit: Limit::Depth, max: 64 }));
+        assert_eq!(Limits::default().parse(&deep), Err(ExprError::LimitExceeded {
+            limit: Limit::Length, max: 10000
+        }));
+        assert!(ExprError::LimitExceeded { limit: Limit::Depth, max: 64 }.to_string()
+                                                                         .contains("64"));
+    }
+
+    #[test]
+    pub fn test_angle_unit() {
+        let degrees = AngleUnit::Degrees;
+        assert_eq!(degrees.eval("sin(30)"), Ok(0.5));
+        assert_eq!(degrees.eval("sin(180) + cos(90) + cos(-270)"), Ok(0f64));
+        assert_eq!(degrees.eval("cos(60) + sin(-30) + sin(7230)"), Ok(0.5));
+        assert_eq!(degrees.eval("sin(45)**2"), Ok(45f64.to_radians().sin().powi(2)));
+        assert_eq!(degrees.eval("sqrt(4) + abs(-1)"), Ok(3f64));
+        let mut env = HashMap::new();
+        env.insert("x".to_string(), 60f64);
+        assert_eq!(degrees.eval_with("cos(x)", &env), Ok(0.5));
+        assert_eq!(AngleUnit::default().eval("sin(pi/2)"), eval("sin(pi/2)"));
+        assert_eq!(AngleUnit::Radians.eval_with("cos(x)", &env), Ok(60f64.cos()));
+    }
+
+    #[test]
+    pub fn test_percent() {
+        assert_eq!(eval("200*15%"), Ok(30f64));
+        assert_eq!(eval("(15%) * 200"), eval("0.15 * 200"));
+        assert_eq!(eval("(-50%) + 1"), Ok(0.5));
+        assert_eq!(eval("2**50%"), eval("2**0.5"));
+        assert_eq!(eval("((1+2)%) == 0.03 ? sqrt(4)% : 0"), Ok(0.02));
+        assert_eq!(eval("(50%)%"), Ok(0.005));
+        assert_eq!(eval("(15%) - 5"), Ok(-4.85));
+        // Followed by anything else, whatever the spacing, it is modulo.
+        assert_eq!(eval("7 % 3"), Ok(1f64));
+        assert_eq!(eval("7%-3"), Ok(1f64));
+        assert_eq!(eval("7 % - 3"), Ok(1f64));
+        assert_eq!(eval("15% - 5"), eval("15 % -5"));
+        assert!(eval("6%*7").is_err());
+        assert!(eval("10 % +3").is_err());
+        assert!(eval("50%%").is_err());
+        assert_eq!(eval("7 % (1+1)"), Ok(1f64));
+        assert_eq!(eval_at("9 % x", 5f64), Ok(4f64));
+        assert_eq!(tok("(5%) - 1").unwrap()[2..4], [Tok::Percent, Tok::RParen]);
+        assert_eq!(tok("5% - 1").unwrap()[1], Tok::Op(Op::Mod));
+        assert_eq!(postfix("5%"), Ok(vec![Tok::Num(5f64), Tok::Num(100f64), Tok::Op(Op::Div)]));
+        assert_eq!(postfix("2**50%"), postfix_compat("2**50%"));
+        assert_eq!(ast::parse("200*15%").unwrap().to_string(), "200 * 15 / 100");
+        assert_eq!(canonical("(15%) - 5"), Ok("(15%)-5".to_string()));
+        assert_eq!(Canonical::new("15% - 5"), Canonical::new("15%-5"));
+        assert_eq!(eval("%5"), Err(ExprError::ExpectedOperand { pos: 0 }));
+        assert_eq!(eval_i64("300*15%"), Err(ExprError::Inexact("15 / 100".to_string())));
+    }
+
+    #[test]
+    pub fn test_eval_with() {
+        let mut env = HashMap::new();
+        env.insert("x".to_string(), 3f64);
+        env.insert("rate_2".to_string(), 0.5);
+        assert_eq!(eval_with("x*rate_2+1", &env), Ok(2.5));
+        assert_eq!(eval_with("(x+y)", &env), Err(ExprError::UnboundVariable("y".to_string())));
+        assert_eq!(eval_with("6*7", &HashMap::new()), Ok(42f64));
+    }
+
+    #[test]
+    pub fn test_constants() {
+        assert_eq!(eval("2*pi"), Ok(2f64 * consts::PI));
+        assert_eq!(eval("log(e)"), Ok(1f64));
+        assert_eq!(eval_at("pi*x", 2f64), Ok(2f64 * consts::PI));
+        let mut env = HashMap::new();
+        env.insert("r".to_string(), 3f64);
+        assert_eq!(eval_with("2*pi*r", &env), Ok(6f64 * consts::PI));
+        env.insert("e".to_string(), 1f64);
+        assert_eq!(eval_with("e+r", &env), Ok(4f64));
+
+        let mut constants = Constants::default();
+        assert_eq!(constants.get("pi"), Some(consts::PI));
+        assert_eq!(constants.define("g", 9.8), Ok(()));
+        assert_eq!(constants.eval("g*2"), Ok(19.6));
+        assert!(constants.eval_with("g*t", &env).is_err());
+        assert!(constants.define("2g", 1f64).is_err());
+        assert!(constants.define("sqrt(", 1f64).is_err());
+        assert!(eval("g").is_err());
+    }
+
+    #[test]
+    pub fn test_eval_in() {
+        assert_eq!(eval_in("0.1*3", EvalMode::Float), Ok(0.30000000000000004));
+        assert_eq!(eval_in("0.1*3", EvalMode::Exact), Ok(0.3));
+        assert!(eval_in("sqrt(2)", EvalMode::Exact).is_err());
+        assert_eq!(eval_in("(7/2)*2", EvalMode::Float), Ok(7f64));
+        assert!(eval_in("(7/2)*2", EvalMode::Integer).is_err());
+        assert_eq!(eval_in("(8/2)*2", EvalMode::Integer), Ok(8f64));
+        assert_eq!(EvalMode::default(), EvalMode::Float);
+    }
+
+    #[test]
+    pub fn test_decimals() {
+        assert_eq!(tok("3.5+1").unwrap(), vec![Tok::Num(3.5), Tok::Op(Op::Add), Tok::Num(1f64)]);
+        assert_eq!(eval("3.5+1"), Ok(4.5));
+        assert_eq!(eval(".5*3."), Ok(1.5));
+        assert_eq!(eval_at("0.25*x", 2f64), Ok(0.5));
+        assert_eq!(canonical("02.50"), Ok("2.5".to_string()));
+        assert_eq!(tok("1.2.3"),
+                   Err(ExprError::InvalidNumber {
+                       pos: 0,
+                       literal: "1.2.3".to_string(),
+                       reason: "more than one decimal point".to_string(),
+                   }));
+        assert!(tok("3+.").is_err());
+    }
+
+    #[test]
+    pub fn test_radix_literals() {
+        assert_eq!(tok("0x1F").unwrap(), vec![Tok::Num(31f64)]);
+        assert_eq!(tok("0o17 0b1010").unwrap(), vec![Tok::Num(15f64), Tok::Num(10f64)]);
+        assert_eq!(eval("0xff+0B11*0X10"), Ok(303f64));
+        assert_eq!(canonical("0x1f"), Ok("31".to_string()));
+        assert_eq!(eval_at("0x", 2f64), Ok(0f64));
+        assert_eq!(tok("0b102").unwrap(), vec![Tok::Num(2f64), Tok::Num(2f64)]);
+        assert!(matches!(tok("0x1ffffffffffffffff"), Err(ExprError::InvalidNumber { .. })));
+    }
+
+    #[test]
+    pub fn test_scientific_notation() {
+        assert_eq!(tok("1e3").unwrap(), vec![Tok::Num(1000f64)]);
+        assert_eq!(tok("2.5E-2").unwrap(), vec![Tok::Num(0.025)]);
+        assert_eq!(eval("1e+2*3"), Ok(300f64));
+        assert_eq!(eval(".5e1-1"), Ok(4f64));
+        assert_eq!(canonical("1E3"), Ok("1000".to_string()));
+        assert_eq!(tok("2e").unwrap(),
+                   vec![Tok::Num(2f64), Tok::Op(Op::Mul), Tok::Var("e".to_string())]);
+        assert_eq!(tok("2e-x").unwrap()[2], Tok::Var("e".to_string()));
+    }
+
+    #[test]
+    pub fn test_exponentiation() {
+        assert_eq!(eval("2**3**2"), Ok(512f64));
+        assert_eq!(eval("(2**3)**2"), Ok(64f64));
+        assert_eq!(eval("4**0.5"), Ok(2f64));
+        assert_eq!(eval("2**-1"), Ok(0.5));
+        assert_eq!(eval("2**3*2"), Ok(16f64));
+        assert_eq!(eval_at("x**2+1", 3f64), Ok(10f64));
+        assert_eq!(eval("(-8)**0.5"), Err(ExprError::DomainError("-8**0.5".to_string())));
+        assert_eq!(eval("0**-1"), Err(ExprError::DivisionByZero));
+        assert_eq!(eval("(-8)**(1/0)"), Err(ExprError::DivisionByZero));
+        assert_eq!(eval("(-2)**3"), Ok(-8f64));
+    }
+
+    #[test]
+    pub fn test_functions() {
+        assert_eq!(tok("sqrt(2)").unwrap(),
+                   vec![Tok::Func(Func::Sqrt), Tok::LParen, Tok::Num(2f64), Tok::RParen]);
+        assert_eq!(eval("sqrt(16)+1"), Ok(5f64));
+        assert_eq!(eval("2*sqrt(9+7)"), Ok(8f64));
+        assert_eq!(eval("abs(-3)*2"), Ok(6f64));
+        assert_eq!(eval("-abs (3)"), Ok(-3f64));
+        assert_eq!(eval("sqrt(sqrt(16))"), Ok(2f64));
+        assert_eq!(eval("log(1)+cos(0)"), Ok(1f64));
+        assert_eq!(eval_at("sqrt(2)*sin(x)", 0f64), Ok(0f64));
+        assert_eq!(canonical("sqrt ( 2 )"), Ok("sqrt(2)".to_string()));
+        assert_eq!(eval("log(-1)"), Err(ExprError::DomainError("log(-1)".to_string())));
+        assert!(eval("log(0)").unwrap_err().is_undefined());
+        assert!(eval("sqrt(-4)").unwrap_err().is_undefined());
+        assert!(eval("sqrt()").is_err());
+        assert!(eval("sqrt+1").is_err());
+    }
+
+    #[test]
+    pub fn test_comparisons() {
+        assert_eq!(tok("1<=2").unwrap()[1], Tok::Op(Op::Le));
+        assert_eq!(tok("1<-2").unwrap()[1..3], [Tok::Op(Op::Lt), Tok::Op(Op::UnNeg)]);
+        assert_eq!(eval("1<2"), Ok(1f64));
+        assert_eq!(eval("2<=1"), Ok(0f64));
+        assert_eq!(eval("3>=3"), Ok(1f64));
+        assert_eq!(eval("1+1==2"), Ok(1f64));
+        assert_eq!(eval("2*3!=6"), Ok(0f64));
+        assert_eq!(eval("1<2==2>1"), Ok(1f64));
+        assert_eq!(eval("1<2 && 3>4 || 5>4"), Ok(1f64));
+        assert_eq!(eval("0||0"), Ok(0f64));
+        assert_eq!(eval("2&&-1"), Ok(1f64));
+        assert_eq!(eval_at("(x>0)*x", -3f64), Ok(0f64));
+        assert_eq!(canonical("1 <= 2 && x"), Ok("1<=2&&x".to_string()));
+        assert!(eval("1=2").is_err());
+        assert!(eval("1!2").is_err());
+        assert_eq!(eval("1&2"), Ok(0f64));
+    }
+
+    #[test]
+    pub fn test_conditionals() {
+        assert_eq!(tok("1?2:3").unwrap(),
+                   vec![Tok::Num(1f64), Tok::Then, Tok::Num(2f64), Tok::Else, Tok::Num(3f64)]);
+        assert_eq!(eval("1 ? 2 : 3"), Ok(2f64));
+        assert_eq!(eval("1 > 2 ? 2 : 3"), Ok(3f64));
+        assert_eq!(eval("0 ? 1 : 0 ? 2 : 3"), Ok(3f64));
+        assert_eq!(eval("1 ? 0 ? 1 : 2 : 3"), Ok(2f64));
+        assert_eq!(eval("(1 ? 2 : 3) * 4"), Ok(8f64));
+        assert_eq!(eval("1 ? 2 : 3 * 4"), Ok(2f64));
+        assert_eq!(eval("0 ? 1 : -1"), Ok(-1f64));
+        assert_eq!(eval_at("x < 0 ? -x : x", -3f64), Ok(3f64));
+        assert_eq!(eval_at("x < 0 ? -x : x", 2f64), Ok(2f64));
+        // The branch not taken is never evaluated.
+        assert_eq!(eval("1 ? 2 : y"), Ok(2f64));
+        assert_eq!(eval("0 ? y : 3"), Ok(3f64));
+        assert!(eval("1 ? y : 3").is_err());
+        assert_eq!(canonical("1 ? 2 : 3"), Ok("1?2:3".to_string()));
+        assert!(eval("1 ? 2").is_err());
+        assert!(eval("1 : 2").is_err());
+        assert!(eval("(1 ? 2) : 3").is_err());
+        assert!(eval("1 ? 2 :").is_err());
+        assert!(eval("1 ? 2 : 3 4").is_err());
+        assert!(eval("0 ? 2 5 : 3").is_err());
+    }
+
+    #[test]
+    pub fn test_bitwise() {
+        assert_eq!(tok("1<<2>>1").unwrap(),
+                   vec![Tok::Num(1f64), Tok::Op(Op::Shl), Tok::Num(2f64), Tok::Op(Op::Shr),
+                        Tok::Num(1f64)]);
+        assert_eq!(eval("12 & 10"), Ok(8f64));
+        assert_eq!(eval("12 | 3"), Ok(15f64));
+        assert_eq!(eval("12 ^ 10"), Ok(6f64));
+        assert_eq!(eval("1 << 4"), Ok(16f64));
+        assert_eq!(eval("-16 >> 2"), Ok(-4f64));
+        assert_eq!(eval("7.9 & 3.2"), Ok(3f64));
+        assert_eq!(eval("1 | 6 & 3"), Ok(3f64));
+        assert_eq!(eval("1 << 2 + 1"), Ok(8f64));
+        assert_eq!(eval("6 & 3 == 3"), Ok(0f64));
+        assert_eq!(eval("1 ^ 3 && 2"), Ok(1f64));
+        assert_eq!(eval("1 < 2 & 3"), Ok(1f64));
+        assert!(eval("1 << 64").unwrap_err().is_undefined());
+        assert!(eval("1 >> -1").unwrap_err().is_undefined());
+        assert!(eval("2**70 | 1").unwrap_err().is_undefined());
+        assert_eq!(eval("1 && 2"), Ok(1f64));
+    }
+
+    #[test]
+    pub fn test_modulo() {
+        assert_eq!(tok("7%3").unwrap()[1], Tok::Op(Op::Mod));
+        assert_eq!(eval("7%3"), Ok(1f64));
+        assert_eq!(eval("7%3+1"), Ok(2f64));
+        assert_eq!(eval("-7%3"), Ok(-1f64));
+        assert_eq!(eval("7.5%2"), Ok(1.5));
+        assert_eq!(eval("7%0"), Err(ExprError::DivisionByZero));
+        assert_eq!(eval("1/0"), Err(ExprError::DivisionByZero));
+        assert_eq!(eval("1/(1-1)+2"), Err(ExprError::DivisionByZero));
+        assert!(!ExprError::Empty.is_undefined());
+        assert_eq!(canonical("7 % 3"), Ok("7%3".to_string()));
+        // A `%` before another operator is modulo, so a percentage of which
+        // to take a remainder needs parentheses.
+        assert!(eval("7%%3").is_err());
+        assert_eq!(eval("(7%)%3"), eval("0.07 % 3"));
+    }
+
 
 }
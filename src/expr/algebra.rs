@@ -0,0 +1,319 @@
+use rand::{Rng, thread_rng};
+use super::{canonical, compile, ExprError, Func, Op, Result};
+use super::ast::{parse, Expr};
+use super::rewrite::Rewriter;
+use super::visit::{walk_transform, ExprTransformer};
+
+/// Number of random points at which `equivalent` compares expressions.
+const SAMPLES: usize = 32;
+
+/// Return `e` simplified bottom-up: operations on numbers are folded into
+/// numbers, unless that yields NaN or infinity, conditionals on numbers are
+/// resolved, and identities like `x*1`, `x+0`, `x**1` and `--x` are applied
+/// (see `Rewriter::identities()`). Differences like `x-x` and products with
+/// 0 are left alone, since `x` could be undefined or infinite.
+pub fn simplify(e: Expr) -> Expr {
+    thread_local!(static IDENTITIES: Rewriter = Rewriter::identities());
+    IDENTITIES.with(|identities| simplify_with(e, identities))
+}
+
+/// Return `e` simplified as by `simplify`, but with the rules of `rules`
+/// for identities, e.g., `Rewriter::identities()` with rules of one's own
+/// added.
+pub fn simplify_with(e: Expr, rules: &Rewriter) -> Expr {
+    Simplifier { rules, steps: 0 }.transform_expr(e)
+}
+
+/// Folds numbers, and applies its rules to each node once its operands are
+/// simplified, until none applies or the rules' limit on rewrites is
+/// reached.
+struct Simplifier<'a> {
+    rules: &'a Rewriter,
+    steps: usize,
+}
+
+impl<'a> ExprTransformer for Simplifier<'a> {
+    fn transform_expr(&mut self, e: Expr) -> Expr {
+        let e = walk_transform(self, e);
+        self.rules.apply_all(e, &mut self.steps)
+    }
+
+    fn transform_unary(&mut self, op: Op, a: Expr) -> Expr {
+        match self.transform_expr(a) {
+            Expr::Num(n) => Expr::Num(-n),
+            a => Expr::Unary(op, Box::new(a)),
+        }
+    }
+
+    fn transform_binary(&mut self, op: Op, a: Expr, b: Expr) -> Expr {
+        let (a, b) = (self.transform_expr(a), self.transform_expr(b));
+        if let (&Expr::Num(x), &Expr::Num(y)) = (&a, &b) {
+            if let Ok(v) = op.apply_binary(x, y) {
+                if v.is_finite() {
+                    return Expr::Num(v);
+                }
+            }
+        }
+        Expr::Binary(op, Box::new(a), Box::new(b))
+    }
+
+    fn transform_call(&mut self, func: Func, a: Expr) -> Expr {
+        match self.transform_expr(a) {
+            Expr::Num(n) => match func.apply(n) {
+                Ok(v) if v.is_finite() => Expr::Num(v),
+                _ => Expr::Call(func, Box::new(Expr::Num(n))),
+            },
+            a => Expr::Call(func, Box::new(a)),
+        }
+    }
+
+    /// A conditional on a number is resolved without simplifying the other
+    /// branch.
+    fn transform_cond(&mut self, c: Expr, a: Expr, b: Expr) -> Expr {
+        match self.transform_expr(c) {
+            Expr::Num(c) if c != 0f64 => self.transform_expr(a),
+            Expr::Num(_) => self.transform_expr(b),
+            c => {
+                let a = self.transform_expr(a);
+                Expr::Cond(Box::new(c), Box::new(a), Box::new(self.transform_expr(b)))
+            }
+        }
+    }
+}
+
+/// Return the derivative of `e` with respect to the variable `var`,
+/// simplified. Comparisons and boolean operators are piecewise constant, so
+/// their derivative is 0, and that of a conditional is the conditional of the
+/// derivatives of its branches. Registered functions are opaque: a call
+/// whose arguments depend on `var` is an error.
+pub fn derive(e: &Expr, var: &str) -> Result<Expr> {
+    derivative(e, var).map(simplify)
+}
+
+fn derivative(e: &Expr, var: &str) -> Result<Expr> {
+    let d = |e: &Expr| derivative(e, var);
+    Ok(match *e {
+        Expr::Num(_) => Expr::Num(0f64),
+        Expr::Var(ref v) => Expr::Num(if v == var { 1f64 } else { 0f64 }),
+        Expr::Unary(ref op, ref a) => Expr::Unary(op.clone(), Box::new(d(a)?)),
+        Expr::Binary(ref op, ref a, ref b) => {
+            let (a, b) = (&**a, &**b);
+            let (da, db) = (d(a)?, d(b)?);
+            // Operands not depending on `var` get the rules for constants,
+            // since simplify() leaves products with 0 alone.
+            let (ca, cb) = (is_zero(&da), is_zero(&db));
+            match *op {
+                _ if ca && cb => Expr::Num(0f64),
+                Op::Add | Op::Sub => binary(op.clone(), da, db),
+                Op::Mul if cb => binary(Op::Mul, da, b.clone()),
+                Op::Mul if ca => binary(Op::Mul, a.clone(), db),
+                Op::Mul => binary(Op::Add, binary(Op::Mul, da, b.clone()),
+                                  binary(Op::Mul, a.clone(), db)),
+                Op::Div if cb => binary(Op::Div, da, b.clone()),
+                Op::Div if ca => Expr::Unary(Op::UnNeg, Box::new(binary(
+                    Op::Div, binary(Op::Mul, a.clone(), db),
+                    binary(Op::Exp, b.clone(), Expr::Num(2f64))))),
+                Op::Div => binary(Op::Div,
+                                  binary(Op::Sub, binary(Op::Mul, da, b.clone()),
+                                         binary(Op::Mul, a.clone(), db)),
+                                  binary(Op::Exp, b.clone(), Expr::Num(2f64))),
+                // With a constant exponent, the power rule; otherwise,
+                // (a**b)' = a**b * (b' * log(a) + b * a' / a).
+                Op::Exp if cb => {
+                    let power = binary(Op::Exp, a.clone(),
+                                       binary(Op::Sub, b.clone(), Expr::Num(1f64)));
+                    binary(Op::Mul, binary(Op::Mul, b.clone(), power), da)
+                }
+                Op::Exp if ca => binary(Op::Mul, e.clone(),
+                                        binary(Op::Mul, db, call(Func::Log, a.clone()))),
+                Op::Exp => binary(Op::Mul, e.clone(),
+                                  binary(Op::Add,
+                                         binary(Op::Mul, db, call(Func::Log, a.clone())),
+                                         binary(Op::Div, binary(Op::Mul, b.clone(), da),
+                                                a.clone()))),
+                // a % b is a - b * n for the whole number n = (a - a % b) / b.
+                Op::Mod if cb => da,
+                Op::Mod => {
+                    let n = binary(Op::Div, binary(Op::Sub, a.clone(), e.clone()), b.clone());
+                    binary(Op::Sub, da, binary(Op::Mul, db, n))
+                }
+                _ => Expr::Num(0f64),
+            }
+        }
+        Expr::Call(ref func, ref a) => {
+            let da = d(a)?;
+            if is_zero(&da) {
+                return Ok(Expr::Num(0f64));
+            }
+            let outer = match *func {
+                Func::Sqrt => binary(Op::Div, Expr::Num(1f64),
+                                     binary(Op::Mul, Expr::Num(2f64), e.clone())),
+                Func::Sin => call(Func::Cos, (**a).clone()),
+                Func::Cos => Expr::Unary(Op::UnNeg, Box::new(call(Func::Sin, (**a).clone()))),
+                Func::Log => binary(Op::Div, Expr::Num(1f64), (**a).clone()),
+                Func::Abs => Expr::Cond(Box::new(binary(Op::Lt, (**a).clone(), Expr::Num(0f64))),
+                                        Box::new(Expr::Num(-1f64)),
+                                        Box::new(Expr::Num(1f64))),
+            };
+            binary(Op::Mul, outer, da)
+        }
+        Expr::Apply(ref name, ref args) => {
+            for a in args {
+                if !is_zero(&d(a)?) {
+                    return Err(ExprError::NotDifferentiable(name.clone()));
+                }
+            }
+            Expr::Num(0f64)
+        }
+        Expr::Cond(ref c, ref a, ref b) => {
+            Expr::Cond(c.clone(), Box::new(d(a)?), Box::new(d(b)?))
+        }
+    })
+}
+
+/// Return whether the expressions `a` and `b` are the same: if they simplify
+/// to the same tree, or else if they agree, to within rounding, at a number
+/// of random points in [-10, 10] for their variables, or at the one point if
+/// they have none, with undefined values agreeing with each other. The
+/// second test can err in taking different expressions for the same, e.g.,
+/// ones that differ on a tiny interval. Malformed expressions are the same
+/// only if they are spelt the same.
+pub fn equivalent(a: &str, b: &str) -> bool {
+    match (parse(a), parse(b)) {
+        (Ok(x), Ok(y)) if simplify(x.clone()) == simplify(y.clone()) => return true,
+        (Ok(_), Ok(_)) => {}
+        _ => return a == b || canonical(a).is_ok_and(|a| canonical(b) == Ok(a)),
+    }
+    let (p, q) = match (compile(a), compile(b)) {
+        (Ok(p), Ok(q)) => (p, q),
+        _ => return false,
+    };
+    let mut variables = p.variables().to_vec();
+    variables.extend(q.variables().iter().filter(|v| !p.variables().contains(v)).cloned());
+    let samples = if variables.is_empty() { 1 } else { SAMPLES };
+    let mut rng = thread_rng();
+    (0..samples).all(|_| {
+        let point = variables.iter().map(|_| rng.gen_range(-10f64, 10f64)).collect::<Vec<_>>();
+        let args = |vars: &[String]| {
+            vars.iter().map(|v| point[variables.iter().position(|w| w == v).unwrap()])
+                .collect::<Vec<_>>()
+        };
+        agree(p.exec(&args(p.variables())), q.exec(&args(q.variables())))
+    })
+}
+
+/// Return whether two values of expressions are the same, to within
+/// rounding.
+fn agree(x: Result<f64>, y: Result<f64>) -> bool {
+    match (x, y) {
+        (Ok(x), Ok(y)) if x.is_nan() || y.is_nan() => x.is_nan() && y.is_nan(),
+        (Ok(x), Ok(y)) => x == y || (x - y).abs() <= 1e-9 * x.abs().max(y.abs()).max(1f64),
+        (Err(e), Err(f)) => e.is_undefined() && f.is_undefined(),
+        _ => false,
+    }
+}
+
+/// Return whether `e` simplifies to 0.
+fn is_zero(e: &Expr) -> bool { simplify(e.clone()) == Expr::Num(0f64) }
+
+fn binary(op: Op, a: Expr, b: Expr) -> Expr { Expr::Binary(op, Box::new(a), Box::new(b)) }
+
+fn call(func: Func, a: Expr) -> Expr { Expr::Call(func, Box::new(a)) }
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use expr::ast::parse;
+
+    fn simplified(s: &str) -> String { simplify(parse(s).unwrap()).to_string() }
+
+    #[test]
+    pub fn test_simplify() {
+        assert_eq!(simplified("1+2*3"), "7");
+        assert_eq!(simplified("x*1+0"), "x");
+        assert_eq!(simplified("1*x**1/1"), "x");
+        assert_eq!(simplified("(x+1)-(x+1)"), "(x + 1) - x + 1");
+        assert_eq!(simplified("(x-x)+3*x"), "(x - x) + 3 * x");
+        // Neither an undefined operand nor an infinite one cancels out.
+        assert_eq!(simplified("1/0 - 1/0"), "1 / 0 - 1 / 0");
+        assert_eq!(simplified("log(0) - log(0)"), "log(0) - log(0)");
+        assert_eq!(simplified("(1/0) ** 0"), "(1 / 0) ** 0");
+        assert_eq!(simplified("sqrt(-1) ** 0"), "sqrt(-1) ** 0");
+        assert_eq!(simplified("0-x"), "-x");
+        assert_eq!(simplified("--x"), "x");
+        assert_eq!(simplified("-(2*3)"), "-6");
+        assert_eq!(simplified("x**(2-2)"), "x ** 0");
+        assert_eq!(simplified("sqrt(16)*x"), "4 * x");
+        assert_eq!(simplified("1<2 ? x : y"), "x");
+        assert_eq!(simplified("x<2 ? 1+1 : y*1"), "x < 2 ? 2 : y");
+        assert_eq!(simplified("1/0+x"), "1 / 0 + x");
+        assert_eq!(simplified("072-22*1**810+8"), "42");
+        assert_eq!(simplified("x+y"), "x + y");
+        // x could be infinite, and y could be 0.
+        assert_eq!(simplified("0*x+y*0+0/y"), "0 * x + y * 0 + 0 / y");
+    }
+
+    #[test]
+    pub fn test_equivalent() {
+        assert!(equivalent("x+1", "1+x"));
+        assert!(equivalent("x * (y + 1)", "x*y + x"));
+        assert!(equivalent("(x+1)**2", "x*x + 2*x + 1"));
+        assert!(equivalent("sqrt(x*x)", "abs(x)"));
+        assert!(equivalent("2*pi", "pi+pi"));
+        assert!(equivalent("0.1+0.2", "0.3"));
+        assert!(equivalent("1/0", "2/0"));
+        // Undefined values agree only with each other.
+        assert!(!equivalent("1/0 - 1/0", "0"));
+        assert!(!equivalent("log(0)-log(0)", "0"));
+        assert!(equivalent("1/0 - 1/0", "log(0)"));
+        assert!(equivalent("1 +", "1+"));
+        assert!(!equivalent("x+1", "x+2"));
+        assert!(!equivalent("x", "y"));
+        assert!(!equivalent("x*x", "abs(x)*x"));
+        assert!(!equivalent("1/0", "1"));
+        assert!(!equivalent("1 +", "1 -"));
+    }
+
+    #[test]
+    pub fn test_simplify_with() {
+        let mut rules = Rewriter::identities();
+        rules.add("sin(x)**2 + cos(x)**2", "1").unwrap();
+        let e = parse("3 * (sin(2*y)**2 + cos(2*y)**2) + z * 1").unwrap();
+        assert_eq!(simplify_with(e.clone(), &rules).to_string(), "3 + z");
+        assert_eq!(simplify_with(e.clone(), &Rewriter::identities()), simplify(e.clone()));
+
+        // A rule that always applies stops at the limit on rewrites.
+        rules.add("x + y", "y + x").unwrap();
+        assert_eq!(simplify_with(parse("a + b").unwrap(), &rules.with_max_steps(3)).to_string(),
+                   "b + a");
+    }
+
+    #[test]
+    pub fn test_derive() {
+        let derived = |s: &str| derive(&parse(s).unwrap(), "x").unwrap().to_string();
+        assert_eq!(derived("7"), "0");
+        assert_eq!(derived("y"), "0");
+        assert_eq!(derived("3*x+y"), "3");
+        assert_eq!(derived("x**3"), "3 * x ** 2");
+        assert_eq!(derived("sin(x)"), "cos(x)");
+        assert_eq!(derived("-cos(x)"), "sin(x)");
+        assert_eq!(derived("log(x)"), "1 / x");
+        assert_eq!(derived("x < 0 ? -x : x*x"), "x < 0 ? -1 : x + x");
+        assert_eq!(derived("y*sin(y) + 2**x"), "2 ** x * 0.6931471805599453");
+        assert_eq!(derived("1/x"), "-(1 / x ** 2)");
+
+        // Check the rest numerically, against a central difference.
+        let cases = ["x*x/(x+1)", "2**x", "x**x", "sqrt(x)*x", "abs(x-3)", "x%2", "sin(x*x)"];
+        for s in &cases {
+            let (e, de) = (parse(s).unwrap(), derive(&parse(s).unwrap(), "x").unwrap());
+            let at = |x: f64| move |var: &str| if var == "x" { Some(x) } else { None };
+            for &x in &[0.7, 1.3, 2.5] {
+                let h = 1e-6;
+                let numeric =
+                    (e.eval(&at(x + h)).unwrap() - e.eval(&at(x - h)).unwrap()) / (2f64 * h);
+                assert!((de.eval(&at(x)).unwrap() - numeric).abs() < 1e-4, "{} at {}", s, x);
+            }
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+use std::fmt;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use super::{check_branches, postfix, postfix_tokens, AngleUnit, ExprError, Func, Op, Registry,
+            Result, Tok, Tokens};
+use super::visit::{walk_expr, ExprVisitor};
+
+/// An expression tree.
+#[derive(Debug,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Expr {
+    Num(f64),
+    Var(String),
+    /// A prefix operator, i.e., negation.
+    Unary(Op, Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    Call(Func, Box<Expr>),
+    /// A call to a registered function.
+    Apply(String, Vec<Expr>),
+    /// A conditional `c ? a : b`.
+    Cond(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// Return how tightly this expression binds as an operand: conditionals
+    /// least of all, then binary operators by precedence, then negation.
+    fn binding(&self) -> u8 {
+        match *self {
+            Expr::Cond(..) => 0,
+            Expr::Binary(ref op, _, _) | Expr::Unary(ref op, _) => op.precedence() + 1,
+            Expr::Num(n) if n < 0f64 => Op::UnNeg.precedence() + 1,
+            _ => u8::MAX,
+        }
+    }
+
+    /// Evaluate this expression, looking up the value of each variable with
+    /// `lookup`. Only the branch taken of a conditional is evaluated. Calls
+    /// to registered functions are an error.
+    pub fn eval<F: Fn(&str) -> Option<f64>>(&self, lookup: &F) -> Result<f64> {
+        self.evaluate(lookup, None, AngleUnit::Radians)
+    }
+
+    /// Evaluate this expression like `eval`, with the trigonometric
+    /// functions taking angles in `unit`.
+    pub fn eval_in<F>(&self, lookup: &F, unit: AngleUnit) -> Result<f64>
+            where F: Fn(&str) -> Option<f64> {
+        self.evaluate(lookup, None, unit)
+    }
+
+    /// Evaluate this expression like `eval`, calling registered functions
+    /// from `functions`.
+    pub fn eval_using<F>(&self, lookup: &F, functions: &Registry) -> Result<f64>
+            where F: Fn(&str) -> Option<f64> {
+        self.evaluate(lookup, Some(functions), AngleUnit::Radians)
+    }
+
+    fn evaluate<F>(&self, lookup: &F, functions: Option<&Registry>, unit: AngleUnit)
+            -> Result<f64> where F: Fn(&str) -> Option<f64> {
+        let eval = |e: &Expr| e.evaluate(lookup, functions, unit);
+        match *self {
+            Expr::Num(n) => Ok(n),
+            Expr::Var(ref var) => lookup(var).ok_or(ExprError::UnboundVariable(var.clone())),
+            Expr::Unary(_, ref a) => Ok(-eval(a)?),
+            Expr::Binary(ref op, ref a, ref b) => op.apply_binary(eval(a)?, eval(b)?),
+            Expr::Call(ref func, ref a) => func.apply_in(eval(a)?, unit),
+            Expr::Apply(ref name, ref args) => {
+                let functions = functions.ok_or(ExprError::UnknownFunction(name.clone()))?;
+                let args = args.iter().map(eval).collect::<Result<Vec<_>>>()?;
+                functions.call(name, &args)
+            }
+            Expr::Cond(ref c, ref a, ref b) => {
+                if eval(c)? != 0f64 { eval(a) } else { eval(b) }
+            }
+        }
+    }
+}
+
+/// Write `e`, in parentheses if it binds less tightly than `min`.
+fn write_operand(f: &mut fmt::Formatter, e: &Expr, min: u8) -> fmt::Result {
+    if e.binding() < min { write!(f, "({})", e) } else { write!(f, "{}", e) }
+}
+
+/// Expressions are written in infix form with spaces around binary operators
+/// and only the parentheses needed to parse back to the same tree. Since
+/// operators of equal precedence group to the right, `(1 - 2) - 3` keeps its
+/// parentheses while `1 - (2 - 3)` loses them.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Expr::Num(n) => write!(f, "{}", n),
+            Expr::Var(ref var) => write!(f, "{}", var),
+            Expr::Unary(ref op, ref a) => {
+                write!(f, "{}", op.symbol())?;
+                write_operand(f, a, self.binding())
+            }
+            Expr::Binary(ref op, ref a, ref b) => {
+                write_operand(f, a, self.binding() + 1)?;
+                write!(f, " {} ", op.symbol())?;
+                write_operand(f, b, self.binding())
+            }
+            Expr::Call(ref func, ref a) => write!(f, "{}({})", func.name(), a),
+            Expr::Apply(ref name, ref args) => {
+                write!(f, "{}(", name)?;
+                for (i, a) in args.iter().enumerate() {
+                    write!(f, "{}{}", if i > 0 { ", " } else { "" }, a)?;
+                }
+                write!(f, ")")
+            }
+            Expr::Cond(ref c, ref a, ref b) => {
+                write_operand(f, c, 1)?;
+                write!(f, " ? {} : {}", a, b)
+            }
+        }
+    }
+}
+
+/// Measures of the size and make-up of an expression tree.
+#[derive(Debug,Clone,PartialEq,Default)]
+pub struct Metrics {
+    /// The number of numbers, variables, operations, calls and conditionals.
+    pub nodes: usize,
+    /// The number of nodes on the longest path from the root to a leaf.
+    pub depth: usize,
+    /// How many times each operator occurs, negation included.
+    pub operators: HashMap<Op, usize>,
+    /// The number of numbers. Named constants like `pi` count as variables.
+    pub constants: usize,
+}
+
+/// Return the metrics of `e`.
+pub fn metrics(e: &Expr) -> Metrics {
+    let mut measure = Measure { metrics: Metrics::default(), level: 0 };
+    measure.visit_expr(e);
+    measure.metrics
+}
+
+/// Adds up the metrics of the nodes it visits, which are `level` deep.
+struct Measure {
+    metrics: Metrics,
+    level: usize,
+}
+
+impl Measure {
+    fn count(&mut self, op: &Op) { *self.metrics.operators.entry(op.clone()).or_insert(0) += 1 }
+}
+
+impl ExprVisitor for Measure {
+    fn visit_expr(&mut self, e: &Expr) {
+        self.level += 1;
+        self.metrics.nodes += 1;
+        self.metrics.depth = self.metrics.depth.max(self.level);
+        walk_expr(self, e);
+        self.level -= 1;
+    }
+
+    fn visit_num(&mut self, _n: f64) { self.metrics.constants += 1 }
+
+    fn visit_unary(&mut self, op: &Op, a: &Expr) {
+        self.count(op);
+        self.visit_expr(a);
+    }
+
+    fn visit_binary(&mut self, op: &Op, a: &Expr, b: &Expr) {
+        self.count(op);
+        self.visit_expr(a);
+        self.visit_expr(b);
+    }
+}
+
+/// Parse `s` into an expression tree.
+pub fn parse(s: &str) -> Result<Expr> {
+    from_postfix(postfix(s)?)
+}
+
+/// Parse `s` into an expression tree, where the functions in `functions`
+/// may be called besides the built-in ones.
+pub fn parse_with(s: &str, functions: &Registry) -> Result<Expr> {
+    from_postfix(postfix_tokens(Tokens::with_registry(s, functions), None)?)
+}
+
+/// Build the expression tree of the tokens `post`, in postfix order.
+pub(crate) fn from_postfix(post: Vec<Tok>) -> Result<Expr> {
+    check_branches(&post)?;
+    let mut stack = Vec::new();
+    let pop = |stack: &mut Vec<Expr>| stack.pop().ok_or(ExprError::StackUnderflow);
+    for token in post {
+        let e = match token {
+            Tok::Num(n) => Expr::Num(n),
+            Tok::Var(var) => Expr::Var(var),
+            Tok::Op(Op::UnNeg) => Expr::Unary(Op::UnNeg, Box::new(pop(&mut stack)?)),
+            Tok::Op(op) => {
+                let b = pop(&mut stack)?;
+                let a = pop(&mut stack)?;
+                Expr::Binary(op, Box::new(a), Box::new(b))
+            }
+            Tok::Func(func) => Expr::Call(func, Box::new(pop(&mut stack)?)),
+            Tok::Call(name, n) => {
+                let args = stack.split_off(stack.len().checked_sub(n)
+                                                 .ok_or(ExprError::StackUnderflow)?);
+                Expr::Apply(name, args)
+            }
+            Tok::EndIf => {
+                let b = pop(&mut stack)?;
+                let a = pop(&mut stack)?;
+                let c = pop(&mut stack)?;
+                Expr::Cond(Box::new(c), Box::new(a), Box::new(b))
+            }
+            // The condition and the branches are already in place.
+            _ => continue,
+        };
+        stack.push(e);
+    }
+    match stack.len() {
+        0 => Err(ExprError::Empty),
+        1 => Ok(stack.pop().unwrap()),
+        _ => Err(ExprError::MissingOperator),
+    }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn num(n: f64) -> Box<Expr> { Box::new(Expr::Num(n)) }
+
+    #[test]
+    pub fn test_parse() {
+        assert_eq!(parse("1+2*3"),
+                   Ok(Expr::Binary(Op::Add, num(1f64),
+                                   Box::new(Expr::Binary(Op::Mul, num(2f64), num(3f64))))));
+        assert_eq!(parse("-sqrt(x)"),
+                   Ok(Expr::Unary(Op::UnNeg,
+                                  Box::new(Expr::Call(Func::Sqrt,
+                                                      Box::new(Expr::Var("x".to_string())))))));
+        assert_eq!(parse("1 ? 2 : 3"), Ok(Expr::Cond(num(1f64), num(2f64), num(3f64))));
+        assert_eq!(parse("(((7)))"), Ok(Expr::Num(7f64)));
+        assert!(parse("1+").is_err());
+        assert!(parse("1 2").is_err());
+        assert!(parse("1 ? : 2 3").is_err());
+        assert!(parse("").is_err());
+    }
+
+    #[test]
+    pub fn test_display() {
+        let pretty = |s: &str| parse(s).unwrap().to_string();
+        assert_eq!(pretty("1+2*3"), "1 + 2 * 3");
+        assert_eq!(pretty("((1+2))*3"), "(1 + 2) * 3");
+        assert_eq!(pretty("(1-2)-3"), "(1 - 2) - 3");
+        assert_eq!(pretty("1-(2-3)"), "1 - 2 - 3");
+        assert_eq!(pretty("2**3**2"), "2 ** 3 ** 2");
+        assert_eq!(pretty("-(x+1)*-x"), "-(x + 1) * -x");
+        assert_eq!(pretty("sqrt((x))"), "sqrt(x)");
+        assert_eq!(pretty("(1?2:3)+(x<1?4:5)"), "(1 ? 2 : 3) + (x < 1 ? 4 : 5)");
+        assert_eq!(pretty("(1?2:3)?4:5"), "(1 ? 2 : 3) ? 4 : 5");
+        assert_eq!(pretty("007*0.50"), "7 * 0.5");
+        let negative = Expr::Binary(Op::Exp, num(-2f64), num(2f64));
+        assert_eq!(negative.to_string(), "-2 ** 2");
+        assert_eq!(parse(&negative.to_string()).unwrap().eval(&|_: &str| None), Ok(4f64));
+        for s in &["1/2/3", "(1/2)/3", "1-2*3+4", "(1+2)**(3-4)", "1 ? 0 ? 1 : 2 : 3", "--x"] {
+            assert_eq!(parse(&pretty(s)), parse(s));
+        }
+    }
+
+    #[test]
+    pub fn test_metrics() {
+        let m = metrics(&parse("-x * (2 + x) * 3").unwrap());
+        assert_eq!((m.nodes, m.depth, m.constants), (8, 4, 2));
+        assert_eq!(m.operators, vec![(Op::Mul, 2), (Op::Add, 1), (Op::UnNeg, 1)]
+                                    .into_iter().collect());
+        let m = metrics(&parse("x < 1 ? sqrt(pi) : 1").unwrap());
+        assert_eq!((m.nodes, m.depth, m.constants), (7, 3, 2));
+        assert_eq!(m.operators.len(), 1);
+        assert_eq!(metrics(&parse("42").unwrap()),
+                   Metrics { nodes: 1, depth: 1, operators: HashMap::new(), constants: 1 });
+    }
+
+    #[test]
+    pub fn test_eval() {
+        let e = parse("x < 0 ? -x : x*2").unwrap();
+        let at = |x: f64| move |var: &str| if var == "x" { Some(x) } else { None };
+        assert_eq!(e.eval(&at(-3f64)), Ok(3f64));
+        assert_eq!(e.eval(&at(3f64)), Ok(6f64));
+        assert!(e.eval(&|_: &str| None).is_err());
+    }
+}
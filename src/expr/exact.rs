@@ -0,0 +1,269 @@
+use num::{BigInt, BigRational, One, Signed, ToPrimitive, Zero};
+use num::pow;
+use super::{builtin_constant, ExprError, Func, Op, Result};
+use super::ast::{self, Expr};
+
+/// The largest exponent `**` takes in exact arithmetic, beyond which numbers
+/// grow too long to be worth computing.
+const MAX_EXPONENT: u32 = 4096;
+
+/// Evaluate `s` exactly in rational arithmetic, so that, e.g., `(1/3)*3` is
+/// 1.
+/// Decimal literals stand for the decimal fraction they spell, while the
+/// constants `pi` and `e` are taken at their floating-point values. It is an
+/// error to divide by zero or to use an operation without a rational result
+/// in general, like `sqrt`, or `**` with a fractional exponent.
+pub fn eval_exact(s: &str) -> Result<BigRational> {
+    rational(&ast::parse(s)?, &builtin_constant)
+}
+
+/// Evaluate `s` in arbitrary precision, for values too large for an `f64`
+/// to hold exactly, like `2**200`. Evaluation is exact, as in `eval_exact`,
+/// and it is an error if the result is not a whole number. Number literals
+/// are read as `f64`s, so write numbers beyond 2**53 as expressions.
+pub fn eval_big(s: &str) -> Result<BigInt> {
+    let v = eval_exact(s)?;
+    if v.is_integer() { Ok(v.to_integer()) } else { Err(ExprError::NotWhole(v.to_string())) }
+}
+
+/// Evaluate `s` in 64-bit integer arithmetic, for puzzles over whole
+/// numbers. Every number must be whole, and, rather than round or wrap, it
+/// is an error for an operation to overflow, or to have no whole result, like
+/// `7/2`, `2**-1` or `sqrt(2)`. Comparisons and conditionals work as in
+/// `eval`.
+pub fn eval_i64(s: &str) -> Result<i64> {
+    integer(&ast::parse(s)?, &builtin_constant)
+}
+
+/// Return the floating-point value closest to `r`, or so.
+pub fn to_f64(r: &BigRational) -> f64 {
+    match (r.numer().to_f64(), r.denom().to_f64()) {
+        (Some(n), Some(d)) => n / d,
+        _ => f64::NAN,
+    }
+}
+
+/// Return the decimal fraction that `n` prints as, e.g., 1/10 for `0.1`, or
+/// None if `n` is infinite or NaN.
+pub fn decimal(n: f64) -> Option<BigRational> {
+    if !n.is_finite() {
+        return None;
+    }
+    let digits = n.to_string();
+    let (whole, fraction) = match digits.find('.') {
+        Some(i) => (&digits[..i], &digits[i + 1..]),
+        None => (&digits[..], ""),
+    };
+    let numer = format!("{}{}", whole, fraction).parse::<BigInt>().ok()?;
+    let denom = pow(BigInt::from(10), fraction.len());
+    Some(BigRational::new(numer, denom))
+}
+
+fn truth(b: bool) -> BigRational {
+    if b { BigRational::one() } else { BigRational::zero() }
+}
+
+/// Evaluate `e` exactly, looking up the value of each variable with
+/// `lookup`.
+pub fn rational<F: Fn(&str) -> Option<f64>>(e: &Expr, lookup: &F) -> Result<BigRational> {
+    match *e {
+        Expr::Num(n) => decimal(n).ok_or(ExprError::Inexact(n.to_string())),
+        Expr::Var(ref var) => {
+            let v = lookup(var).ok_or(ExprError::UnboundVariable(var.clone()))?;
+            decimal(v).ok_or(ExprError::Inexact(var.clone()))
+        }
+        Expr::Unary(_, ref a) => Ok(-rational(a, lookup)?),
+        Expr::Binary(ref op, ref a, ref b) => {
+            let (a, b) = (rational(a, lookup)?, rational(b, lookup)?);
+            match *op {
+                Op::Add => Ok(a + b),
+                Op::Sub => Ok(a - b),
+                Op::Mul => Ok(a * b),
+                Op::Div | Op::Mod if b.is_zero() => Err(ExprError::DivisionByZero),
+                Op::Div => Ok(a / b),
+                Op::Mod => Ok(a % b),
+                Op::Exp => power(a, b),
+                Op::Lt => Ok(truth(a < b)),
+                Op::Gt => Ok(truth(a > b)),
+                Op::Le => Ok(truth(a <= b)),
+                Op::Ge => Ok(truth(a >= b)),
+                Op::Eq => Ok(truth(a == b)),
+                Op::Ne => Ok(truth(a != b)),
+                Op::And => Ok(truth(!a.is_zero() && !b.is_zero())),
+                Op::Or => Ok(truth(!a.is_zero() || !b.is_zero())),
+                _ if op.is_bitwise() => {
+                    let v = op.apply_bitwise(integer_part(&a)?, integer_part(&b)?)?;
+                    Ok(BigRational::from_integer(BigInt::from(v)))
+                }
+                _ => Err(ExprError::MisusedOperator(op.symbol().to_string())),
+            }
+        }
+        Expr::Call(Func::Abs, ref a) => Ok(rational(a, lookup)?.abs()),
+        Expr::Call(ref func, _) => Err(ExprError::Inexact(func.name().to_string())),
+        Expr::Apply(ref name, _) => Err(ExprError::Inexact(name.clone())),
+        Expr::Cond(ref c, ref a, ref b) => {
+            if !rational(c, lookup)?.is_zero() { rational(a, lookup) } else { rational(b, lookup) }
+        }
+    }
+}
+
+/// Return `n` as an `i64`, or an error if it is not a whole number that
+/// fits one.
+fn whole(n: f64, what: &str) -> Result<i64> {
+    if n.fract() != 0f64 || n.is_nan() {
+        Err(ExprError::NotWhole(what.to_string()))
+    } else if n.abs() < i64::MAX as f64 {
+        Ok(n as i64)
+    } else {
+        Err(ExprError::Overflow(what.to_string()))
+    }
+}
+
+/// Evaluate `e` in checked 64-bit integer arithmetic, looking up the value
+/// of each variable with `lookup`.
+pub fn integer<F: Fn(&str) -> Option<f64>>(e: &Expr, lookup: &F) -> Result<i64> {
+    match *e {
+        Expr::Num(n) => whole(n, &n.to_string()),
+        Expr::Var(ref var) => {
+            whole(lookup(var).ok_or(ExprError::UnboundVariable(var.clone()))?, var)
+        }
+        Expr::Unary(_, ref a) => {
+            let a = integer(a, lookup)?;
+            a.checked_neg().ok_or_else(|| ExprError::Overflow(format!("-({})", a)))
+        }
+        Expr::Binary(ref op, ref a, ref b) => {
+            let (a, b) = (integer(a, lookup)?, integer(b, lookup)?);
+            let what = || format!("{} {} {}", a, op.symbol(), b);
+            let checked = |v: Option<i64>| v.ok_or_else(|| ExprError::Overflow(what()));
+            match *op {
+                Op::Add => checked(a.checked_add(b)),
+                Op::Sub => checked(a.checked_sub(b)),
+                Op::Mul => checked(a.checked_mul(b)),
+                Op::Div | Op::Mod if b == 0 => Err(ExprError::DivisionByZero),
+                Op::Div if a.checked_rem(b).is_some_and(|r| r != 0) => {
+                    Err(ExprError::Inexact(what()))
+                }
+                Op::Div => checked(a.checked_div(b)),
+                Op::Mod => checked(a.checked_rem(b)),
+                Op::Exp => match (a, b) {
+                    (0, _) if b < 0 => Err(ExprError::DivisionByZero),
+                    (1, _) => Ok(1),
+                    (-1, _) => Ok(if b % 2 == 0 { 1 } else { -1 }),
+                    _ if b < 0 => Err(ExprError::Inexact(what())),
+                    _ => checked(b.to_u32().and_then(|b| a.checked_pow(b))),
+                },
+                Op::Lt => Ok((a < b) as i64),
+                Op::Gt => Ok((a > b) as i64),
+                Op::Le => Ok((a <= b) as i64),
+                Op::Ge => Ok((a >= b) as i64),
+                Op::Eq => Ok((a == b) as i64),
+                Op::Ne => Ok((a != b) as i64),
+                Op::And => Ok((a != 0 && b != 0) as i64),
+                Op::Or => Ok((a != 0 || b != 0) as i64),
+                // Bits shifted out to the left are an overflow.
+                Op::Shl => {
+                    let v = op.apply_bitwise(a, b)?;
+                    if v >> b == a { Ok(v) } else { Err(ExprError::Overflow(what())) }
+                }
+                _ if op.is_bitwise() => op.apply_bitwise(a, b),
+                _ => Err(ExprError::MisusedOperator(op.symbol().to_string())),
+            }
+        }
+        Expr::Call(Func::Abs, ref a) => {
+            let a = integer(a, lookup)?;
+            a.checked_abs().ok_or_else(|| ExprError::Overflow(format!("abs({})", a)))
+        }
+        Expr::Call(ref func, _) => Err(ExprError::Inexact(func.name().to_string())),
+        Expr::Apply(ref name, _) => Err(ExprError::Inexact(name.clone())),
+        Expr::Cond(ref c, ref a, ref b) => {
+            if integer(c, lookup)? != 0 { integer(a, lookup) } else { integer(b, lookup) }
+        }
+    }
+}
+
+/// Return the integer part of `a`, or an error if it does not fit an `i64`.
+fn integer_part(a: &BigRational) -> Result<i64> {
+    a.trunc().to_integer().to_i64().ok_or(ExprError::TooLarge)
+}
+
+/// Return `a` to the power of the whole number `b`.
+fn power(a: BigRational, b: BigRational) -> Result<BigRational> {
+    if !b.is_integer() {
+        return Err(ExprError::Inexact(format!("{}**{}", a, b)));
+    }
+    let n = b.to_integer().abs().to_u32().filter(|&n| n <= MAX_EXPONENT)
+                          .ok_or(ExprError::TooLarge)?;
+    if b.is_negative() && a.is_zero() {
+        return Err(ExprError::DivisionByZero);
+    }
+    let p = pow(a, n as usize);
+    Ok(if b.is_negative() { p.recip() } else { p })
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn ratio(n: i64, d: i64) -> BigRational {
+        BigRational::new(BigInt::from(n), BigInt::from(d))
+    }
+
+    #[test]
+    pub fn test_eval_exact() {
+        assert_eq!(eval_exact("(1/3)*3"), Ok(ratio(1, 1)));
+        assert_eq!(eval_exact("1/3*3"), Ok(ratio(1, 9)));
+        assert_eq!(eval_exact("0.1*3"), Ok(ratio(3, 10)));
+        assert_eq!(eval_exact("0.1*3 == 0.3"), Ok(ratio(1, 1)));
+        assert_eq!(eval_exact("2**-2 + 7%4"), Ok(ratio(13, 4)));
+        assert_eq!(eval_exact("-abs(-1/2)"), Ok(ratio(-1, 2)));
+        assert_eq!(eval_exact("1 > 2 ? sqrt(2) : 5"), Ok(ratio(5, 1)));
+        assert_eq!(to_f64(&eval_exact("2**100").unwrap()), 2f64.powi(100));
+        assert_eq!(eval_exact("(7/2 | 8) << 2"), Ok(ratio(44, 1)));
+        assert!(eval_exact("2**70 ^ 1").is_err());
+        assert!(eval_exact("1/(3-3)").is_err());
+        assert!(eval_exact("4**0.5").is_err());
+        assert!(eval_exact("sqrt(4)").is_err());
+        assert!(eval_exact("2**100000").is_err());
+        assert!(eval_exact("x").is_err());
+        assert_eq!(decimal(2.5e-3), Some(ratio(1, 400)));
+        assert_eq!(decimal(-12f64), Some(ratio(-12, 1)));
+        assert_eq!(decimal(f64::NAN), None);
+    }
+
+    #[test]
+    pub fn test_eval_i64() {
+        assert_eq!(eval_i64("6*7 - 2**3 % 5"), Ok(39));
+        assert_eq!(eval_i64("8/2/2"), Ok(8));
+        assert_eq!(eval_i64("(8/2)/2"), Ok(2));
+        assert_eq!(eval_i64("-7 % 3 + abs(-4) + (2 < 3 ? 1 : sqrt(2))"), Ok(4));
+        assert_eq!(eval_i64("(-1)**-3 + 1**-2"), Ok(0));
+        assert_eq!(eval_i64("1 << 62 | 5 & 3"), Ok((1 << 62) | 1));
+        assert_eq!(eval_i64("7/2"), Err(ExprError::Inexact("7 / 2".to_string())));
+        assert_eq!(eval_i64("2**-1"), Err(ExprError::Inexact("2 ** -1".to_string())));
+        assert_eq!(eval_i64("2.5*2"), Err(ExprError::NotWhole("2.5".to_string())));
+        assert_eq!(eval_i64("7/(3-3)"), Err(ExprError::DivisionByZero));
+        assert_eq!(eval_i64("sqrt(4)"), Err(ExprError::Inexact("sqrt".to_string())));
+        assert_eq!(eval_i64("x"), Err(ExprError::UnboundVariable("x".to_string())));
+
+        let overflow = |what: &str| Err(ExprError::Overflow(what.to_string()));
+        assert_eq!(eval_i64("3**40"), overflow("3 ** 40"));
+        assert_eq!(eval_i64("2**62 + 2**62"),
+                   overflow("4611686018427387904 + 4611686018427387904"));
+        assert_eq!(eval_i64("-2**63 / -1"), overflow("-9223372036854775808 / -1"));
+        assert_eq!(eval_i64("-(-2**63)"), overflow("-(-9223372036854775808)"));
+        assert_eq!(eval_i64("3 << 62"), overflow("3 << 62"));
+        assert_eq!(eval_i64("1e19"), overflow("10000000000000000000"));
+        assert_eq!(eval_i64("2**64**0"), Ok(2));
+    }
+
+    #[test]
+    pub fn test_eval_big() {
+        let big = "1606938044258990275541962092341162602522202993782792835301376";
+        assert_eq!(eval_big("2**200"), Ok(big.parse::<BigInt>().unwrap()));
+        assert_eq!(eval_big("2**200+1").map(|v| v.to_string()),
+                   Ok(big.replace("376", "377")));
+        assert_eq!(eval_big("(2**200/3)*3"), eval_big("2**200"));
+        assert_eq!(eval_big("7/2"), Err(ExprError::NotWhole("7/2".to_string())));
+    }
+}
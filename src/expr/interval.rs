@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use super::{builtin_constant, ExprError, Func, Op, Result};
+use super::ast::{self, Expr};
+
+/// A closed interval of numbers, possibly unbounded.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Interval {
+    pub lo: f64,
+    pub hi: f64,
+}
+
+impl Interval {
+    /// Return the interval `[lo, hi]`, whichever way round they come.
+    pub fn new(lo: f64, hi: f64) -> Interval { Interval { lo: lo.min(hi), hi: lo.max(hi) } }
+
+    /// Return the interval holding `v` alone.
+    pub fn point(v: f64) -> Interval { Interval { lo: v, hi: v } }
+
+    /// Return the interval of all numbers.
+    pub fn entire() -> Interval { Interval { lo: f64::NEG_INFINITY, hi: f64::INFINITY } }
+
+    pub fn contains(&self, v: f64) -> bool { self.lo <= v && v <= self.hi }
+
+    /// Return the smallest interval holding all of `values`.
+    fn hull(values: &[f64]) -> Interval {
+        Interval {
+            lo: values.iter().cloned().fold(f64::INFINITY, f64::min),
+            hi: values.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+        }
+    }
+
+    /// Return the interval of truth values: 1 if `always`, 0 if `never`,
+    /// either otherwise.
+    fn truth(always: bool, never: bool) -> Interval {
+        match (always, never) {
+            (true, _) => Interval::point(1f64),
+            (_, true) => Interval::point(0f64),
+            _ => Interval::new(0f64, 1f64),
+        }
+    }
+
+    /// Return true if every number in the interval is nonzero, i.e., true.
+    fn is_true(&self) -> bool { !self.contains(0f64) }
+
+    /// Return true if the interval holds 0 alone, i.e., false.
+    fn is_false(&self) -> bool { *self == Interval::point(0f64) }
+}
+
+/// Evaluate `s` in interval arithmetic, with each variable ranging over its
+/// interval in `env`, and return an interval holding every value `s` can
+/// take. The interval may be wider than needed, e.g., for `x-x`, which is
+/// only ever 0. The constants `pi` and `e` stand for themselves.
+pub fn eval_interval(s: &str, env: &HashMap<String, Interval>) -> Result<Interval> {
+    interval(&ast::parse(s)?, env)
+}
+
+fn interval(e: &Expr, env: &HashMap<String, Interval>) -> Result<Interval> {
+    match *e {
+        Expr::Num(n) => Ok(Interval::point(n)),
+        Expr::Var(ref var) => env.get(var).cloned()
+                                 .or_else(|| builtin_constant(var).map(Interval::point))
+                                 .ok_or(ExprError::UnboundVariable(var.clone())),
+        Expr::Unary(_, ref a) => {
+            let a = interval(a, env)?;
+            Ok(Interval::new(-a.hi, -a.lo))
+        }
+        Expr::Binary(ref op, ref a, ref b) => Ok(binary(op, interval(a, env)?, interval(b, env)?)),
+        Expr::Call(ref func, ref a) => Ok(call(*func, interval(a, env)?)),
+        // Nothing is known of what a registered function returns.
+        Expr::Apply(..) => Ok(Interval::entire()),
+        Expr::Cond(ref c, ref a, ref b) => {
+            let c = interval(c, env)?;
+            if c.is_true() {
+                interval(a, env)
+            } else if c.is_false() {
+                interval(b, env)
+            } else {
+                let (a, b) = (interval(a, env)?, interval(b, env)?);
+                Ok(Interval::hull(&[a.lo, a.hi, b.lo, b.hi]))
+            }
+        }
+    }
+}
+
+fn binary(op: &Op, a: Interval, b: Interval) -> Interval {
+    match *op {
+        Op::Add => Interval::new(a.lo + b.lo, a.hi + b.hi),
+        Op::Sub => Interval::new(a.lo - b.hi, a.hi - b.lo),
+        Op::Mul => {
+            let corners = [a.lo * b.lo, a.lo * b.hi, a.hi * b.lo, a.hi * b.hi];
+            // 0 times an infinite bound is NaN, which hull() would drop;
+            // the product could then be anything.
+            if corners.iter().any(|c| c.is_nan()) {
+                Interval::entire()
+            } else {
+                Interval::hull(&corners)
+            }
+        }
+        Op::Div if b.contains(0f64) => Interval::entire(),
+        Op::Div => binary(&Op::Mul, a, Interval::new(1f64 / b.lo, 1f64 / b.hi)),
+        Op::Exp => power(a, b),
+        // The remainder is smaller than the divisor, and has the sign of
+        // the dividend.
+        Op::Mod => {
+            let m = b.lo.abs().max(b.hi.abs());
+            Interval::new(a.lo.max(-m).min(0f64), a.hi.min(m).max(0f64))
+        }
+        Op::Lt => Interval::truth(a.hi < b.lo, a.lo >= b.hi),
+        Op::Gt => Interval::truth(a.lo > b.hi, a.hi <= b.lo),
+        Op::Le => Interval::truth(a.hi <= b.lo, a.lo > b.hi),
+        Op::Ge => Interval::truth(a.lo >= b.hi, a.hi < b.lo),
+        Op::Eq => Interval::truth(a == b && a.lo == a.hi, a.hi < b.lo || b.hi < a.lo),
+        Op::Ne => Interval::truth(a.hi < b.lo || b.hi < a.lo, a == b && a.lo == a.hi),
+        Op::And => Interval::truth(a.is_true() && b.is_true(), a.is_false() || b.is_false()),
+        Op::Or => Interval::truth(a.is_true() || b.is_true(), a.is_false() && b.is_false()),
+        // Bitwise operators and shifts are only evaluated on points.
+        _ if a.lo == a.hi && b.lo == b.hi => {
+            op.apply_binary(a.lo, b.lo).map_or(Interval::entire(), Interval::point)
+        }
+        _ => Interval::entire(),
+    }
+}
+
+/// Return the interval of `a ** b`. A power of a nonnegative base is
+/// monotonic in both, so its extremes are at the corners; a negative base
+/// only has real powers for whole exponents.
+fn power(a: Interval, b: Interval) -> Interval {
+    let corners = |a: Interval| {
+        Interval::hull(&[a.lo.powf(b.lo), a.lo.powf(b.hi), a.hi.powf(b.lo), a.hi.powf(b.hi)])
+    };
+    if a.lo >= 0f64 {
+        return corners(a);
+    }
+    if b.lo != b.hi || b.lo.fract() != 0f64 {
+        return Interval::entire();
+    }
+    let n = b.lo;
+    if n < 0f64 {
+        return binary(&Op::Div, Interval::point(1f64), power(a, Interval::point(-n)));
+    }
+    if n % 2f64 == 0f64 && a.contains(0f64) {
+        Interval::new(0f64, a.lo.abs().max(a.hi).powf(n))
+    } else {
+        Interval::new(a.lo.powf(n), a.hi.powf(n))
+    }
+}
+
+fn call(func: Func, a: Interval) -> Interval {
+    match func {
+        Func::Sqrt => Interval::new(a.lo.max(0f64).sqrt(), a.hi.sqrt()),
+        Func::Log => Interval::new(a.lo.max(0f64).ln(), a.hi.ln()),
+        Func::Abs if a.lo >= 0f64 => a,
+        Func::Abs if a.hi <= 0f64 => Interval::new(-a.hi, -a.lo),
+        Func::Abs => Interval::new(0f64, a.hi.max(-a.lo)),
+        Func::Sin => sine(a),
+        Func::Cos => sine(Interval::new(a.lo + PI / 2f64, a.hi + PI / 2f64)),
+    }
+}
+
+/// Return the interval of `sin` over `a`: the sines at the ends, widened to
+/// 1 or -1 if a peak or a trough lies in between.
+fn sine(a: Interval) -> Interval {
+    if a.hi - a.lo >= 2f64 * PI || !a.lo.is_finite() || !a.hi.is_finite() {
+        return Interval::new(-1f64, 1f64);
+    }
+    // Is there a whole k with lo <= offset + 2*k*pi <= hi?
+    let reaches = |offset: f64| {
+        ((a.lo - offset) / (2f64 * PI)).ceil() <= ((a.hi - offset) / (2f64 * PI)).floor()
+    };
+    let ends = Interval::hull(&[a.lo.sin(), a.hi.sin()]);
+    Interval {
+        lo: if reaches(-PI / 2f64) { -1f64 } else { ends.lo },
+        hi: if reaches(PI / 2f64) { 1f64 } else { ends.hi },
+    }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_eval_interval() {
+        let mut env = HashMap::new();
+        env.insert("x".to_string(), Interval::new(-1f64, 2f64));
+        env.insert("y".to_string(), Interval::new(1f64, 3f64));
+        let eval = |s: &str| eval_interval(s, &env).unwrap();
+        assert_eq!(eval("x+y"), Interval::new(0f64, 5f64));
+        assert_eq!(eval("x-y"), Interval::new(-4f64, 1f64));
+        assert_eq!(eval("x*y"), Interval::new(-3f64, 6f64));
+        assert_eq!(eval("x/y"), Interval::new(-1f64, 2f64));
+        assert_eq!(eval("y/x"), Interval::entire());
+        assert_eq!(eval("0*(y/x)"), Interval::entire());
+        assert_eq!(eval("-x"), Interval::new(-2f64, 1f64));
+        assert_eq!(eval("x**2"), Interval::new(0f64, 4f64));
+        assert_eq!(eval("x**3"), Interval::new(-1f64, 8f64));
+        assert_eq!(eval("y**0.5"), Interval::new(1f64, 3f64.sqrt()));
+        assert_eq!(eval("2**y"), Interval::new(2f64, 8f64));
+        assert_eq!(eval("abs(x)"), Interval::new(0f64, 2f64));
+        assert_eq!(eval("6 & 3"), Interval::point(2f64));
+        assert_eq!(eval("y << 1"), Interval::entire());
+        assert_eq!(eval("sqrt(y+1)"), Interval::new(2f64.sqrt(), 2f64));
+        assert_eq!(eval("sin(x*pi)"), Interval::new(-1f64, 1f64));
+        // x-x is taken to range over [-3, 3].
+        let c = eval("cos(x-x)");
+        assert_eq!(c.hi, 1f64);
+        assert!((c.lo - 3f64.cos()).abs() < 1e-12);
+        assert_eq!(eval("x%y"), Interval::new(-1f64, 2f64));
+        assert_eq!(eval("y>0"), Interval::point(1f64));
+        assert_eq!(eval("x>0"), Interval::new(0f64, 1f64));
+        assert_eq!(eval("y<1 || y>3"), Interval::point(0f64));
+        assert_eq!(eval("y>0 ? x : y"), env["x"]);
+        assert_eq!(eval("x>0 ? x : y"), Interval::new(-1f64, 3f64));
+        assert_eq!(eval("7"), Interval::point(7f64));
+        assert!(eval_interval("z", &env).is_err());
+
+        // Spot-check that the intervals hold the values.
+        let e = ast::parse("x*x-y/(x+2)").unwrap();
+        let bounds = eval("x*x-y/(x+2)");
+        for &(x, y) in &[(-1f64, 1f64), (0f64, 3f64), (2f64, 2f64), (0.5, 1.5)] {
+            let v = e.eval(&|var: &str| if var == "x" { Some(x) } else { Some(y) }).unwrap();
+            assert!(bounds.contains(v), "{} not in {:?}", v, bounds);
+        }
+    }
+
+    #[test]
+    pub fn test_sine() {
+        assert_eq!(sine(Interval::new(0f64, 1f64)), Interval::new(0f64, 1f64.sin()));
+        assert_eq!(sine(Interval::new(0f64, 2f64)).hi, 1f64);
+        assert_eq!(sine(Interval::new(4f64, 5f64)).lo, -1f64);
+    }
+}
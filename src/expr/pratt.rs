@@ -0,0 +1,191 @@
+use super::{Assoc, ExprError, Op, PrecedenceTable, Result, Tok, Tokens};
+
+/// A Pratt parser, which reads an expression by recursive descent, and
+/// writes out its tokens in postfix order.
+struct Parser<'a> {
+    tokens: Vec<(Tok, usize)>,
+    /// The index of the next token.
+    i: usize,
+    /// The character offset of the end of the expression.
+    end: usize,
+    table: Option<&'a PrecedenceTable>,
+    post: Vec<Tok>,
+}
+
+/// Return `tokens` in postfix order, as `postfix` does, with the built-in
+/// precedences unless a `table` is given.
+pub(crate) fn parse(tokens: Tokens, table: Option<&PrecedenceTable>) -> Result<Vec<Tok>> {
+    let end = tokens.s.chars().count();
+    let tokens = tokens.collect::<Result<Vec<_>>>()?;
+    if tokens.is_empty() {
+        return Err(ExprError::Empty);
+    }
+    let mut parser = Parser { tokens, i: 0, end, table, post: Vec::new() };
+    parser.conditional()?;
+    match parser.next() {
+        None => Ok(parser.post),
+        Some((tok, pos)) => Err(unexpected(tok, pos)),
+    }
+}
+
+/// Return the error for `tok`, at `pos`, where an operator or the end of an
+/// expression should have been.
+fn unexpected(tok: Tok, pos: usize) -> ExprError {
+    match tok {
+        Tok::RParen | Tok::LParen => ExprError::UnbalancedParen,
+        Tok::Comma => ExprError::MisplacedComma,
+        Tok::Then | Tok::Else | Tok::EndIf => ExprError::MalformedConditional,
+        _ => ExprError::ExpectedOperator { pos },
+    }
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Tok> { self.tokens.get(self.i).map(|(tok, _)| tok) }
+
+    fn next(&mut self) -> Option<(Tok, usize)> {
+        let token = self.tokens.get(self.i).cloned();
+        self.i += 1;
+        token
+    }
+
+    /// Return the character offset of the next token.
+    fn pos(&self) -> usize { self.tokens.get(self.i).map_or(self.end, |&(_, pos)| pos) }
+
+    fn level(&self, op: &Op) -> (u8, Assoc) {
+        self.table.map_or((op.precedence(), Assoc::Right), |t| t.level(op))
+    }
+
+    /// Read an expression, which may be a conditional. A conditional binds
+    /// looser than any operator, and groups to the right: `c ? a : d ? b :
+    /// e` is `c ? a : (d ? b : e)`. It becomes `c ? a : b EndIf`, so that
+    /// the end of the `else` branch is marked.
+    fn conditional(&mut self) -> Result<()> {
+        self.binary(None)?;
+        if self.peek() != Some(&Tok::Then) {
+            return Ok(());
+        }
+        self.i += 1;
+        self.post.push(Tok::Then);
+        self.conditional()?;
+        if self.peek() != Some(&Tok::Else) {
+            return Err(ExprError::MalformedConditional);
+        }
+        self.i += 1;
+        self.post.push(Tok::Else);
+        self.conditional()?;
+        self.post.push(Tok::EndIf);
+        Ok(())
+    }
+
+    /// Read an operand, and the operators after it that bind tighter than
+    /// the operator of precedence `outer` whose operand it is, if any, with
+    /// their operands. An operator of the same precedence as `outer` is
+    /// taken if it groups to the right, as by default, so that `2**3**2` is
+    /// `2**(3**2)`.
+    fn binary(&mut self, outer: Option<u8>) -> Result<()> {
+        self.operand()?;
+        loop {
+            let op = match self.peek() {
+                Some(Tok::Op(op)) => op.clone(),
+                Some(&Tok::Num(_)) | Some(&Tok::Var(_)) | Some(&Tok::LParen) |
+                Some(&Tok::Func(_)) | Some(&Tok::Call(..)) => {
+                    return Err(ExprError::ExpectedOperator { pos: self.pos() });
+                }
+                _ => return Ok(()),
+            };
+            let (precedence, assoc) = self.level(&op);
+            let tighter = |outer| {
+                precedence > outer || (precedence == outer && assoc == Assoc::Right)
+            };
+            if !outer.is_none_or(tighter) {
+                return Ok(());
+            }
+            self.i += 1;
+            self.binary(Some(precedence))?;
+            self.post.push(Tok::Op(op));
+        }
+    }
+
+    /// Read a number, a variable, a negation, a call or a parenthesized
+    /// expression, and any percent signs after it.
+    fn operand(&mut self) -> Result<()> {
+        let pos = self.pos();
+        match self.next() {
+            Some((Tok::Num(n), _)) => self.post.push(Tok::Num(n)),
+            Some((Tok::Var(var), _)) => self.post.push(Tok::Var(var)),
+            // A negation takes the operators that bind tighter than it does
+            // along with its operand.
+            Some((Tok::Op(Op::UnNeg), _)) => {
+                let (precedence, _) = self.level(&Op::UnNeg);
+                self.binary(Some(precedence))?;
+                self.post.push(Tok::Op(Op::UnNeg));
+            }
+            Some((Tok::LParen, _)) => {
+                self.conditional()?;
+                self.close()?;
+            }
+            Some((Tok::Func(func), _)) => {
+                self.open()?;
+                self.conditional()?;
+                self.close()?;
+                self.post.push(Tok::Func(func));
+            }
+            // A call to a registered function comes after its arguments,
+            // with their number.
+            Some((Tok::Call(name, _), _)) => {
+                self.open()?;
+                let n = self.arguments()?;
+                self.post.push(Tok::Call(name, n));
+            }
+            Some((Tok::Comma, _)) => return Err(ExprError::MisplacedComma),
+            _ => return Err(ExprError::ExpectedOperand { pos }),
+        }
+        // A percent sign binds tighter than any operator, so that `2**50%`
+        // is `2**(50/100)` and `-50%` is `-(50/100)`.
+        while self.peek() == Some(&Tok::Percent) {
+            self.i += 1;
+            self.post.extend(vec![Tok::Num(100f64), Tok::Op(Op::Div)]);
+        }
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<()> {
+        match self.next() {
+            Some((Tok::LParen, _)) => Ok(()),
+            _ => Err(ExprError::UnbalancedParen),
+        }
+    }
+
+    /// Read a closing parenthesis.
+    fn close(&mut self) -> Result<()> {
+        match self.next() {
+            Some((Tok::RParen, _)) => Ok(()),
+            None => Err(ExprError::UnbalancedParen),
+            Some((tok, pos)) => Err(unexpected(tok, pos)),
+        }
+    }
+
+    /// Read the comma-separated arguments of a call, and the closing
+    /// parenthesis, and return how many there were.
+    fn arguments(&mut self) -> Result<usize> {
+        if self.peek() == Some(&Tok::RParen) {
+            self.i += 1;
+            return Ok(0);
+        }
+        let mut n = 0;
+        loop {
+            // A comma needs an argument on either side.
+            if matches!(self.peek(), Some(&Tok::Comma) | Some(&Tok::RParen)) {
+                return Err(ExprError::MisplacedComma);
+            }
+            self.conditional()?;
+            n += 1;
+            match self.next() {
+                Some((Tok::Comma, _)) => {}
+                Some((Tok::RParen, _)) => return Ok(n),
+                None => return Err(ExprError::UnbalancedParen),
+                Some((tok, pos)) => return Err(unexpected(tok, pos)),
+            }
+        }
+    }
+}
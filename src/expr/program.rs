@@ -0,0 +1,179 @@
+use rayon::prelude::*;
+use super::{builtin_constant, ExprError, Func, Op, Result};
+use super::ast::{self, Expr};
+
+/// An instruction of a `Program`, which works on a stack of values.
+#[derive(Debug,Clone,PartialEq)]
+enum Instr {
+    Num(f64),
+    /// Push the value of the variable in this slot.
+    Load(usize),
+    Neg,
+    Binary(Op),
+    Call(Func),
+    /// Pop a condition, and jump to this instruction if it is false.
+    JumpUnless(usize),
+    Jump(usize),
+}
+
+/// An expression compiled to postfix bytecode, to be evaluated many times
+/// over, e.g., at each point of a dataset, without being parsed again.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Program {
+    code: Vec<Instr>,
+    variables: Vec<String>,
+    /// The most values on the stack at once.
+    depth: usize,
+}
+
+/// Compile `s` into a program. The constants `pi` and `e` are folded in, and
+/// every other variable gets a slot, in the order of first appearance.
+pub fn compile(s: &str) -> Result<Program> {
+    let mut program = Program { code: Vec::new(), variables: Vec::new(), depth: 0 };
+    program.emit(&ast::parse(s)?, 0)?;
+    Ok(program)
+}
+
+impl Program {
+    /// Return the names of the variables, in the order in which `exec`
+    /// takes their values.
+    pub fn variables(&self) -> &[String] { &self.variables }
+
+    /// Append the code for `e`, to be run with `depth` values on the stack.
+    fn emit(&mut self, e: &Expr, depth: usize) -> Result<()> {
+        self.depth = self.depth.max(depth + 1);
+        match *e {
+            Expr::Num(n) => self.code.push(Instr::Num(n)),
+            Expr::Var(ref var) => {
+                let instr = match self.variables.iter().position(|v| v == var) {
+                    Some(slot) => Instr::Load(slot),
+                    None => match builtin_constant(var) {
+                        Some(v) => Instr::Num(v),
+                        None => {
+                            self.variables.push(var.clone());
+                            Instr::Load(self.variables.len() - 1)
+                        }
+                    },
+                };
+                self.code.push(instr);
+            }
+            Expr::Unary(_, ref a) => {
+                self.emit(a, depth)?;
+                self.code.push(Instr::Neg);
+            }
+            Expr::Binary(ref op, ref a, ref b) => {
+                self.emit(a, depth)?;
+                self.emit(b, depth + 1)?;
+                self.code.push(Instr::Binary(op.clone()));
+            }
+            Expr::Call(func, ref a) => {
+                self.emit(a, depth)?;
+                self.code.push(Instr::Call(func));
+            }
+            Expr::Apply(ref name, _) => return Err(ExprError::UnknownFunction(name.clone())),
+            // The jumps are patched once the branches are in place.
+            Expr::Cond(ref c, ref a, ref b) => {
+                self.emit(c, depth)?;
+                let unless = self.code.len();
+                self.code.push(Instr::JumpUnless(0));
+                self.emit(a, depth)?;
+                let jump = self.code.len();
+                self.code.push(Instr::Jump(0));
+                self.code[unless] = Instr::JumpUnless(self.code.len());
+                self.emit(b, depth)?;
+                self.code[jump] = Instr::Jump(self.code.len());
+            }
+        }
+        Ok(())
+    }
+
+    /// Run the program with `args` holding the values of its variables,
+    /// giving the same result as evaluating the expression would.
+    pub fn exec(&self, args: &[f64]) -> Result<f64> {
+        if let Some(var) = self.variables.get(args.len()) {
+            return Err(ExprError::UnboundVariable(var.clone()));
+        }
+        let mut stack = Vec::with_capacity(self.depth);
+        let mut pc = 0;
+        while let Some(instr) = self.code.get(pc) {
+            pc += 1;
+            match *instr {
+                Instr::Num(n) => stack.push(n),
+                Instr::Load(slot) => stack.push(args[slot]),
+                Instr::Neg => {
+                    let a = stack.pop().ok_or(ExprError::StackUnderflow)?;
+                    stack.push(-a);
+                }
+                Instr::Binary(ref op) => {
+                    let b = stack.pop().ok_or(ExprError::StackUnderflow)?;
+                    let a = stack.pop().ok_or(ExprError::StackUnderflow)?;
+                    stack.push(op.apply_binary(a, b)?);
+                }
+                Instr::Call(func) => {
+                    let a = stack.pop().ok_or(ExprError::StackUnderflow)?;
+                    stack.push(func.apply(a)?);
+                }
+                Instr::JumpUnless(target) => {
+                    if stack.pop().ok_or(ExprError::StackUnderflow)? == 0f64 {
+                        pc = target;
+                    }
+                }
+                Instr::Jump(target) => pc = target,
+            }
+        }
+        stack.pop().ok_or(ExprError::Empty)
+    }
+
+    /// Run the program once for each of `inputs`, which hold the values of
+    /// its variables as for `exec`, e.g., once per point of a dataset.
+    pub fn eval_batch(&self, inputs: &[&[f64]]) -> Vec<Result<f64>> {
+        inputs.iter().map(|args| self.exec(args)).collect()
+    }
+
+    /// Like `eval_batch`, but runs the program on `inputs` in parallel,
+    /// which pays off for large batches.
+    pub fn par_eval_batch(&self, inputs: &[&[f64]]) -> Vec<Result<f64>> {
+        inputs.par_iter().map(|args| self.exec(args)).collect()
+    }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use expr;
+
+    #[test]
+    pub fn test_compile() {
+        let program = compile("y * (x + pi) - y").unwrap();
+        assert_eq!(program.variables(), &["y".to_string(), "x".to_string()]);
+        assert_eq!(program.depth, 3);
+        assert_eq!(program.exec(&[2f64, 1f64]), Ok(2f64 * (1f64 + ::std::f64::consts::PI) - 2f64));
+        assert_eq!(program.exec(&[2f64]), Err(ExprError::UnboundVariable("x".to_string())));
+        assert_eq!(compile("7").unwrap().exec(&[]), Ok(7f64));
+        assert!(compile("1 +").is_err());
+    }
+
+    #[test]
+    pub fn test_exec() {
+        let cases = ["x ? 1/x : 0", "-x**2 + sqrt(abs(x))", "x > 1 ? x < 3 ? 1 : 2 : 3",
+                     "1 - 2 - x", "x % 2 == 0 && x", "log(x)", "(x & 6) << 1", "2(x+1)"];
+        for e in &cases {
+            let program = compile(e).unwrap();
+            for &x in &[-2f64, 0f64, 0.5, 2f64, 5f64] {
+                assert_eq!(program.exec(&[x]), expr::eval_at(e, x), "{} at {}", e, x);
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_eval_batch() {
+        let program = compile("x / y").unwrap();
+        let inputs: [&[f64]; 3] = [&[1f64, 2f64], &[3f64, 0f64], &[4f64]];
+        let expected = vec![Ok(0.5), Err(ExprError::DivisionByZero),
+                            Err(ExprError::UnboundVariable("y".to_string()))];
+        assert_eq!(program.eval_batch(&inputs), expected);
+        assert_eq!(program.par_eval_batch(&inputs), expected);
+        assert!(program.eval_batch(&[]).is_empty());
+    }
+}
@@ -0,0 +1,100 @@
+use rand::Rng;
+use super::{Func, Op};
+use super::ast::Expr;
+
+/// What random expressions are made of.
+#[derive(Debug,Clone,PartialEq)]
+pub struct SymbolPolicy {
+    /// The operators to draw from; `Op::UnNeg` makes negations.
+    pub operators: Vec<Op>,
+    /// The functions to draw from.
+    pub functions: Vec<Func>,
+    /// The variables to draw from.
+    pub variables: Vec<String>,
+    /// Numbers are whole numbers below this; with no variables, there must
+    /// be at least one.
+    pub numbers: u32,
+    /// Probability that a node above the maximum depth is a leaf anyway.
+    pub leaf_probability: f64,
+}
+
+impl Default for SymbolPolicy {
+    /// Single digits and the arithmetic operators.
+    fn default() -> SymbolPolicy {
+        SymbolPolicy {
+            operators: vec![Op::Add, Op::Sub, Op::Mul, Op::Div, Op::Exp],
+            functions: Vec::new(),
+            variables: Vec::new(),
+            numbers: 10,
+            leaf_probability: 1f64 / 3f64,
+        }
+    }
+}
+
+/// Return a random expression made of the symbols in `policy`, no deeper than
+/// `max_depth` (a leaf has depth 1), growing each branch to a random depth.
+///
+/// Panics if `policy` has neither numbers nor variables.
+pub fn random_expr<R: Rng>(rng: &mut R, max_depth: usize, policy: &SymbolPolicy) -> Expr {
+    let inner = policy.operators.len() + policy.functions.len();
+    if max_depth <= 1 || inner == 0 || rng.gen_range(0f64, 1f64) < policy.leaf_probability {
+        let leaves = policy.numbers as usize + policy.variables.len();
+        assert!(leaves > 0, "No numbers or variables to make a leaf of");
+        let i = rng.gen_range(0, leaves);
+        return match policy.variables.get(i) {
+            Some(var) => Expr::Var(var.clone()),
+            None => Expr::Num((i - policy.variables.len()) as f64),
+        };
+    }
+    let operand = |rng: &mut R| Box::new(random_expr(rng, max_depth - 1, policy));
+    match policy.operators.get(rng.gen_range(0, inner)) {
+        Some(&Op::UnNeg) => Expr::Unary(Op::UnNeg, operand(rng)),
+        Some(op) => {
+            let a = operand(rng);
+            Expr::Binary(op.clone(), a, operand(rng))
+        }
+        None => {
+            let func = policy.functions[rng.gen_range(0, policy.functions.len())];
+            Expr::Call(func, operand(rng))
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use rand::thread_rng;
+    use expr::{metrics, OPERATORS};
+    use expr::ast::parse;
+
+    #[test]
+    pub fn test_random_expr() {
+        let mut rng = thread_rng();
+        let policy = SymbolPolicy::default();
+        for _ in 0..50 {
+            let e = random_expr(&mut rng, 4, &policy);
+            assert!(metrics(&e).depth <= 4);
+            assert!(metrics(&e).operators.keys().all(|op| policy.operators.contains(op)));
+        }
+        let leaves = SymbolPolicy { numbers: 0, variables: vec!["x".to_string()],
+                                    ..Default::default() };
+        assert_eq!(random_expr(&mut rng, 1, &leaves), Expr::Var("x".to_string()));
+    }
+
+    #[test]
+    pub fn test_round_trip() {
+        // Every expression should print as one that parses back to it.
+        let mut rng = thread_rng();
+        let policy = SymbolPolicy {
+            operators: OPERATORS.to_vec(),
+            functions: vec![Func::Sqrt, Func::Sin, Func::Cos, Func::Log, Func::Abs],
+            variables: vec!["x".to_string(), "y".to_string()],
+            numbers: 100,
+            leaf_probability: 0.2,
+        };
+        for _ in 0..500 {
+            let e = random_expr(&mut rng, 6, &policy);
+            assert_eq!(parse(&e.to_string()).as_ref(), Ok(&e), "{}", e);
+        }
+    }
+}
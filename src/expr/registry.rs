@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use super::{builtin_constant, tok, ExprError, Func, Result, Tok};
+use super::ast::{self, Expr};
+
+/// A function registered with a `Registry`, called with the values of its
+/// arguments.
+pub type Function = Arc<dyn Fn(&[f64]) -> f64 + Send + Sync>;
+
+/// A registry of named functions that expressions may call besides the
+/// built-in ones, as in `clamp(x, 0, 1)` after
+/// `registry.register("clamp", 3, |args| args[0].max(args[1]).min(args[2]))`.
+/// A function registered with an arity is only called with that many
+/// arguments; calling it with any other number is an error.
+#[derive(Clone,Default)]
+pub struct Registry {
+    /// Each function with its arity, or None if it takes any number of
+    /// arguments.
+    functions: HashMap<String, (Option<usize>, Function)>,
+}
+
+impl fmt::Debug for Registry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut names = self.functions.keys().collect::<Vec<_>>();
+        names.sort();
+        f.debug_struct("Registry").field("functions", &names).finish()
+    }
+}
+
+impl Registry {
+    pub fn new() -> Registry { Registry::default() }
+
+    /// Register `f` as the function `name` of `arity` arguments, replacing
+    /// any previous one, or return an error if `name` is not a valid
+    /// variable name or is that of a built-in function.
+    pub fn register<F>(&mut self, name: &str, arity: usize, f: F) -> Result<()>
+            where F: Fn(&[f64]) -> f64 + Send + Sync + 'static {
+        self.insert(name, Some(arity), Arc::new(f))
+    }
+
+    /// Register `f` as the function `name` of any number of arguments, as
+    /// `register` does.
+    pub fn register_variadic<F>(&mut self, name: &str, f: F) -> Result<()>
+            where F: Fn(&[f64]) -> f64 + Send + Sync + 'static {
+        self.insert(name, None, Arc::new(f))
+    }
+
+    fn insert(&mut self, name: &str, arity: Option<usize>, f: Function) -> Result<()> {
+        if tok(name).ok() != Some(vec![Tok::Var(name.to_string())]) ||
+           Func::from_str(name).is_some() {
+            return Err(ExprError::InvalidName(name.to_string()));
+        }
+        self.functions.insert(name.to_string(), (arity, f));
+        Ok(())
+    }
+
+    /// Return true if the function `name` is registered.
+    pub fn contains(&self, name: &str) -> bool { self.functions.contains_key(name) }
+
+    /// Call the function `name` with the arguments `args`, or return an
+    /// error if it takes a different number of them.
+    pub fn call(&self, name: &str, args: &[f64]) -> Result<f64> {
+        let &(arity, ref f) =
+            self.functions.get(name).ok_or(ExprError::UnknownFunction(name.to_string()))?;
+        match arity {
+            Some(n) if n != args.len() => Err(ExprError::WrongArity {
+                name: name.to_string(), expected: n, found: args.len()
+            }),
+            _ => Ok(f(args)),
+        }
+    }
+
+    /// Parse `s`, which may call these functions, into an expression tree.
+    pub fn parse(&self, s: &str) -> Result<Expr> { ast::parse_with(s, self) }
+
+    /// Evaluate `s`, which may call these functions and use the constants
+    /// `pi` and `e`.
+    pub fn eval(&self, s: &str) -> Result<f64> { self.eval_with(s, &HashMap::new()) }
+
+    /// Evaluate `s` with the variables bound in `env`, which take precedence
+    /// over the constants `pi` and `e`.
+    pub fn eval_with(&self, s: &str, env: &HashMap<String, f64>) -> Result<f64> {
+        let lookup = |var: &str| env.get(var).cloned().or_else(|| builtin_constant(var));
+        self.parse(s)?.eval_using(&lookup, self)
+    }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use expr;
+
+    fn registry() -> Registry {
+        let mut registry = Registry::new();
+        registry.register("clamp", 3, |args| args[0].max(args[1]).min(args[2])).unwrap();
+        registry.register("hypot", 2, |args| args[0].hypot(args[1])).unwrap();
+        registry.register("answer", 0, |_| 42f64).unwrap();
+        registry.register_variadic("count", |args| args.len() as f64).unwrap();
+        registry
+    }
+
+    #[test]
+    pub fn test_register() {
+        let mut registry = registry();
+        assert!(registry.contains("clamp"));
+        assert!(!registry.contains("sqrt"));
+        assert_eq!(registry.register("sqrt", 1, |_| 0f64),
+                   Err(ExprError::InvalidName("sqrt".to_string())));
+        assert!(registry.register("2x", 1, |_| 0f64).is_err());
+        assert!(registry.register_variadic("f(x)", |_| 0f64).is_err());
+        assert_eq!(registry.call("hypot", &[3f64, 4f64]), Ok(5f64));
+        assert_eq!(registry.call("hypot", &[3f64]),
+                   Err(ExprError::WrongArity { name: "hypot".to_string(), expected: 2, found: 1 }));
+        assert_eq!(registry.call("count", &[]), Ok(0f64));
+        assert_eq!(registry.call("nope", &[]), Err(ExprError::UnknownFunction("nope".to_string())));
+        assert_eq!(format!("{:?}", registry),
+                   "Registry { functions: [\"answer\", \"clamp\", \"count\", \"hypot\"] }");
+    }
+
+    #[test]
+    pub fn test_eval() {
+        let registry = registry();
+        assert_eq!(registry.eval("clamp(7, 0, 5) * 2"), Ok(10f64));
+        assert_eq!(registry.eval("hypot(3, 2 + 2) - 1"), Ok(4f64));
+        assert_eq!(registry.eval("answer()"), Ok(42f64));
+        assert_eq!(registry.eval("clamp(1)"),
+                   Err(ExprError::WrongArity { name: "clamp".to_string(), expected: 3, found: 1 }));
+        assert_eq!(registry.eval("count(1, (2, 3) ? 1 : 0)"), Err(ExprError::MisplacedComma));
+        assert_eq!(registry.eval("count(1, 1 ? 2 : 3, hypot(0, 1), -4)"), Ok(4f64));
+        assert_eq!(registry.eval("2clamp(-1, 0, 1)"), Ok(0f64));
+        assert_eq!(registry.eval("clamp(sqrt(16), 0, pi)"), Ok(::std::f64::consts::PI));
+        let mut env = HashMap::new();
+        env.insert("x".to_string(), 9f64);
+        assert_eq!(registry.eval_with("clamp(x, 0, 5) + sqrt(x)", &env), Ok(8f64));
+
+        assert_eq!(registry.eval("count(1,)"), Err(ExprError::MisplacedComma));
+        assert_eq!(registry.eval("count(, 1)"), Err(ExprError::MisplacedComma));
+        assert_eq!(registry.eval("1, 2"), Err(ExprError::MisplacedComma));
+        assert_eq!(registry.eval("sqrt(1, 2)"), Err(ExprError::MisplacedComma));
+        assert!(registry.eval("count(1 2)").is_err());
+        assert_eq!(expr::eval("clamp(7, 0, 5)"), Err(ExprError::MisplacedComma));
+        assert_eq!(expr::eval("answer()"), Err(ExprError::ExpectedOperand { pos: 7 }));
+    }
+
+    #[test]
+    pub fn test_parse() {
+        let registry = registry();
+        let e = registry.parse("clamp(x+1, 0, 2*y)").unwrap();
+        assert_eq!(e.to_string(), "clamp(x + 1, 0, 2 * y)");
+        assert_eq!(registry.parse(&e.to_string()), Ok(e.clone()));
+        assert_eq!(e.eval(&|_| Some(1f64)), Err(ExprError::UnknownFunction("clamp".to_string())));
+        assert_eq!(expr::derive(&e, "x"), Err(ExprError::NotDifferentiable("clamp".to_string())));
+        assert_eq!(expr::derive(&e, "z"), Ok(Expr::Num(0f64)));
+        assert_eq!(expr::derive(&registry.parse("x * answer()").unwrap(), "x").unwrap().to_string(),
+                   "answer()");
+        assert_eq!(expr::canonical("f (1 ,2)"), Ok("f*(1,2)".to_string()));
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use super::{builtin_constant, ExprError, Result};
+use super::ast::{parse, Expr};
+use super::visit::{walk_transform, ExprTransformer};
+
+/// Most rewrites a `Rewriter` makes of an expression, unless set otherwise.
+const MAX_STEPS: usize = 1000;
+
+/// An algebraic rule, which rewrites expressions matching its pattern into
+/// its replacement, like `x*1` into `x`. Every variable in the pattern but
+/// the constants `pi` and `e` stands for any expression, the same one
+/// wherever it occurs, so that `x - x` matches `(a+1) - (a+1)` but not
+/// `a - b`.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Rule {
+    pattern: Expr,
+    replacement: Expr,
+}
+
+impl Rule {
+    /// Make the rule rewriting `pattern` into `replacement`. It is an error
+    /// for the replacement to use a variable the pattern does not.
+    pub fn new(pattern: &str, replacement: &str) -> Result<Rule> {
+        let rule = Rule { pattern: parse(pattern)?, replacement: parse(replacement)? };
+        // The pattern matches itself, with each variable standing for itself.
+        let mut bindings = HashMap::new();
+        rule.pattern.matches(&rule.pattern, &mut bindings);
+        substitute(&rule.replacement, &bindings)?;
+        Ok(rule)
+    }
+
+    /// Return `e` rewritten by this rule, if it matches `e` as a whole.
+    pub fn apply(&self, e: &Expr) -> Option<Expr> {
+        let mut bindings = HashMap::new();
+        if self.pattern.matches(e, &mut bindings) {
+            substitute(&self.replacement, &bindings).ok()
+        } else {
+            None
+        }
+    }
+}
+
+impl Expr {
+    /// Return whether `e` matches this pattern, adding what each of its
+    /// variables stands for to `bindings`.
+    fn matches<'a>(&'a self, e: &'a Expr, bindings: &mut HashMap<&'a str, &'a Expr>) -> bool {
+        match (self, e) {
+            (Expr::Var(var), _) if builtin_constant(var).is_none() => {
+                match bindings.get(var.as_str()) {
+                    Some(&bound) => bound == e,
+                    None => {
+                        bindings.insert(var, e);
+                        true
+                    }
+                }
+            }
+            (Expr::Unary(op, a), Expr::Unary(op2, a2)) => {
+                op == op2 && a.matches(a2, bindings)
+            }
+            (Expr::Binary(op, a, b), Expr::Binary(op2, a2, b2)) => {
+                op == op2 && a.matches(a2, bindings) && b.matches(b2, bindings)
+            }
+            (Expr::Call(func, a), Expr::Call(func2, a2)) => {
+                func == func2 && a.matches(a2, bindings)
+            }
+            (Expr::Apply(name, args), Expr::Apply(name2, args2)) => {
+                name == name2 && args.len() == args2.len() &&
+                    args.iter().zip(args2).all(|(a, a2)| a.matches(a2, bindings))
+            }
+            (Expr::Cond(c, a, b), Expr::Cond(c2, a2, b2)) => {
+                c.matches(c2, bindings) && a.matches(a2, bindings) && b.matches(b2, bindings)
+            }
+            _ => self == e,
+        }
+    }
+}
+
+/// Return `e` with its variables replaced by what they stand for.
+fn substitute(e: &Expr, bindings: &HashMap<&str, &Expr>) -> Result<Expr> {
+    let sub = |e: &Expr| substitute(e, bindings).map(Box::new);
+    Ok(match *e {
+        Expr::Var(ref var) if builtin_constant(var).is_none() => match bindings.get(var.as_str()) {
+            Some(&bound) => bound.clone(),
+            None => return Err(ExprError::UnboundVariable(var.clone())),
+        },
+        Expr::Unary(ref op, ref a) => Expr::Unary(op.clone(), sub(a)?),
+        Expr::Binary(ref op, ref a, ref b) => Expr::Binary(op.clone(), sub(a)?, sub(b)?),
+        Expr::Call(func, ref a) => Expr::Call(func, sub(a)?),
+        Expr::Apply(ref name, ref args) => {
+            Expr::Apply(name.clone(),
+                        args.iter().map(|a| substitute(a, bindings)).collect::<Result<_>>()?)
+        }
+        Expr::Cond(ref c, ref a, ref b) => Expr::Cond(sub(c)?, sub(a)?, sub(b)?),
+        ref e => e.clone(),
+    })
+}
+
+/// A set of rules, applied to expressions bottom-up, over and over until
+/// none applies or a limit on the number of rewrites is reached, since rules
+/// like `x + y` to `y + x` would go on forever.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Rewriter {
+    rules: Vec<Rule>,
+    max_steps: usize,
+}
+
+impl Default for Rewriter {
+    fn default() -> Rewriter { Rewriter::new() }
+}
+
+impl Rewriter {
+    /// Create a rewriter without rules.
+    pub fn new() -> Rewriter { Rewriter { rules: Vec::new(), max_steps: MAX_STEPS } }
+
+    /// Create a rewriter with the identities `simplify` applies, like `x*1`
+    /// to `x` and `x+0` to `x`, to add rules of one's own to. None of them
+    /// drops `x`, which could be undefined or infinite, so there is no `x-x`
+    /// to `0` or `x**0` to `1`.
+    pub fn identities() -> Rewriter {
+        let mut rewriter = Rewriter::new();
+        let identities = [("0 + x", "x"), ("x + 0", "x"), ("x - 0", "x"), ("0 - x", "-x"),
+                          ("1 * x", "x"), ("x * 1", "x"), ("x / 1", "x"), ("x ** 1", "x"),
+                          ("--x", "x")];
+        for &(pattern, replacement) in &identities {
+            rewriter.add(pattern, replacement).unwrap();
+        }
+        rewriter
+    }
+
+    /// Add the rule rewriting `pattern` into `replacement`, to be tried
+    /// after the rules added before it (see `Rule::new()`).
+    pub fn add(&mut self, pattern: &str, replacement: &str) -> Result<()> {
+        self.rules.push(Rule::new(pattern, replacement)?);
+        Ok(())
+    }
+
+    /// Return this rewriter with at most `max_steps` rewrites per expression.
+    pub fn with_max_steps(self, max_steps: usize) -> Rewriter { Rewriter { max_steps, ..self } }
+
+    /// Return `e` rewritten by the first rule that applies to it as a whole,
+    /// if any.
+    pub fn apply(&self, e: &Expr) -> Option<Expr> {
+        self.rules.iter().filter_map(|rule| rule.apply(e)).next()
+    }
+
+    /// Return `e` rewritten by its rules, as a whole, until none applies or
+    /// `steps` rewrites have been made of the expression it is part of.
+    pub(crate) fn apply_all(&self, e: Expr, steps: &mut usize) -> Expr {
+        let mut e = e;
+        while *steps < self.max_steps {
+            match self.apply(&e) {
+                Some(rewritten) => e = rewritten,
+                None => break,
+            }
+            *steps += 1;
+        }
+        e
+    }
+
+    /// Return `e` rewritten until no rule applies to any part of it, or
+    /// the limit on rewrites is reached.
+    pub fn rewrite(&self, e: Expr) -> Expr {
+        let mut pass = Pass { rewriter: self, steps: 0 };
+        let mut e = e;
+        loop {
+            let before = pass.steps;
+            e = pass.transform_expr(e);
+            if pass.steps == before || pass.steps >= self.max_steps {
+                return e;
+            }
+        }
+    }
+}
+
+/// A bottom-up pass of a rewriter over an expression.
+struct Pass<'a> {
+    rewriter: &'a Rewriter,
+    /// The number of rewrites so far.
+    steps: usize,
+}
+
+impl<'a> ExprTransformer for Pass<'a> {
+    fn transform_expr(&mut self, e: Expr) -> Expr {
+        let e = walk_transform(self, e);
+        self.rewriter.apply_all(e, &mut self.steps)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn rewritten(rewriter: &Rewriter, s: &str) -> String {
+        rewriter.rewrite(parse(s).unwrap()).to_string()
+    }
+
+    #[test]
+    pub fn test_rule() {
+        let rule = Rule::new("x - x", "0").unwrap();
+        assert_eq!(rule.apply(&parse("(a+1) - (a+1)").unwrap()), Some(Expr::Num(0f64)));
+        assert_eq!(rule.apply(&parse("a - b").unwrap()), None);
+        assert_eq!(rule.apply(&parse("a - a - a").unwrap()), None);
+
+        let rule = Rule::new("sin(x) * pi", "pi * sin(x)").unwrap();
+        assert_eq!(rule.apply(&parse("sin(2y) * pi").unwrap()), parse("pi * sin(2y)").ok());
+        assert_eq!(rule.apply(&parse("sin(2y) * e").unwrap()), None);
+
+        assert_eq!(Rule::new("x * 1", "y"), Err(ExprError::UnboundVariable("y".to_string())));
+        assert!(Rule::new("x *", "x").is_err());
+    }
+
+    #[test]
+    pub fn test_rewriter() {
+        let mut rewriter = Rewriter::new();
+        rewriter.add("log(x * y)", "log(x) + log(y)").unwrap();
+        assert_eq!(rewritten(&rewriter, "1 + log(a * b * c)"), "1 + log(a) + log(b) + log(c)");
+        assert_eq!(rewritten(&Rewriter::identities(), "0 - (x * 1 - 0)"), "-x");
+        assert_eq!(rewritten(&Rewriter::identities(), "--(x + 0)"), "x");
+
+        // A rule that always applies stops at the limit.
+        let mut rewriter = Rewriter::new().with_max_steps(3);
+        rewriter.add("x + y", "y + x").unwrap();
+        assert_eq!(rewritten(&rewriter, "a + b"), "b + a");
+        assert_eq!(rewritten(&rewriter.clone().with_max_steps(4), "a + b"), "a + b");
+    }
+}
@@ -0,0 +1,429 @@
+use super::{var, ExprError, Func, Op, Result, Tok};
+use super::ast::Expr;
+
+/// An expression tree as nested lists: an atom is a number, a name or an
+/// operator symbol, and a list is an application of its first element.
+#[derive(Debug,Clone,PartialEq)]
+enum Node {
+    /// An atom, and the character offset at which it was read.
+    Atom(String, usize),
+    List(Vec<Node>, usize),
+}
+
+/// Return whether `s` is the name of a variable.
+fn is_name(s: &str) -> bool {
+    match var(s, None) {
+        Some((Tok::Var(_), n)) => n == s.len(),
+        _ => false,
+    }
+}
+
+/// Return whether `s` is meant to be a number, i.e., starts with a digit
+/// or a decimal point, possibly after a minus sign.
+fn is_numeric(s: &str) -> bool {
+    let s = s.strip_prefix('-').unwrap_or(s);
+    s.starts_with(|c: char| c.is_ascii_digit() || c == '.')
+}
+
+fn node(e: &Expr) -> Node {
+    let atom = |s: &str| Node::Atom(s.to_string(), 0);
+    let list = |head: &str, args: &[&Expr]| {
+        let mut items = vec![atom(head)];
+        items.extend(args.iter().map(|a| node(a)));
+        Node::List(items, 0)
+    };
+    match *e {
+        Expr::Num(n) if n.is_nan() => atom("#nan"),
+        Expr::Num(n) if n.is_infinite() => atom(if n > 0f64 { "#inf" } else { "#-inf" }),
+        Expr::Num(n) => atom(&n.to_string()),
+        Expr::Var(ref name) => atom(name),
+        Expr::Unary(ref op, ref a) => list(op.symbol(), &[a]),
+        Expr::Binary(ref op, ref a, ref b) => list(op.symbol(), &[a, b]),
+        Expr::Call(ref func, ref a) => list(func.name(), &[a]),
+        Expr::Apply(ref name, ref args) => list(name, &args.iter().collect::<Vec<_>>()),
+        Expr::Cond(ref c, ref a, ref b) => list("?", &[c, a, b]),
+    }
+}
+
+fn expr(node: Node) -> Result<Expr> {
+    match node {
+        Node::Atom(s, pos) => atom(s, pos),
+        Node::List(items, pos) => {
+            let mut items = items.into_iter();
+            let head = match items.next() {
+                Some(Node::Atom(head, _)) => head,
+                Some(Node::List(..)) => {
+                    return Err(ExprError::InvalidOperator { pos, op: "(".to_string() })
+                }
+                None => return Err(ExprError::Empty),
+            };
+            let mut args = items.map(expr).collect::<Result<Vec<_>>>()?;
+            let arity = match (head.as_str(), args.len()) {
+                ("?", _) => 3,
+                ("-", 1) => 1,
+                (h, _) if Op::from_str(h).is_some() => 2,
+                (h, _) if Func::from_str(h).is_some() => 1,
+                (h, _) if is_name(h) => return Ok(Expr::Apply(head, args)),
+                _ => return Err(ExprError::InvalidOperator { pos, op: head }),
+            };
+            if args.len() < arity {
+                return Err(ExprError::StackUnderflow);
+            } else if args.len() > arity {
+                return Err(ExprError::MissingOperator);
+            }
+            let mut arg = || Box::new(args.remove(0));
+            Ok(match (Op::from_str(&head), Func::from_str(&head)) {
+                _ if head == "?" => Expr::Cond(arg(), arg(), arg()),
+                (Some(_), _) if arity == 1 => Expr::Unary(Op::UnNeg, arg()),
+                (Some(op), _) => Expr::Binary(op, arg(), arg()),
+                (_, Some(func)) => Expr::Call(func, arg()),
+                (None, None) => unreachable!(),
+            })
+        }
+    }
+}
+
+fn atom(s: String, pos: usize) -> Result<Expr> {
+    match s.as_str() {
+        "#nan" => return Ok(Expr::Num(f64::NAN)),
+        "#inf" => return Ok(Expr::Num(f64::INFINITY)),
+        "#-inf" => return Ok(Expr::Num(f64::NEG_INFINITY)),
+        _ => {}
+    }
+    if is_name(&s) {
+        return Ok(Expr::Var(s));
+    }
+    if !is_numeric(&s) {
+        let found = s.chars().next().unwrap_or(' ');
+        return Err(ExprError::InvalidToken { pos, found });
+    }
+    match s.parse() {
+        Ok(n) => Ok(Expr::Num(n)),
+        Err(e) => Err(ExprError::InvalidNumber { pos, literal: s, reason: format!("{}", e) }),
+    }
+}
+
+/// Write `e` as an S-expression, like `(* 2 (+ x 1))` for `2 * (x + 1)`.
+///
+/// Operators and built-in functions are written by their symbols and names,
+/// a negation as `(- x)`, a conditional `c ? a : b` as `(? c a b)`, and
+/// numbers that are not finite as `#nan`, `#inf` and `#-inf`. The result
+/// reads back as the same expression with `from_sexpr`.
+pub fn to_sexpr(e: &Expr) -> String {
+    let mut s = String::new();
+    write_sexpr(&node(e), &mut s);
+    s
+}
+
+fn write_sexpr(node: &Node, s: &mut String) {
+    match *node {
+        Node::Atom(ref atom, _) => s.push_str(atom),
+        Node::List(ref items, _) => {
+            s.push('(');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    s.push(' ');
+                }
+                write_sexpr(item, s);
+            }
+            s.push(')');
+        }
+    }
+}
+
+/// Read an expression written as an S-expression by `to_sexpr`.
+pub fn from_sexpr(s: &str) -> Result<Expr> {
+    let chars = s.chars().collect::<Vec<_>>();
+    let mut stack: Vec<(Vec<Node>, usize)> = Vec::new();
+    let mut top = None;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let node = match c {
+            _ if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => {
+                stack.push((Vec::new(), i));
+                i += 1;
+                continue;
+            }
+            ')' => {
+                let (items, pos) = stack.pop().ok_or(ExprError::UnbalancedParen)?;
+                i += 1;
+                Node::List(items, pos)
+            }
+            _ => {
+                let n = chars[i..].iter()
+                                  .position(|&c| c.is_whitespace() || c == '(' || c == ')')
+                                  .unwrap_or(chars.len() - i);
+                let atom = chars[i..i + n].iter().collect();
+                i += n;
+                Node::Atom(atom, i - n)
+            }
+        };
+        match stack.last_mut() {
+            Some(&mut (ref mut items, _)) => items.push(node),
+            None if top.is_none() => top = Some(node),
+            None => return Err(ExprError::MissingOperator),
+        }
+    }
+    if !stack.is_empty() {
+        return Err(ExprError::UnbalancedParen);
+    }
+    expr(top.ok_or(ExprError::Empty)?)
+}
+
+/// Write `e` as JSON, in the shape of its S-expression: numbers as numbers,
+/// names, symbols and numbers that are not finite as strings, and lists as
+/// arrays, like `["*", 2, ["+", "x", 1]]` for `2 * (x + 1)`. The result
+/// reads back as the same expression with `from_json`.
+pub fn to_json(e: &Expr) -> String {
+    let mut s = String::new();
+    write_json(&node(e), &mut s);
+    s
+}
+
+fn write_json(node: &Node, s: &mut String) {
+    match *node {
+        Node::Atom(ref atom, _) if is_numeric(atom) => s.push_str(atom),
+        Node::Atom(ref atom, _) => {
+            s.push('"');
+            for c in atom.chars() {
+                if c == '"' || c == '\\' {
+                    s.push('\\');
+                }
+                s.push(c);
+            }
+            s.push('"');
+        }
+        Node::List(ref items, _) => {
+            s.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    s.push_str(", ");
+                }
+                write_json(item, s);
+            }
+            s.push(']');
+        }
+    }
+}
+
+/// Read an expression written as JSON by `to_json`.
+///
+/// Only the subset of JSON that `to_json` writes is read: arrays, strings
+/// and numbers, without objects, `true`, `false` or `null`. A `\u` escape
+/// in a string must stand for a character by itself, not half of a
+/// surrogate pair. Any other JSON is an error.
+pub fn from_json(s: &str) -> Result<Expr> {
+    let chars = s.chars().collect::<Vec<_>>();
+    let mut i = 0;
+    let node = json_value(&chars, &mut i)?;
+    skip_whitespace(&chars, &mut i);
+    match chars.get(i) {
+        Some(&found) => Err(ExprError::InvalidToken { pos: i, found }),
+        None => expr(node),
+    }
+}
+
+fn skip_whitespace(chars: &[char], i: &mut usize) {
+    while chars.get(*i).is_some_and(|c| c.is_whitespace()) {
+        *i += 1;
+    }
+}
+
+/// Read the JSON value at `chars[*i..]`, and move `i` past it.
+fn json_value(chars: &[char], i: &mut usize) -> Result<Node> {
+    skip_whitespace(chars, i);
+    let pos = *i;
+    match chars.get(pos) {
+        None => Err(ExprError::Empty),
+        Some(&'[') => {
+            *i += 1;
+            let mut items = Vec::new();
+            skip_whitespace(chars, i);
+            if chars.get(*i) == Some(&']') {
+                *i += 1;
+                return Ok(Node::List(items, pos));
+            }
+            loop {
+                items.push(json_value(chars, i)?);
+                skip_whitespace(chars, i);
+                match chars.get(*i) {
+                    Some(&',') => *i += 1,
+                    Some(&']') => {
+                        *i += 1;
+                        return Ok(Node::List(items, pos));
+                    }
+                    Some(&found) => return Err(ExprError::InvalidToken { pos: *i, found }),
+                    None => return Err(ExprError::UnbalancedParen),
+                }
+            }
+        }
+        Some(&'"') => {
+            *i += 1;
+            let mut atom = String::new();
+            loop {
+                match chars.get(*i) {
+                    Some(&'"') => break,
+                    Some(&'\\') => atom.push(json_escape(chars, i)?),
+                    Some(&c) => {
+                        atom.push(c);
+                        *i += 1;
+                    }
+                    None => return Err(ExprError::InvalidToken { pos, found: '"' }),
+                }
+            }
+            *i += 1;
+            Ok(Node::Atom(atom, pos + 1))
+        }
+        Some(&c) if c == '-' || c.is_ascii_digit() => {
+            let n = chars[pos..].iter()
+                                .position(|&c| !(c.is_ascii_digit() || "+-.eE".contains(c)))
+                                .unwrap_or(chars.len() - pos);
+            *i += n;
+            let literal = chars[pos..pos + n].iter().collect::<String>();
+            if !is_json_number(&literal) {
+                let reason = "not a JSON number".to_string();
+                return Err(ExprError::InvalidNumber { pos, literal, reason });
+            }
+            Ok(Node::Atom(literal, pos))
+        }
+        Some(&found) => Err(ExprError::InvalidToken { pos, found }),
+    }
+}
+
+/// Read the escape at `chars[*i..]`, which starts with a backslash, and
+/// move `i` past it.
+fn json_escape(chars: &[char], i: &mut usize) -> Result<char> {
+    let pos = *i;
+    let c = match chars.get(pos + 1) {
+        Some(&c) if "\"\\/".contains(c) => Some(c),
+        Some(&'b') => Some('\u{8}'),
+        Some(&'f') => Some('\u{c}'),
+        Some(&'n') => Some('\n'),
+        Some(&'r') => Some('\r'),
+        Some(&'t') => Some('\t'),
+        Some(&'u') => {
+            let hex = chars.get(pos + 2..pos + 6).unwrap_or(&[]);
+            if hex.len() == 4 && hex.iter().all(|c| c.is_ascii_hexdigit()) {
+                let code = u32::from_str_radix(&hex.iter().collect::<String>(), 16);
+                code.ok().and_then(char::from_u32)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    };
+    let c = c.ok_or(ExprError::InvalidToken { pos, found: '\\' })?;
+    *i += if chars[pos + 1] == 'u' { 6 } else { 2 };
+    Ok(c)
+}
+
+/// Return whether `s` is a number as JSON spells them: an optional minus
+/// sign, digits without leading zeros, and optionally a fraction and an
+/// exponent, each with at least one digit.
+fn is_json_number(s: &str) -> bool {
+    let digits = |s: &str| s.len() - s.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let s = s.strip_prefix('-').unwrap_or(s);
+    let n = digits(s);
+    if n == 0 || (n > 1 && s.starts_with('0')) {
+        return false;
+    }
+    let mut s = &s[n..];
+    if let Some(fraction) = s.strip_prefix('.') {
+        let n = digits(fraction);
+        if n == 0 {
+            return false;
+        }
+        s = &fraction[n..];
+    }
+    if let Some(exponent) = s.strip_prefix(|c| c == 'e' || c == 'E') {
+        let exponent = exponent.strip_prefix(|c| c == '+' || c == '-').unwrap_or(exponent);
+        let n = digits(exponent);
+        if n == 0 {
+            return false;
+        }
+        s = &exponent[n..];
+    }
+    s.is_empty()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use super::super::ast::parse;
+
+    #[test]
+    pub fn test_sexpr() {
+        let e = parse("2 * (x + 1)").unwrap();
+        assert_eq!(to_sexpr(&e), "(* 2 (+ x 1))");
+        assert_eq!(from_sexpr("(* 2 (+ x 1))"), Ok(e));
+
+        for s in &["-x ** 2.5", "x < 1 ? sqrt(x) : -1e-7", "1 / 2 / 3 % 4", "(1 >> 2) ^ y"] {
+            let e = parse(s).unwrap();
+            assert_eq!(from_sexpr(&to_sexpr(&e)), Ok(e));
+        }
+        let e = Expr::Apply("clamp".to_string(), vec![Expr::Var("x".to_string()),
+                                                      Expr::Num(f64::NEG_INFINITY)]);
+        assert_eq!(to_sexpr(&e), "(clamp x #-inf)");
+        assert_eq!(from_sexpr(&to_sexpr(&e)), Ok(e));
+        assert!(matches!(from_sexpr("#nan"), Ok(Expr::Num(n)) if n.is_nan()));
+        assert_eq!(from_sexpr(" (sqrt\n 4) "), parse("sqrt(4)"));
+
+        assert_eq!(from_sexpr(""), Err(ExprError::Empty));
+        assert_eq!(from_sexpr("(+ 1 2"), Err(ExprError::UnbalancedParen));
+        assert_eq!(from_sexpr("(+ 1 2))"), Err(ExprError::UnbalancedParen));
+        assert_eq!(from_sexpr("1 2"), Err(ExprError::MissingOperator));
+        assert_eq!(from_sexpr("(+ 1)"), Err(ExprError::StackUnderflow));
+        assert_eq!(from_sexpr("(sqrt 1 2)"), Err(ExprError::MissingOperator));
+        assert_eq!(from_sexpr("(+ 1 $)"), Err(ExprError::InvalidToken { pos: 5, found: '$' }));
+        assert_eq!(from_sexpr("(~ 1 2)"),
+                   Err(ExprError::InvalidOperator { pos: 0, op: "~".to_string() }));
+        assert!(matches!(from_sexpr("(+ 1.2.3 1)"),
+                         Err(ExprError::InvalidNumber { pos: 3, .. })));
+    }
+
+    #[test]
+    pub fn test_json() {
+        let e = parse("2 * (x + 1)").unwrap();
+        assert_eq!(to_json(&e), r#"["*", 2, ["+", "x", 1]]"#);
+        assert_eq!(from_json(r#" [ "*",2,["+","x",1] ] "#), Ok(e));
+
+        for s in &["-x ** 2.5", "x < 1 ? sqrt(x) : -1e-7", "1 / 2 / 3 % 4", "(1 >> 2) ^ y"] {
+            let e = parse(s).unwrap();
+            assert_eq!(from_json(&to_json(&e)), Ok(e));
+        }
+        assert_eq!(to_json(&Expr::Num(f64::INFINITY)), r##""#inf""##);
+        assert_eq!(from_json("1e3"), Ok(Expr::Num(1000f64)));
+
+        assert_eq!(from_json(""), Err(ExprError::Empty));
+        assert_eq!(from_json(r#"["+", 1"#), Err(ExprError::UnbalancedParen));
+        assert_eq!(from_json(r#"["+", 1] 2"#), Err(ExprError::InvalidToken { pos: 9, found: '2' }));
+        assert_eq!(from_json(r#"["+" 1]"#), Err(ExprError::InvalidToken { pos: 5, found: '1' }));
+        assert_eq!(from_json("[]"), Err(ExprError::Empty));
+
+        // Escapes stand for the characters they name.
+        assert_eq!(from_json(r#""\u0078""#), Ok(Expr::Var("x".to_string())));
+        assert_eq!(from_json(r#"["+", "x\n", 1]"#),
+                   Err(ExprError::InvalidToken { pos: 7, found: 'x' }));
+        assert_eq!(from_json(r#""\ud83d""#), Err(ExprError::InvalidToken { pos: 1, found: '\\' }));
+        assert_eq!(from_json(r#""\q""#), Err(ExprError::InvalidToken { pos: 1, found: '\\' }));
+        assert_eq!(from_json(r#""\u00"#), Err(ExprError::InvalidToken { pos: 1, found: '\\' }));
+
+        for s in &["1e", "1.", "01", "-", "1e+", "1-2"] {
+            assert!(matches!(from_json(s), Err(ExprError::InvalidNumber { pos: 0, .. })), "{}", s);
+        }
+        assert_eq!(from_json("-0.5E+1"), Ok(Expr::Num(-5f64)));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    pub fn test_serde() {
+        use serde::{Deserialize, Serialize};
+        fn is_serde<T: Serialize + for<'de> Deserialize<'de>>() {}
+        is_serde::<Expr>();
+    }
+}
@@ -0,0 +1,141 @@
+use super::{Func, Op};
+use super::ast::Expr;
+
+/// A walk over an expression tree. Each method is called on a node of its
+/// kind, and by default visits the node's subexpressions in order, so that
+/// a visitor need only handle the nodes it cares about.
+pub trait ExprVisitor {
+    /// Visit `e`, calling the method for its kind of node.
+    fn visit_expr(&mut self, e: &Expr) { walk_expr(self, e) }
+
+    fn visit_num(&mut self, _n: f64) {}
+
+    fn visit_var(&mut self, _name: &str) {}
+
+    fn visit_unary(&mut self, _op: &Op, a: &Expr) { self.visit_expr(a) }
+
+    fn visit_binary(&mut self, _op: &Op, a: &Expr, b: &Expr) {
+        self.visit_expr(a);
+        self.visit_expr(b);
+    }
+
+    fn visit_call(&mut self, _func: Func, a: &Expr) { self.visit_expr(a) }
+
+    fn visit_apply(&mut self, _name: &str, args: &[Expr]) {
+        for a in args {
+            self.visit_expr(a);
+        }
+    }
+
+    fn visit_cond(&mut self, c: &Expr, a: &Expr, b: &Expr) {
+        self.visit_expr(c);
+        self.visit_expr(a);
+        self.visit_expr(b);
+    }
+}
+
+/// Call the method of `visitor` for the kind of node `e` is.
+pub fn walk_expr<V: ExprVisitor + ?Sized>(visitor: &mut V, e: &Expr) {
+    match *e {
+        Expr::Num(n) => visitor.visit_num(n),
+        Expr::Var(ref name) => visitor.visit_var(name),
+        Expr::Unary(ref op, ref a) => visitor.visit_unary(op, a),
+        Expr::Binary(ref op, ref a, ref b) => visitor.visit_binary(op, a, b),
+        Expr::Call(func, ref a) => visitor.visit_call(func, a),
+        Expr::Apply(ref name, ref args) => visitor.visit_apply(name, args),
+        Expr::Cond(ref c, ref a, ref b) => visitor.visit_cond(c, a, b),
+    }
+}
+
+/// A rewrite of an expression tree into another. Each method is called on
+/// a node of its kind, with its subexpressions as they were, and by default
+/// rebuilds the node from its transformed subexpressions, so that a
+/// transformer need only handle the nodes it changes.
+pub trait ExprTransformer {
+    /// Transform `e`, calling the method for its kind of node.
+    fn transform_expr(&mut self, e: Expr) -> Expr { walk_transform(self, e) }
+
+    fn transform_num(&mut self, n: f64) -> Expr { Expr::Num(n) }
+
+    fn transform_var(&mut self, name: String) -> Expr { Expr::Var(name) }
+
+    fn transform_unary(&mut self, op: Op, a: Expr) -> Expr {
+        Expr::Unary(op, Box::new(self.transform_expr(a)))
+    }
+
+    fn transform_binary(&mut self, op: Op, a: Expr, b: Expr) -> Expr {
+        let a = self.transform_expr(a);
+        Expr::Binary(op, Box::new(a), Box::new(self.transform_expr(b)))
+    }
+
+    fn transform_call(&mut self, func: Func, a: Expr) -> Expr {
+        Expr::Call(func, Box::new(self.transform_expr(a)))
+    }
+
+    fn transform_apply(&mut self, name: String, args: Vec<Expr>) -> Expr {
+        Expr::Apply(name, args.into_iter().map(|a| self.transform_expr(a)).collect())
+    }
+
+    fn transform_cond(&mut self, c: Expr, a: Expr, b: Expr) -> Expr {
+        let (c, a) = (self.transform_expr(c), self.transform_expr(a));
+        Expr::Cond(Box::new(c), Box::new(a), Box::new(self.transform_expr(b)))
+    }
+}
+
+/// Call the method of `transformer` for the kind of node `e` is.
+pub fn walk_transform<T: ExprTransformer + ?Sized>(transformer: &mut T, e: Expr) -> Expr {
+    match e {
+        Expr::Num(n) => transformer.transform_num(n),
+        Expr::Var(name) => transformer.transform_var(name),
+        Expr::Unary(op, a) => transformer.transform_unary(op, *a),
+        Expr::Binary(op, a, b) => transformer.transform_binary(op, *a, *b),
+        Expr::Call(func, a) => transformer.transform_call(func, *a),
+        Expr::Apply(name, args) => transformer.transform_apply(name, args),
+        Expr::Cond(c, a, b) => transformer.transform_cond(*c, *a, *b),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use expr::ast::parse;
+
+    /// Collects the names of variables, in order of appearance.
+    struct Variables(Vec<String>);
+
+    impl ExprVisitor for Variables {
+        fn visit_var(&mut self, name: &str) { self.0.push(name.to_string()) }
+    }
+
+    /// Renames `x` to `y`, and swaps the operands of additions.
+    struct Rename;
+
+    impl ExprTransformer for Rename {
+        fn transform_var(&mut self, name: String) -> Expr {
+            Expr::Var(if name == "x" { "y".to_string() } else { name })
+        }
+
+        fn transform_binary(&mut self, op: Op, a: Expr, b: Expr) -> Expr {
+            let (a, b) = (self.transform_expr(a), self.transform_expr(b));
+            match op {
+                Op::Add => Expr::Binary(op, Box::new(b), Box::new(a)),
+                op => Expr::Binary(op, Box::new(a), Box::new(b)),
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_visitor() {
+        let mut variables = Variables(Vec::new());
+        variables.visit_expr(&parse("x < 1 ? sqrt(y) : -z * (x + 2)").unwrap());
+        assert_eq!(variables.0, vec!["x", "y", "z", "x"]);
+    }
+
+    #[test]
+    pub fn test_transformer() {
+        let e = parse("x < 1 ? sqrt(x + 1) : -z * (x + 2)").unwrap();
+        assert_eq!(Rename.transform_expr(e), parse("y < 1 ? sqrt(1 + y) : -z * (2 + y)").unwrap());
+        let e = Expr::Apply("f".to_string(), vec![Expr::Var("x".to_string())]);
+        assert_eq!(Rename.transform_expr(e).to_string(), "f(y)");
+    }
+}
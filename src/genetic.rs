@@ -1,224 +1,3258 @@
 use std::cmp;
-use std::mem;
-use rand::{Rng,thread_rng};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::time::{Duration, Instant};
+use rand::{Rng,thread_rng,seq};
 use bit_vec::BitVec;
-use expr;
+use rayon::prelude::*;
+use num::{BigInt, BigRational, Signed};
+use expr::{self, exact, EvalMode};
+use grammar::Grammar;
 
+const GENE_BITS: usize = 4;
+const POPSIZE: usize = 500;
 const MAX_GENS: usize = 1000;
 const CHROMOSOME_MIN: usize = 3;
 const CHROMOSOME_MAX: usize = 101;
 const MUTATION_RATE: f64 = 0.01;
 const CROSSOVER_RATE: f64 = 0.70;
 const EPSILON: f64 = 1e-9;
+const MIN_TEMPERATURE: f64 = 1e-3;
+const HALL_OF_FAME: usize = 5;
+const CONTROL_BITS: usize = 8;
+const MIN_SELF_ADAPTIVE_RATE: f64 = 1e-4;
+const MAX_SELF_ADAPTIVE_RATE: f64 = 0.25;
 
-/// A single phenotype.
-#[derive(Clone)]
-// See the impl below
-pub struct Chromosome {
-    pub bits: BitVec,
-    pub fitness: f64
+/// An objective for the GA to maximise.
+///
+/// Implementations score a decoded expression, which may well be malformed.
+/// Scores are expected to lie in `[0, 1]`, where 1 means the expression is an
+/// exact solution; `ga` stops as soon as it sees a chromosome scoring 1.
+/// Chromosomes are scored on several threads at once, hence the `Sync` bound.
+pub trait Fitness: Sync {
+    /// Score the (possibly malformed) expression `expr`.
+    fn fitness(&self, expr: &str) -> f64;
+
+    /// Score `expr` on each test case separately, for lexicase selection.
+    /// Objectives not made up of test cases return no scores, the default.
+    fn cases(&self, _expr: &str) -> Vec<f64> { Vec::new() }
 }
 
-fn randrange(lo: f64, hi: f64) -> f64 { thread_rng().gen_range(lo, hi) }
+/// Any `Fn(&str) -> f64` closure can be used as a fitness function.
+impl<F> Fitness for F where F: Fn(&str) -> f64 + Sync {
+    fn fitness(&self, expr: &str) -> f64 { self(expr) }
+}
 
-fn randbit() -> bool { randrange(0.0, 1.0) < 0.5 }
+/// How far from a target a value may be and still count as hitting it. A
+/// value is accepted if it is within either tolerance; the default accepts
+/// (nearly) exact hits only.
+#[derive(Debug,Clone,Copy,PartialEq,Default)]
+pub struct Tolerance {
+    /// Maximum absolute difference, e.g., 1e-3 to accept 3.1416 for 3.14159.
+    pub absolute: f64,
+    /// Maximum difference relative to the magnitude of the target.
+    pub relative: f64,
+}
 
-/// Convert a number from its binary representation in a BitVec to a usize.
-pub fn from_binary(b: &BitVec) -> usize {
-    let bytes = b.to_bytes();
-    let n = bytes.len();
-    let mut acc: usize = 0;
-    for (i, byte) in bytes.iter().enumerate() {
-        acc |= (*byte as usize) << (n - 1 - i) * 8;
+impl Tolerance {
+    /// Return true if `v` is close enough to `target`.
+    pub fn accepts(&self, v: f64, target: f64) -> bool {
+        let d = (v - target).abs();
+        d <= self.absolute || d <= self.relative * target.abs()
     }
-    acc
 }
 
-/// Convert a number to its binary representation.
-pub fn to_binary(x: usize) -> BitVec {
-    let mut n = x;
-    let mut bytes: Vec<u8> = Vec::new();
-    while (n > 0) {
-        bytes.push((n & 0xff) as u8);
-        n >>= 8;
+/// The default objective: `1 / (1 + |v - target|)`, where `v` is the value
+/// the expression evaluates to, or 1 if `v` is within `tolerance` of the
+/// target. Malformed expressions score 0.
+///
+/// In `EvalMode::Exact`, only an expression whose exact value is the target
+/// scores 1, and the tolerance is ignored. So it is in `EvalMode::Integer`,
+/// where an expression that overflows or divides unevenly scores 0.
+#[derive(Debug,Clone)]
+pub struct Closeness {
+    pub target: f64,
+    pub tolerance: Tolerance,
+    pub mode: EvalMode,
+}
+
+impl Closeness {
+    pub fn new(target: f64) -> Closeness { Closeness::within(target, Tolerance::default()) }
+
+    /// Return the closeness to `target`, accepting values within `tolerance`
+    /// as solutions.
+    pub fn within(target: f64, tolerance: Tolerance) -> Closeness {
+        Closeness { target, tolerance, mode: EvalMode::Float }
+    }
+
+    /// Return the closeness to `target` in exact arithmetic.
+    pub fn exact(target: f64) -> Closeness {
+        Closeness { mode: EvalMode::Exact, ..Closeness::new(target) }
+    }
+
+    /// Return the closeness to the whole number `target` in checked integer
+    /// arithmetic.
+    pub fn integer(target: f64) -> Closeness {
+        Closeness { mode: EvalMode::Integer, ..Closeness::new(target) }
+    }
+
+    /// Return the fitness of `expr` in integer arithmetic.
+    fn integer_fitness(&self, expr: &str) -> f64 {
+        let v = match expr::eval_i64(expr) {
+            Ok(v) => v,
+            Err(_) => return 0f64,
+        };
+        if v as f64 == self.target && v == self.target as i64 {
+            return 1f64;
+        }
+        let d = (v as f64 - self.target).abs();
+        (1f64 / (1f64 + d)).min(1f64 - 2f64 * EPSILON)
+    }
+
+    /// Return the fitness of `expr` in exact arithmetic.
+    fn exact_fitness(&self, expr: &str) -> f64 {
+        let v = match expr::eval_exact(expr) {
+            Ok(v) => v,
+            Err(_) => return 0f64,
+        };
+        if exact::decimal(self.target).is_some_and(|target| target == v) {
+            return 1f64;
+        }
+        // Keep near misses from passing for solutions.
+        let d = (exact::to_f64(&v) - self.target).abs();
+        if d.is_nan() { 0f64 } else { (1f64 / (1f64 + d)).min(1f64 - 2f64 * EPSILON) }
     }
-    BitVec::from_bytes(&bytes)
 }
 
-/// Return a string of 0s and 1s, given a BitVec.
-pub fn bitstring(b: &BitVec) -> String {
-    let mut ret = String::new();
-    for bit in b.iter() {
-        ret.push(if bit { '1' } else { '0' });
+/// Closeness to a whole number too large for an `f64` to hold exactly, like
+/// `2**200`: expressions are evaluated in arbitrary precision, and only one
+/// evaluating to exactly `target` scores 1.
+#[derive(Debug,Clone)]
+pub struct BigCloseness {
+    pub target: BigInt,
+}
+
+impl BigCloseness {
+    pub fn new(target: BigInt) -> BigCloseness { BigCloseness { target } }
+}
+
+impl Fitness for BigCloseness {
+    fn fitness(&self, expr: &str) -> f64 {
+        let v = match expr::eval_exact(expr) {
+            Ok(v) => v,
+            Err(_) => return 0f64,
+        };
+        if v.is_integer() && v.to_integer() == self.target {
+            return 1f64;
+        }
+        let d = exact::to_f64(&(v - BigRational::from_integer(self.target.clone())).abs());
+        if d.is_nan() { 0f64 } else { (1f64 / (1f64 + d)).min(1f64 - 2f64 * EPSILON) }
     }
-    ret
 }
 
+impl Fitness for Closeness {
+    fn fitness(&self, expr: &str) -> f64 {
+        match self.mode {
+            EvalMode::Exact => return self.exact_fitness(expr),
+            EvalMode::Integer => return self.integer_fitness(expr),
+            EvalMode::Float => {}
+        }
+        expr::eval(expr)
+            .map(|v| -> f64 {
+                // NaN can result because of a divide by zero.
+                if v.is_nan() {
+                    0f64
+                } else if self.tolerance.accepts(v, self.target) {
+                    1f64
+                } else {
+                    1f64 / (1f64 + (v - self.target).abs())
+                }
+            })
+            .unwrap_or(0f64)
+    }
+}
+
+/// How expressions that are well-formed but have no value, like `1/0` or
+/// `sqrt(-1)`, are scored.
+#[derive(Debug,Clone,Copy,PartialEq,Default)]
+pub enum UndefinedPolicy {
+    /// They score 0, like malformed expressions without partial credit.
+    #[default]
+    Zero,
+    /// They get this much partial credit, like malformed expressions do in
+    /// `Graded`, so that `6*7/0` scores higher than `1/0`.
+    Penalty(f64),
+}
+
+/// A fitness function giving partial credit to malformed expressions, to
+/// lead the search out of the flat region where they all score 0.
+///
+/// A malformed expression scores `credit * fitness(p) * |p| / |e|`, where `p`
+/// is its longest well-formed prefix, so that longer valid parts score
+/// higher. With `credit` below 1, a malformed expression is never a
+/// solution. Without a credit, scores are passed through unchanged.
+/// Undefined expressions are scored according to an `UndefinedPolicy`.
+pub struct Graded<'a, F: Fitness + ?Sized + 'a> {
+    fitness: &'a F,
+    credit: Option<f64>,
+    undefined: UndefinedPolicy,
+}
+
+impl<'a, F: Fitness + ?Sized> Graded<'a, F> {
+    pub fn new(fitness: &'a F, credit: Option<f64>) -> Graded<'a, F> {
+        Graded { fitness, credit, undefined: UndefinedPolicy::Zero }
+    }
+
+    /// Return this fitness function with undefined expressions scored
+    /// according to `undefined`.
+    pub fn with_undefined(self, undefined: UndefinedPolicy) -> Graded<'a, F> {
+        Graded { undefined, ..self }
+    }
+}
 
-/// Decodes a 4 bit number to a string symbol it represents. Returns the empty
-/// string for invalid numbers.
-/// For n from 0 through 9, returns the string representation of the digit.
-/// For n = 10 through 14, the operators "+", "-", "*", "/", "**" are returned
-/// in that order.
-fn get_symbol(n: u8) -> String {
-    match n {
-        0 ... 9 => n.to_string(),
-             10 => String::from("+"),
-             11 => String::from("-"),
-             12 => String::from("*"),
-             13 => String::from("/"),
-             14 => String::from("**"),
-              _ => String::from(""),
+impl<'a, F: Fitness + ?Sized> Fitness for Graded<'a, F> {
+    fn fitness(&self, e: &str) -> f64 {
+        if self.credit.is_none() && self.undefined == UndefinedPolicy::Zero {
+            return self.fitness.fitness(e);
+        }
+        // Whether an expression is malformed is a matter of syntax alone;
+        // valuing it, variables and all, is up to the wrapped fitness.
+        let credit = if expr::validate(e).is_ok() {
+            match (expr::eval(e), self.undefined) {
+                (Err(ref err), UndefinedPolicy::Penalty(penalty)) if err.is_undefined() => penalty,
+                (Err(ref err), UndefinedPolicy::Zero) if err.is_undefined() => return 0f64,
+                _ => return self.fitness.fitness(e),
+            }
+        } else {
+            match self.credit {
+                Some(credit) => credit,
+                None => return self.fitness.fitness(e),
+            }
+        };
+        let prefix = e.char_indices()
+                      .map(|(i, c)| &e[..i + c.len_utf8()])
+                      .rev()
+                      .skip(1)
+                      .find(|p| expr::validate(p).is_ok());
+        match prefix {
+            Some(p) => credit * self.fitness.fitness(p) * p.len() as f64 / e.len() as f64,
+            None => 0f64,
+        }
     }
+
+    fn cases(&self, e: &str) -> Vec<f64> { self.fitness.cases(e) }
 }
 
+/// Parameters controlling a GA run.
+#[derive(Debug,Clone)]
+pub struct GaConfig {
+    /// Number of chromosomes in each generation.
+    pub popsize: usize,
+    /// Number of generations after which `ga` gives up.
+    pub max_gens: usize,
+    /// Probability of flipping each bit during mutation.
+    pub mutation_rate: f64,
+    /// Probability of crossing over a pair of selected parents.
+    pub crossover_rate: f64,
+    /// Number of the fittest chromosomes copied unchanged into the next
+    /// generation.
+    pub elitism: usize,
+    /// How parents are picked from the population.
+    pub selection: Selection,
+    /// How a pair of parents is recombined.
+    pub crossover: CrossoverStrategy,
+    /// If set, `mutation_rate` is raised whenever the population's diversity
+    /// drops too low.
+    pub adaptive_mutation: Option<AdaptiveMutation>,
+    /// Mutations acting on whole genes, applied on top of bit flips.
+    pub gene_mutation: GeneMutation,
+    /// How expressions are laid out in the bits of each chromosome.
+    pub encoding: Encoding,
+    /// How each generation replaces the one before it.
+    pub mode: GaMode,
+    /// If set, restart most of the population when progress stalls.
+    pub restart: Option<Restart>,
+    /// Number of the fittest distinct chromosomes ever seen that are kept
+    /// and returned at the end of a run.
+    pub hall_of_fame: usize,
+    /// If set, share fitness among similar chromosomes when selecting
+    /// parents in generational mode.
+    pub sharing: Option<Sharing>,
+    /// If set, duplicates are removed from each new generation and replaced
+    /// with random chromosomes.
+    pub dedup: Option<Dedup>,
+    /// Fitness penalty per gene of chromosome length, or per unit of
+    /// `parsimony_size`, applied when picking parents and survivors, so that
+    /// compact expressions are preferred.
+    pub parsimony: f64,
+    /// What the parsimony penalty is charged for.
+    pub parsimony_size: Size,
+    /// When to stop a run. If unset, `ga` stops as soon as a solution is
+    /// found or after `max_gens` generations. An empty `Termination::Any`,
+    /// which could never be met, stands for `Generations(max_gens)`.
+    pub termination: Option<Termination>,
+    /// How the chromosomes of the initial population (and of restarts) are
+    /// generated.
+    pub initialization: Initialization,
+    /// How the lengths of those chromosomes are distributed.
+    pub lengths: LengthDistribution,
+    /// If set, the fittest chromosomes of each new generation are improved
+    /// by hill climbing before it is used.
+    pub local_search: Option<LocalSearch>,
+    /// If set, numeric constants in offspring are tuned arithmetically.
+    pub constant_tuning: Option<ConstantTuning>,
+    /// Expressions encoded into the first chromosomes of the initial
+    /// population, to start the search near known partial solutions.
+    pub seeds: Vec<String>,
+    /// If true, fitness is memoized by decoded expression for the whole
+    /// run, so that expressions seen before are not scored again.
+    pub cache: bool,
+    /// If set, malformed expressions get this much partial credit (see
+    /// `Graded`).
+    pub partial_credit: Option<f64>,
+    /// How expressions dividing by zero or otherwise undefined are scored.
+    pub undefined: UndefinedPolicy,
+    /// Number of distinct solutions (by canonical form) to collect before a
+    /// run counts as solved; above 1, the run goes on after the first.
+    pub solutions: usize,
+}
 
-/// Decodes a bitvec into an expression. Note that the expression returned
-/// may very well be malformed. All this function does is go over bit
-/// quadruplets, substituting each with the value returned from `get_symbol()`.
-fn decode(b: &BitVec) -> String {
-    let mut e = String::new();
-    for byte in b.to_bytes().iter() {
-        let hi = 0xf & ((*byte as i32) >> 4);
-        let lo = 0xf & (*byte as i32);
-        e.push_str(&get_symbol(hi as u8));
-        e.push_str(&get_symbol(lo as u8));
+impl Default for GaConfig {
+    fn default() -> GaConfig {
+        GaConfig {
+            popsize: POPSIZE,
+            max_gens: MAX_GENS,
+            mutation_rate: MUTATION_RATE,
+            crossover_rate: CROSSOVER_RATE,
+            elitism: 0,
+            selection: Selection::Roulette,
+            crossover: CrossoverStrategy::SinglePoint,
+            adaptive_mutation: None,
+            gene_mutation: GeneMutation::default(),
+            encoding: Encoding::default(),
+            mode: GaMode::Generational,
+            restart: None,
+            hall_of_fame: HALL_OF_FAME,
+            sharing: None,
+            dedup: None,
+            parsimony: 0f64,
+            parsimony_size: Size::Genes,
+            termination: None,
+            initialization: Initialization::Random,
+            lengths: LengthDistribution::default(),
+            local_search: None,
+            constant_tuning: None,
+            seeds: Vec::new(),
+            cache: false,
+            partial_credit: None,
+            undefined: UndefinedPolicy::Zero,
+            solutions: 1,
+        }
     }
-    e
 }
 
-/// Try to evaluate the expression encoded in a bit vector and return it.
-fn value(b: &BitVec) -> Option<f64> { expr::eval(&decode(b)).ok() }
+impl GaConfig {
+    /// Return the mutation rate to use when breeding from `population`.
+    fn mutation_rate_for(&self, population: &[Chromosome]) -> f64 {
+        match self.adaptive_mutation {
+            Some(ref adaptive) => adaptive.rate(self.mutation_rate, diversity(population)),
+            None => self.mutation_rate,
+        }
+    }
 
-/// Roulette select a chromosome from a population.
-fn select<'a>(population: &'a [Chromosome], total_fitness: f64) -> &'a Chromosome {
-    loop {
-        let slice = randrange(0.0, 1.0) * total_fitness;
-        let mut acc = 0f64;
-        for c in population {
-            acc += c.fitness;
-            if acc >= slice {
-                return c;
-            }
+    /// Return `fitness` graded as configured by `partial_credit` and
+    /// `undefined`.
+    pub fn graded<'a, F: Fitness + ?Sized>(&self, fitness: &'a F) -> Graded<'a, F> {
+        Graded::new(fitness, self.partial_credit).with_undefined(self.undefined)
+    }
+
+    /// Return the criteria for stopping a run configured by `self`.
+    pub fn termination(&self) -> Termination {
+        match self.termination {
+            Some(ref termination) => termination.clone().bounded(self.max_gens),
+            None => Termination::Any(vec![self.solved(), Termination::Generations(self.max_gens)]),
+        }
+    }
+
+    /// Return the criterion for a solved run: `Solved`, or `Solutions` if
+    /// more than one solution is wanted.
+    pub fn solved(&self) -> Termination {
+        if self.solutions > 1 {
+            Termination::Solutions(self.solutions)
+        } else {
+            Termination::Solved
+        }
+    }
+
+    /// Return chromosomes encoding the seed expressions, or an error if one
+    /// of them cannot be encoded.
+    pub fn seed_chromosomes<F: Fitness + ?Sized>(&self, fitness: &F)
+            -> Result<Vec<Chromosome>, String> {
+        let encoding = Arc::new(self.encoding.clone());
+        self.seeds.iter().map(|e| {
+            let bits = encoding.encode(e)?;
+            Ok(Chromosome::with_encoding(bits, encoding.clone(), fitness))
+        }).collect()
+    }
+
+    /// Return this configuration with the operators of its symbol table
+    /// restricted to `allowed`, e.g., to forbid division and
+    /// exponentiation. Genes for the other operators are dropped from the
+    /// table, so that no chromosome can express them. It is an error to
+    /// allow an operator the table lacks, or to restrict a grammar encoding.
+    pub fn with_operators(mut self, allowed: &[&str]) -> Result<GaConfig, String> {
+        if self.encoding.grammar.is_some() {
+            return Err("Cannot restrict the operators of a grammar".to_string());
+        }
+        let operators = self.encoding.symbols.operators();
+        if let Some(op) = allowed.iter().find(|op| !operators.contains(op)) {
+            return Err(format!("Unknown operator: {}", op));
+        }
+        self.encoding.symbols = self.encoding.symbols.with_operators(allowed);
+        Ok(self)
+    }
+
+    /// Return the fitness of `c` less the parsimony penalty for its size.
+    fn penalized(&self, c: &Chromosome) -> f64 {
+        let size = match self.parsimony_size {
+            Size::Genes => c.genes(),
+            Size::Nodes => expr::ast::parse(&c.decode()).map(|e| expr::metrics(&e).nodes)
+                                                      .unwrap_or_else(|_| c.genes()),
+        };
+        (c.fitness - self.parsimony * size as f64).max(0f64)
+    }
+
+    /// Return copies of `population` with the parsimony penalty applied to
+    /// their fitness, or None if there is no penalty.
+    fn parsimonious(&self, population: &[Chromosome]) -> Option<Vec<Chromosome>> {
+        if self.parsimony <= 0f64 {
+            return None;
         }
+        Some(population.iter().map(|c| Chromosome { fitness: self.penalized(c), ..c.clone() })
+                       .collect())
     }
 }
 
+/// How an expression is laid out in the bits of a chromosome.
+#[derive(Debug,Clone,Default)]
+pub struct Encoding {
+    /// If true, every chromosome starts with 8 control bits holding its own
+    /// mutation rate, on a log scale from 0.0001 to 0.25. The control bits
+    /// are inherited and mutated like any others, so the rate itself evolves;
+    /// they are not part of the encoded expression.
+    pub self_adaptive: bool,
+    /// The symbols that genes stand for.
+    pub symbols: SymbolTable,
+    /// If set, chromosomes are decoded by grammatical evolution with this
+    /// grammar instead of symbol by symbol, so that every chromosome is a
+    /// well-formed expression.
+    pub grammar: Option<Grammar>,
+    /// If set, common defects in expressions decoded symbol by symbol are
+    /// repaired before evaluation.
+    pub repair: Option<Repair>,
+}
 
-impl Chromosome {
-    /// Construct a new Chromosome from a bit pattern and a target number.
-    pub fn new(bits: BitVec, target: f64) -> Chromosome {
-        let fitness = value(&bits)
-                      .map(|v| -> f64 {
-                          // NaN can result because of a divide by zero.
-                          if v.is_nan() {
-                              0f64
-                          } else {
-                              1f64 / (1f64 + (v - target).abs())
-                          }
-                      })
-                      .unwrap_or(0f64);
-        Chromosome { bits: bits, fitness: fitness }
-    }
-
-    /// Construct a Chromosome with a random bit pattern, given a target number.
-    pub fn random(target: f64) -> Chromosome {
-        let size = thread_rng().gen_range(CHROMOSOME_MIN, CHROMOSOME_MAX) * 4;
-        let bits = BitVec::from_fn(size, |_| randbit());
-        Chromosome::new(bits, target)
-    }
-
-    /// Return the expression (possibly malformed) represented by this chromosome.
-    pub fn decode(&self) -> String { decode(&self.bits) }
+/// The symbols that the values of 4-bit genes stand for. A gene holding `v`
+/// stands for the symbol at index `v % len()`, so a table may have fewer than
+/// 16 symbols, but not more.
+///
+/// The default table has the digits 0-9, `+`, `-`, `*`, `/`, `**` and an
+/// empty symbol, in that order, and decodes genes without separators.
+#[derive(Debug,Clone,PartialEq)]
+pub struct SymbolTable {
+    symbols: Vec<String>,
+    separator: String,
+}
 
-    /// Return the value that the expression encoded by this chromosome evaluates
-    /// to. If the encoded expression is malformed, return None.
-    pub fn value(&self) -> Option<f64> { value(&self.bits) }
+impl Default for SymbolTable {
+    fn default() -> SymbolTable {
+        let mut symbols = (0..10).map(|d: u8| d.to_string()).collect::<Vec<_>>();
+        symbols.extend(["+", "-", "*", "/", "**", ""].iter().map(|s| s.to_string()));
+        SymbolTable { symbols, separator: String::new() }
+    }
+}
 
-    /// Crossover two chromosomes according to CROSSOVER_RATE.
-    /// This is one cause of variation in the gene pool.
-    pub fn crossover(&self, them: &Chromosome, target: f64) -> (Chromosome, Chromosome) {
-        if randrange(0.0, 1.0) >= CROSSOVER_RATE {
-            return ((*self).clone(), (*them).clone());
+impl SymbolTable {
+    /// Create a table from a list of symbols.
+    ///
+    /// Panics if there are no symbols, or more than a gene can address.
+    pub fn new<S: Into<String>>(symbols: Vec<S>) -> SymbolTable {
+        assert!(!symbols.is_empty() && symbols.len() <= 1 << GENE_BITS,
+                "a symbol table must have between 1 and {} symbols", 1 << GENE_BITS);
+        SymbolTable {
+            symbols: symbols.into_iter().map(|s| s.into()).collect(),
+            separator: String::new(),
         }
+    }
+
+    /// Return the default table with `(` and `)` in place of `**` and the
+    /// empty symbol, so that expressions can group terms. Pair it with
+    /// `Repair` to fix unbalanced parentheses.
+    pub fn parenthesized() -> SymbolTable {
+        SymbolTable::default().without(&["**", ""]).with(&["(", ")"])
+    }
+
+    /// Return a copy of this table that puts `separator` between the symbols
+    /// of a decoded expression, e.g., a space, to keep numbers apart. The
+    /// separator should be whitespace, which `encode()` skips.
+    pub fn with_separator(&self, separator: &str) -> SymbolTable {
+        SymbolTable { separator: separator.to_string(), ..self.clone() }
+    }
+
+    /// Return a copy of this table with the modulo operator `%` in place of
+    /// the empty symbol, or an error if the table has neither and is full.
+    pub fn with_modulo(&self) -> Result<SymbolTable, String> {
+        if self.symbols.iter().any(|s| s == "%") {
+            return Ok(self.clone());
+        }
+        let table = self.without(&[""]);
+        if table.len() == 1 << GENE_BITS {
+            return Err("No room for % in the symbol table".to_string());
+        }
+        Ok(table.with(&["%"]))
+    }
+
+    /// Return a copy of this table with `extra` symbols appended.
+    pub fn with(&self, extra: &[&str]) -> SymbolTable {
+        let mut symbols = self.symbols.clone();
+        symbols.extend(extra.iter().map(|s| s.to_string()));
+        SymbolTable::new(symbols).with_separator(&self.separator)
+    }
+
+    /// Return a copy of this table without any of the `unwanted` symbols.
+    pub fn without(&self, unwanted: &[&str]) -> SymbolTable {
+        SymbolTable::new(self.symbols.iter()
+                             .filter(|s| !unwanted.contains(&s.as_str()))
+                             .cloned()
+                             .collect())
+            .with_separator(&self.separator)
+    }
+
+    /// Return a copy of this table without the operators not in `allowed`.
+    pub fn with_operators(&self, allowed: &[&str]) -> SymbolTable {
+        let unwanted = self.operators().into_iter()
+                                       .filter(|op| !allowed.contains(op))
+                                       .collect::<Vec<_>>();
+        self.without(&unwanted)
+    }
+
+    /// Return the symbols, in order of gene value.
+    pub fn symbols(&self) -> &[String] { &self.symbols }
 
-        let m = self.bits.len();
-        let n = them.bits.len();
-        let k = cmp::max(m, n);
-        let lim = thread_rng().gen_range(0, k);
+    /// Return the operator symbols, in order of gene value.
+    pub fn operators(&self) -> Vec<&str> {
+        (0..self.symbols.len()).filter(|&g| self.is_operator(g))
+                               .map(|g| self.symbol(g))
+                               .collect()
+    }
+
+    /// Return the number of symbols.
+    pub fn len(&self) -> usize { self.symbols.len() }
+
+    pub fn is_empty(&self) -> bool { self.symbols.is_empty() }
+
+    /// Return the symbol a gene holding `value` stands for.
+    pub fn symbol(&self, value: usize) -> &str { &self.symbols[value % self.symbols.len()] }
+
+    /// Decode a bitvec into an expression. Note that the expression returned
+    /// may very well be malformed. All this does is go over bit quadruplets,
+    /// substituting each with the symbol it stands for. Decoding starts at
+    /// bit `start`, and trailing bits that do not make up a whole quadruplet
+    /// are ignored.
+    pub fn decode(&self, b: &BitVec, start: usize) -> String {
+        self.concat(&genes(b, start))
+    }
+
+    /// Return the symbols that `genes` stand for, concatenated.
+    pub fn concat(&self, genes: &[usize]) -> String {
+        genes.iter().map(|&g| self.symbol(g)).collect::<Vec<_>>().join(&self.separator)
+    }
 
-        let mut b1 = BitVec::new();
-        for i in 0..cmp::min(m, lim+1) {
-            b1.push(self.bits.get(i).unwrap()); 
+    /// Fix the common defects of a sequence of genes: drop genes standing
+    /// for an empty symbol, operators at the start or end of the expression
+    /// or next to a parenthesis on the inside, operators directly after
+    /// another operator, unbalanced parentheses and empty pairs of them.
+    pub fn repair(&self, genes: &[usize]) -> Vec<usize> {
+        let mut repaired = self.repair_operators(genes);
+        // Dropping parentheses can leave operators out of place, and the
+        // other way around, so go on until nothing changes.
+        loop {
+            let balanced = self.balance(&repaired);
+            if balanced == repaired {
+                return repaired;
+            }
+            repaired = self.repair_operators(&balanced);
         }
+    }
 
-        let mut b2 = BitVec::new();
-        for i in 0..cmp::min(n, lim+1) {
-            b2.push(them.bits.get(i).unwrap());
+    /// Drop closing parentheses without a match, the opening ones left
+    /// unclosed, and empty pairs.
+    fn balance(&self, genes: &[usize]) -> Vec<usize> {
+        let mut balanced: Vec<usize> = Vec::with_capacity(genes.len());
+        let mut open = Vec::new();
+        for &g in genes {
+            match self.symbol(g) {
+                "(" => {
+                    open.push(balanced.len());
+                    balanced.push(g);
+                }
+                ")" => {
+                    match open.pop() {
+                        Some(i) if i + 1 == balanced.len() => { balanced.pop(); }
+                        Some(_) => balanced.push(g),
+                        None => {}
+                    }
+                }
+                _ => balanced.push(g),
+            }
+        }
+        for i in open.into_iter().rev() {
+            balanced.remove(i);
         }
+        balanced
+    }
 
-        for i in lim..k {
-            if i < m {
-                b2.push(self.bits.get(i).unwrap());
+    /// Drop empty symbols and operators without an operand on either side.
+    fn repair_operators(&self, genes: &[usize]) -> Vec<usize> {
+        let is_op = |g: usize| self.is_operator(g);
+        let mut forward: Vec<usize> = Vec::with_capacity(genes.len());
+        for &g in genes {
+            if self.symbol(g).is_empty() {
+                continue;
+            }
+            let after_op = forward.last().is_none_or(|&p| is_op(p) || self.symbol(p) == "(");
+            if !(is_op(g) && after_op) {
+                forward.push(g);
+            }
+        }
+        let mut repaired: Vec<usize> = Vec::with_capacity(forward.len());
+        for &g in forward.iter().rev() {
+            if !(is_op(g) && repaired.last().is_none_or(|&n| self.symbol(n) == ")")) {
+                repaired.push(g);
             }
-            if i < n {
-                b1.push(them.bits.get(i).unwrap());
+        }
+        repaired.reverse();
+        repaired
+    }
+
+    /// Return the genes spelling out `e`, matching the longest symbol first at
+    /// each point and ignoring whitespace, or an error if some part of `e` is
+    /// not in the table.
+    pub fn encode(&self, e: &str) -> Result<Vec<usize>, String> {
+        let mut genes = Vec::new();
+        let mut rest = e.trim_start();
+        while !rest.is_empty() {
+            let gene = (0..self.symbols.len())
+                .filter(|&g| !self.symbols[g].is_empty() && rest.starts_with(&self.symbols[g]))
+                .max_by_key(|&g| self.symbols[g].len())
+                .ok_or_else(|| format!("Cannot encode \"{}\" in \"{}\"", rest, e))?;
+            genes.push(gene);
+            rest = rest[self.symbols[gene].len()..].trim_start();
+        }
+        Ok(genes)
+    }
+
+    /// Return true if `gene` stands for a binary operator.
+    pub fn is_operator(&self, gene: usize) -> bool {
+        let s = self.symbol(gene);
+        !s.is_empty() && s.chars().all(|c| "+-*/%<>=!&|^".contains(c))
+    }
+
+    /// Return true if `gene` stands for a variable, e.g., `x`.
+    pub fn is_variable(&self, gene: usize) -> bool {
+        let s = self.symbol(gene);
+        !s.is_empty() && s.chars().all(|c| c.is_alphabetic() || c == '_')
+    }
+
+    /// Return `n` genes (rounded down to an odd number) alternating between
+    /// random numbers or variables and random operators, or None if the
+    /// table lacks either.
+    fn well_formed(&self, n: usize) -> Option<Vec<usize>> {
+        let values = 0..self.symbols.len();
+        let ops = values.clone().filter(|&g| self.is_operator(g)).collect::<Vec<_>>();
+        let numbers = values.filter(|&g| {
+            self.symbol(g).parse::<f64>().is_ok() || self.is_variable(g)
+        }).collect::<Vec<_>>();
+        if ops.is_empty() || numbers.is_empty() {
+            return None;
+        }
+        let mut rng = thread_rng();
+        let n = (n.max(1) - 1) / 2 * 2 + 1;
+        Some((0..n).map(|i| {
+            *rng.choose(if i % 2 == 0 { &numbers } else { &ops }).unwrap()
+        }).collect())
+    }
+
+    /// Return the bits of a gene standing for a random symbol.
+    fn random_gene(&self) -> Vec<bool> {
+        gene_bits(thread_rng().gen_range(0, self.symbols.len()))
+    }
+}
+
+/// How defects in decoded expressions are repaired (see
+/// `SymbolTable::repair()`).
+#[derive(Debug,Clone,PartialEq)]
+pub enum Repair {
+    /// Repair the decoded expression only, leaving the bits alone.
+    Decoded,
+    /// Also write the repaired genes back into the chromosome's bits, so
+    /// that offspring inherit the repair.
+    WriteBack,
+}
+
+impl Encoding {
+    /// Return the number of leading control bits in each chromosome.
+    fn control_bits(&self) -> usize {
+        if self.self_adaptive { CONTROL_BITS } else { 0 }
+    }
+
+    /// Return the expression (possibly malformed) encoded in `b`.
+    fn decode(&self, b: &BitVec) -> String {
+        let start = self.control_bits();
+        match self.grammar {
+            Some(ref grammar) => grammar.decode(b, start),
+            None if self.repair.is_some() =>
+                self.symbols.concat(&self.symbols.repair(&genes(b, start))),
+            None => self.symbols.decode(b, start),
+        }
+    }
+
+    /// Return the bits of a chromosome encoding `e`, with random control bits.
+    pub fn encode(&self, e: &str) -> Result<BitVec, String> {
+        if self.grammar.is_some() {
+            return Err("Expressions cannot be encoded with a grammar".to_string());
+        }
+        Ok(self.from_genes(&self.symbols.encode(e)?))
+    }
+
+    /// Return the bits of a chromosome made up of `genes`, with random
+    /// control bits.
+    pub fn from_genes(&self, genes: &[usize]) -> BitVec {
+        let mut bits = BitVec::from_fn(self.control_bits(), |_| randbit());
+        for &g in genes {
+            bits.extend(gene_bits(g));
+        }
+        bits
+    }
+
+    /// Return `b` with the repaired genes written back, if so configured.
+    fn write_back(&self, b: BitVec) -> BitVec {
+        if self.grammar.is_some() || self.repair != Some(Repair::WriteBack) {
+            return b;
+        }
+        let start = self.control_bits();
+        let mut repaired = b.iter().take(start).collect::<BitVec>();
+        for g in self.symbols.repair(&genes(&b, start)) {
+            repaired.extend(gene_bits(g));
+        }
+        repaired
+    }
+
+    /// Return a mutated copy of `b`, flipping each bit with probability
+    /// `rate` (see `Chromosome::mutate()`) and then applying the gene-level
+    /// mutations in `genes`.
+    fn mutate(&self, b: &BitVec, rate: f64, genes: &GeneMutation) -> BitVec {
+        let b = if self.self_adaptive {
+            let control = flip_bits(b, 0, rate);
+            let own_rate = control_rate(&control);
+            flip_bits(&control, CONTROL_BITS, own_rate)
+        } else {
+            flip_bits(b, 0, rate)
+        };
+        genes.apply(&b, self.control_bits())
+    }
+}
+
+/// Probabilities of mutations that act on whole 4-bit genes rather than on
+/// single bits. Each is the chance of the mutation happening once to an
+/// offspring; all are zero by default.
+#[derive(Debug,Clone,Default)]
+pub struct GeneMutation {
+    /// Insert a random gene at a random position, unless the chromosome
+    /// already has the maximum number of genes.
+    pub insertion: f64,
+    /// Delete a random gene, unless the chromosome already has the minimum
+    /// number of genes.
+    pub deletion: f64,
+    /// Reverse the order of the genes in a random segment.
+    pub inversion: f64,
+    /// Swap two random genes.
+    pub swap: f64,
+    /// Move a random block of genes to a random new position.
+    pub transposition: f64,
+}
+
+impl GeneMutation {
+    /// Apply these mutations to the genes of `b`, which start at bit `start`.
+    fn apply(&self, b: &BitVec, start: usize) -> BitVec {
+        let bits = b.iter().collect::<Vec<_>>();
+        let end = start + (bits.len() - start) / GENE_BITS * GENE_BITS;
+        let mut genes = bits[start..end].chunks(GENE_BITS).map(|g| g.to_vec()).collect::<Vec<_>>();
+        let mut rng = thread_rng();
+        if randrange(0.0, 1.0) < self.insertion && genes.len() < CHROMOSOME_MAX {
+            let at = rng.gen_range(0, genes.len() + 1);
+            genes.insert(at, (0..GENE_BITS).map(|_| randbit()).collect());
+        }
+        if randrange(0.0, 1.0) < self.deletion && genes.len() > CHROMOSOME_MIN {
+            let at = rng.gen_range(0, genes.len());
+            genes.remove(at);
+        }
+        if randrange(0.0, 1.0) < self.inversion && !genes.is_empty() {
+            let (i, j) = (rng.gen_range(0, genes.len()), rng.gen_range(0, genes.len()));
+            genes[cmp::min(i, j)..cmp::max(i, j) + 1].reverse();
+        }
+        if randrange(0.0, 1.0) < self.swap && !genes.is_empty() {
+            let (i, j) = (rng.gen_range(0, genes.len()), rng.gen_range(0, genes.len()));
+            genes.swap(i, j);
+        }
+        if randrange(0.0, 1.0) < self.transposition && !genes.is_empty() {
+            let (i, j) = (rng.gen_range(0, genes.len()), rng.gen_range(0, genes.len()));
+            let block = genes.drain(cmp::min(i, j)..cmp::max(i, j) + 1).collect::<Vec<_>>();
+            let at = rng.gen_range(0, genes.len() + 1);
+            for (k, g) in block.into_iter().enumerate() {
+                genes.insert(at + k, g);
             }
         }
+        bits[..start].iter()
+                     .chain(genes.iter().flat_map(|g| g.iter()))
+                     .chain(bits[end..].iter())
+                     .cloned()
+                     .collect()
+    }
+}
 
-        (Chromosome::new(b1, target), Chromosome::new(b2, target))
+/// Settings for adapting the mutation rate to the diversity of the population,
+/// as measured by `diversity()`.
+#[derive(Debug,Clone)]
+pub struct AdaptiveMutation {
+    /// While diversity is at or above this level, the base mutation rate is
+    /// used.
+    pub threshold: f64,
+    /// The mutation rate used when diversity has collapsed entirely. Between
+    /// zero diversity and `threshold`, the rate is interpolated linearly from
+    /// this down to the base rate.
+    pub max_rate: f64,
+}
+
+impl AdaptiveMutation {
+    /// Return the mutation rate for a population with the given diversity.
+    fn rate(&self, base_rate: f64, diversity: f64) -> f64 {
+        if diversity >= self.threshold {
+            base_rate
+        } else {
+            let collapse = 1f64 - diversity / self.threshold;
+            base_rate + (self.max_rate - base_rate) * collapse
+        }
     }
+}
 
-    /// Return a mutated chromosome, according to MUTATION_RATE.
-    /// This is another cause for variation in the gene pool (the other
-    /// being crossover), although mutations are comparatively very, very
-    /// rare (as reflected in the MUTATION_RATE constant).
-    pub fn mutate(&self, target: f64) -> Chromosome {
-        let b: BitVec = self.bits.iter().map(|bit| -> bool {
-            if randrange(0f64, 1f64) <= MUTATION_RATE { !bit } else { bit }
-        }).collect();
-        Chromosome::new(b, target)
+/// Settings for restarting a run that has stagnated.
+#[derive(Debug,Clone)]
+pub struct Restart {
+    /// Restart once the best fitness seen has not improved for this many
+    /// generations.
+    pub window: usize,
+    /// Number of the fittest chromosomes kept on restarting; the rest of the
+    /// population is replaced with random chromosomes.
+    pub keep: usize,
+}
+
+/// Settings for fitness sharing, which divides each chromosome's fitness by
+/// the number of similar chromosomes in the population (its niche count)
+/// before selection, so that no single kind of expression can take over.
+#[derive(Debug,Clone)]
+pub struct Sharing {
+    /// Chromosomes at least this far apart do not share fitness. Closer
+    /// pairs share in proportion to `1 - distance / radius`.
+    pub radius: f64,
+    /// How distance between chromosomes is measured.
+    pub similarity: Similarity,
+}
+
+/// Measures of distance between two chromosomes.
+#[derive(Debug,Clone)]
+pub enum Similarity {
+    /// Hamming distance between the bits, as a fraction of the longer
+    /// chromosome's length (see `hamming()`).
+    Hamming,
+    /// Absolute difference between the values of the decoded expressions.
+    /// Malformed expressions are all at distance 0 from each other, and
+    /// infinitely far from well-formed ones.
+    Value,
+}
+
+impl Sharing {
+    /// Return copies of the chromosomes in `population` with their fitness
+    /// divided by their niche counts.
+    fn share(&self, population: &[Chromosome]) -> Vec<Chromosome> {
+        let n = population.len();
+        let distance: Box<dyn Fn(usize, usize) -> f64> = match self.similarity {
+            Similarity::Hamming => Box::new(move |i, j| {
+                let (a, b) = (&population[i].genome.bits, &population[j].genome.bits);
+                hamming(a, b) as f64 / cmp::max(1, cmp::max(a.len(), b.len())) as f64
+            }),
+            Similarity::Value => {
+                let values = population.iter().map(|c| c.value()).collect::<Vec<_>>();
+                Box::new(move |i, j| match (values[i], values[j]) {
+                    (Some(a), Some(b)) => (a - b).abs(),
+                    (None, None) => 0f64,
+                    _ => f64::INFINITY,
+                })
+            }
+        };
+        let mut niche = vec![1f64; n];
+        for i in 0..n {
+            for j in i + 1..n {
+                let d = distance(i, j);
+                if d < self.radius {
+                    let share = 1f64 - d / self.radius;
+                    niche[i] += share;
+                    niche[j] += share;
+                }
+            }
+        }
+        population.iter().zip(niche).map(|(c, count)| {
+            let mut shared = c.clone();
+            shared.fitness /= count;
+            shared
+        }).collect()
     }
 }
 
-/// Breed one generation of chromosomes and return the new population.
-fn ga_epoch(population: &[Chromosome], target: f64) -> Vec<Chromosome> {
-    let fitness: f64 = population.iter()
-                                 .map(|c| c.fitness)
-                                 .fold(0f64, |a, b| a + b);
-    let mut new_population = Vec::new();
-    loop {
-        let (c1, c2) = select(&population, fitness).crossover(
-            select(&population, fitness),
-            target
-        );
-        let (c1, c2) = (c1.mutate(target), c2.mutate(target));
-        new_population.push(c1);
-        new_population.push(c2);
-        if new_population.len() >= population.len() {
+/// Return the Hamming distance between two bit vectors, counting the extra
+/// bits of the longer one as differences.
+pub fn hamming(a: &BitVec, b: &BitVec) -> usize {
+    let common = cmp::min(a.len(), b.len());
+    let mut differing = cmp::max(a.len(), b.len()) - common;
+    for (k, (x, y)) in a.blocks().zip(b.blocks()).enumerate() {
+        let start = k * 32;
+        if start >= common {
             break;
         }
-    }    
-    new_population
+        let mask = if common - start >= 32 { !0u32 } else { (1u32 << (common - start)) - 1 };
+        differing += ((x ^ y) & mask).count_ones() as usize;
+    }
+    differing
+}
+
+/// How the size of a chromosome is measured for the parsimony penalty.
+#[derive(Debug,Clone,Copy,PartialEq,Default)]
+pub enum Size {
+    /// Its number of genes.
+    #[default]
+    Genes,
+    /// The number of nodes in the tree of its expression (see
+    /// `expr::metrics()`), or its number of genes if that is malformed.
+    Nodes,
+}
+
+/// What makes two chromosomes duplicates of each other.
+#[derive(Debug,Clone)]
+pub enum Dedup {
+    /// They decode to the same expression, up to formatting.
+    Expression,
+    /// They have the same bits.
+    Bits,
+}
+
+impl Dedup {
+    /// Return `population` with all but the first of each set of duplicates
+    /// removed.
+    fn dedup(&self, population: Vec<Chromosome>) -> Vec<Chromosome> {
+        match *self {
+            Dedup::Expression => {
+                let mut seen = HashSet::new();
+                population.into_iter()
+                          .filter(|c| seen.insert(expr::Canonical::new(&c.decode())))
+                          .collect()
+            }
+            Dedup::Bits => {
+                let mut seen = HashSet::new();
+                population.into_iter().filter(|c| seen.insert(c.genome.bits.clone())).collect()
+            }
+        }
+    }
+}
+
+/// Ways of replacing one generation with the next.
+#[derive(Debug,Clone)]
+pub enum GaMode {
+    /// Breed a whole new population every generation.
+    Generational,
+    /// Breed two offspring at a time, each of which immediately replaces a
+    /// member of the population chosen by `replacement`, so that offspring
+    /// can be picked as parents right away. A generation is counted every
+    /// `popsize` offspring, to keep generation counts comparable with the
+    /// generational mode. `GaConfig::elitism` is not used in this mode;
+    /// `Replacement::Worst` never loses the fittest chromosome anyway.
+    SteadyState { replacement: Replacement },
+    /// Deterministic crowding: the population is paired off at random, each
+    /// pair breeds two offspring, and each offspring competes only against
+    /// the parent it is most similar to (by Hamming distance) for a place in
+    /// the next generation. This preserves distinct niches, since a newcomer
+    /// can only displace a chromosome like itself. `GaConfig::selection` and
+    /// `GaConfig::elitism` are not used in this mode.
+    DeterministicCrowding,
+}
+
+/// Policies for choosing which chromosome an offspring replaces in
+/// steady-state mode.
+#[derive(Debug,Clone)]
+pub enum Replacement {
+    /// Replace the least fit chromosome.
+    Worst,
+    /// Replace a chromosome picked uniformly at random.
+    Random,
+}
+
+impl Replacement {
+    /// Return the index of the chromosome in `population` to be replaced.
+    fn victim(&self, population: &[Chromosome]) -> usize {
+        match *self {
+            Replacement::Worst => rank_order(population)[0],
+            Replacement::Random => thread_rng().gen_range(0, population.len()),
+        }
+    }
+}
+
+/// Ways of recombining two parent chromosomes.
+#[derive(Debug,Clone)]
+pub enum CrossoverStrategy {
+    /// Swap the tails of the parents after a random cut point.
+    SinglePoint,
+    /// Swap the segment between two random cut points.
+    TwoPoint,
+    /// Cut the parents at `n` random points, and swap every other segment.
+    NPoint(usize),
+    /// Swap each bit independently with probability `p`.
+    Uniform { p: f64 },
+    /// Apply the given strategy to whole 4-bit genes rather than bits, so
+    /// that the digits and operators encoded in the parents are exchanged
+    /// intact instead of being cut in half.
+    GeneAligned(Box<CrossoverStrategy>),
+}
+
+impl CrossoverStrategy {
+    /// Recombine two bit vectors into two children.
+    fn recombine(&self, a: &BitVec, b: &BitVec) -> (BitVec, BitVec) {
+        self.recombine_units(a, b, 1)
+    }
+
+    /// Recombine two bit vectors into two children, treating each run of
+    /// `unit` bits as indivisible.
+    fn recombine_units(&self, a: &BitVec, b: &BitVec, unit: usize) -> (BitVec, BitVec) {
+        match *self {
+            CrossoverStrategy::GeneAligned(ref inner) => inner.recombine_units(a, b, GENE_BITS),
+            CrossoverStrategy::Uniform { p } => {
+                let mut swapped = false;
+                crossover_by(a, b, |i| {
+                    if i % unit == 0 {
+                        swapped = randrange(0.0, 1.0) < p;
+                    }
+                    swapped
+                })
+            }
+            _ => {
+                let units = cmp::max(a.len(), b.len()).div_ceil(unit);
+                let cuts = self.cut_points(units).iter().map(|c| c * unit).collect::<Vec<_>>();
+                crossover_at(a, b, &cuts)
+            }
+        }
+    }
+
+    /// Return sorted, distinct random cut points for crossing over parents,
+    /// the longer of which has `len` bits.
+    fn cut_points(&self, len: usize) -> Vec<usize> {
+        let n = match *self {
+            CrossoverStrategy::SinglePoint => 1,
+            CrossoverStrategy::TwoPoint    => 2,
+            CrossoverStrategy::NPoint(n)   => n,
+            CrossoverStrategy::Uniform { .. } |
+            CrossoverStrategy::GeneAligned(_) => 0,
+        };
+        if len < 2 {
+            return Vec::new();
+        }
+        // Asking for more cuts than there are positions yields all of them.
+        let mut cuts = seq::sample_iter(&mut thread_rng(), 1..len, n).unwrap_or_else(|all| all);
+        cuts.sort();
+        cuts
+    }
+}
+
+/// The moves tried by `LocalSearch`.
+#[derive(Debug,Clone,PartialEq)]
+pub enum Neighbourhood {
+    /// Flip a single random bit.
+    Bit,
+    /// Replace a single random gene with a random symbol.
+    Gene,
 }
 
-pub fn ga(popsize: usize, target: f64) -> (usize, Option<Chromosome>) {
-    let mut pop = Vec::new();
-    for i in 0..popsize {
-        pop.push(Chromosome::random(target));
+impl Neighbourhood {
+    /// Return a random neighbour of `c`, scored by `fitness`. A chromosome
+    /// without genes is its own only neighbour.
+    pub fn neighbour<F: Fitness + ?Sized>(&self, c: &Chromosome, fitness: &F) -> Chromosome {
+        let start = c.genome.encoding.control_bits();
+        if c.genes() == 0 {
+            return c.clone();
+        }
+        let mut bits = c.genome.bits.clone();
+        match *self {
+            Neighbourhood::Bit => {
+                let i = thread_rng().gen_range(start, bits.len());
+                let bit = bits[i];
+                bits.set(i, !bit);
+            }
+            Neighbourhood::Gene => {
+                let i = start + GENE_BITS * thread_rng().gen_range(0, c.genes());
+                for (j, bit) in c.genome.encoding.symbols.random_gene().into_iter().enumerate() {
+                    bits.set(i + j, bit);
+                }
+            }
+        }
+        Chromosome::with_encoding(bits, c.genome.encoding.clone(), fitness)
+    }
+}
+
+/// Lamarckian hill climbing: each of the `top` fittest chromosomes of a
+/// generation tries `steps` random moves, keeping each one that improves its
+/// fitness. The improvements are written into the chromosome and inherited.
+#[derive(Debug,Clone)]
+pub struct LocalSearch {
+    pub top: usize,
+    pub steps: usize,
+    pub neighbourhood: Neighbourhood,
+}
+
+impl LocalSearch {
+    /// Return `c` after hill climbing on `fitness`.
+    pub fn climb<F: Fitness + ?Sized>(&self, c: &Chromosome, fitness: &F) -> Chromosome {
+        let mut best = c.clone();
+        for _ in 0..self.steps {
+            let candidate = self.neighbourhood.neighbour(&best, fitness);
+            if candidate.fitness > best.fitness {
+                best = candidate;
+            }
+        }
+        best
+    }
+
+    /// Return `population` with its `top` fittest members hill climbed.
+    fn apply<F: Fitness + ?Sized>(&self, mut population: Vec<Chromosome>, fitness: &F)
+            -> Vec<Chromosome> {
+        let mut top = rank_order(&population);
+        top.reverse();
+        top.truncate(self.top);
+        let climbed = top.par_iter().map(|&i| self.climb(&population[i], fitness))
+                         .collect::<Vec<_>>();
+        for (i, c) in top.into_iter().zip(climbed) {
+            population[i] = c;
+        }
+        population
     }
+}
+
+/// A mutation inspired by differential evolution that tunes the numeric
+/// constants (runs of digits) of an expression arithmetically rather than
+/// through bit flips. With probability `rate`, a random constant `x` of an
+/// offspring becomes `x + weight * (y - z)`, rounded to a whole number no
+/// less than 0, where `y` and `z` are random constants of its two parents;
+/// if they are equal, `x` moves by one instead.
+#[derive(Debug,Clone)]
+pub struct ConstantTuning {
+    pub rate: f64,
+    pub weight: f64,
+}
 
-    for i in 0..MAX_GENS {
-        if i % 10 == 9 || i + 10 >= MAX_GENS {
-            println!("Generation {} of {}", i+1, MAX_GENS);
+impl ConstantTuning {
+    /// Return the bits of an offspring of `a` and `b` with a constant tuned.
+    /// Offspring whose constants cannot be re-encoded are left as they are.
+    fn apply(&self, bits: BitVec, a: &Chromosome, b: &Chromosome) -> BitVec {
+        let encoding = &a.genome.encoding;
+        if randrange(0.0, 1.0) >= self.rate || encoding.grammar.is_some() {
+            return bits;
         }
-        for c in pop.iter() {
-            if (1f64 - c.fitness).abs() <= EPSILON {
-                return (i, Some(c.clone()))
+        let symbols = &encoding.symbols;
+        let start = encoding.control_bits();
+        let mut genes = genes(&bits, start);
+        let constants = constants(symbols, &genes);
+        let mut rng = thread_rng();
+        let &(from, to, x) = match rng.choose(&constants) {
+            Some(c) => c,
+            None => return bits,
+        };
+        let mut pick = |c: &Chromosome| {
+            rng.choose(&constants_of(c)).map_or(x, |&(_, _, v)| v)
+        };
+        let (y, z) = (pick(a), pick(b));
+        let tuned = if y != z {
+            x + self.weight * (y - z)
+        } else if randbit() {
+            x + 1f64
+        } else {
+            x - 1f64
+        };
+        let digits = format!("{:.0}", tuned.max(0f64).round());
+        let tuned_genes = digits.chars().map(|d| {
+            (0..symbols.len()).find(|&g| symbols.symbol(g) == d.to_string())
+        }).collect::<Option<Vec<_>>>();
+        match tuned_genes {
+            Some(tuned) if genes.len() - (to - from) + tuned.len() <= CHROMOSOME_MAX => {
+                genes.splice(from..to, tuned);
+                let mut tuned_bits = bits.iter().take(start).collect::<BitVec>();
+                for g in genes {
+                    tuned_bits.extend(gene_bits(g));
+                }
+                tuned_bits
             }
+            _ => bits,
+        }
+    }
+}
+
+/// Return the constants in `genes`, as (first gene, gene past the last,
+/// value), where a constant is a run of genes standing for single digits.
+fn constants(symbols: &SymbolTable, genes: &[usize]) -> Vec<(usize, usize, f64)> {
+    let is_digit = |g: usize| {
+        let s = symbols.symbol(g);
+        s.len() == 1 && s.chars().all(|c| c.is_ascii_digit())
+    };
+    let mut constants = Vec::new();
+    let mut i = 0;
+    while i < genes.len() {
+        if !is_digit(genes[i]) {
+            i += 1;
+            continue;
+        }
+        let from = i;
+        while i < genes.len() && is_digit(genes[i]) {
+            i += 1;
         }
-        pop = ga_epoch(&pop, target);
+        let value = genes[from..i].iter().map(|&g| symbols.symbol(g)).collect::<String>();
+        constants.push((from, i, value.parse().unwrap()));
+    }
+    constants
+}
+
+/// Return the constants in a chromosome (see `constants()`).
+fn constants_of(c: &Chromosome) -> Vec<(usize, usize, f64)> {
+    constants(&c.genome.encoding.symbols, &genes(&c.genome.bits, c.genome.encoding.control_bits()))
+}
+
+/// Ways of generating random chromosomes.
+#[derive(Debug,Clone,PartialEq)]
+pub enum Initialization {
+    /// Uniformly random symbols, which mostly make malformed expressions.
+    Random,
+    /// Random numbers alternating with random operators, so that every
+    /// expression is well-formed. Falls back to `Random` for symbol tables
+    /// without both numbers and operators.
+    WellFormed,
+    /// Half `WellFormed` and half `Random`.
+    HalfAndHalf,
+}
+
+/// How the lengths, in genes, of random chromosomes are distributed.
+#[derive(Debug,Clone,PartialEq)]
+pub enum LengthDistribution {
+    /// Uniformly random between `min` (inclusive) and `max` (exclusive).
+    Uniform { min: usize, max: usize },
+    /// Evenly spread from `min` up to `max` (exclusive) across the
+    /// chromosomes generated together, so there are as many short as long
+    /// ones.
+    Ramped { min: usize, max: usize },
+    /// Always this length.
+    Fixed(usize),
+}
+
+impl Default for LengthDistribution {
+    fn default() -> LengthDistribution {
+        LengthDistribution::Uniform { min: CHROMOSOME_MIN, max: CHROMOSOME_MAX }
     }
-    (MAX_GENS, None)
 }
 
+impl LengthDistribution {
+    /// Return the length of chromosome number `i` out of `n` generated
+    /// together.
+    pub fn length(&self, i: usize, n: usize) -> usize {
+        match *self {
+            LengthDistribution::Uniform { min, max } if max > min =>
+                thread_rng().gen_range(min, max),
+            LengthDistribution::Ramped { min, max } if max > min =>
+                min + i * (max - min) / n.max(1),
+            LengthDistribution::Uniform { min, .. } |
+            LengthDistribution::Ramped { min, .. } => min,
+            LengthDistribution::Fixed(length) => length,
+        }
+    }
+}
+
+/// Criteria for stopping a GA run, checked after every generation.
+#[derive(Debug,Clone)]
+pub enum Termination {
+    /// Stop once a chromosome scoring 1 turns up.
+    Solved,
+    /// Stop once this many distinct solutions have turned up.
+    Solutions(usize),
+    /// Stop after this many generations, counting the initial population.
+    Generations(usize),
+    /// Stop once this much wall-clock time has passed.
+    Time(Duration),
+    /// Stop once the fitness function has been called this many times.
+    Evaluations(usize),
+    /// Stop once the best fitness has not improved for this many
+    /// generations.
+    NoImprovement(usize),
+    /// Stop once a chromosome scores at least this much.
+    FitnessThreshold(f64),
+    /// Stop once any of these criteria is met.
+    Any(Vec<Termination>),
+    /// Stop once all of these criteria are met.
+    All(Vec<Termination>),
+}
+
+impl Termination {
+    /// Return true if a run should stop after `generation`.
+    pub fn is_met<G: Genome>(&self, generation: &Generation<G>) -> bool {
+        self.met_by(generation).is_some()
+    }
+
+    /// If a run should stop after `generation`, return the criterion that
+    /// says so: the first one met among `Any` alternatives, and the whole
+    /// of an `All`.
+    pub fn met_by<G: Genome>(&self, generation: &Generation<G>) -> Option<&Termination> {
+        let met = match *self {
+            Termination::Solved => generation.solution().is_some(),
+            Termination::Solutions(n) => generation.solutions.len() >= n,
+            Termination::Generations(n) => generation.index + 1 >= n,
+            Termination::Time(limit) => generation.elapsed >= limit,
+            Termination::Evaluations(n) => generation.evaluations >= n,
+            Termination::NoImprovement(n) => generation.stagnant >= n,
+            Termination::FitnessThreshold(threshold) => {
+                generation.best().is_some_and(|c| c.fitness >= threshold)
+            }
+            Termination::Any(ref criteria) =>
+                return criteria.iter().filter_map(|t| t.met_by(generation)).next(),
+            Termination::All(ref criteria) => criteria.iter().all(|t| t.is_met(generation)),
+        };
+        if met { Some(self) } else { None }
+    }
+
+    /// Return these criteria with every empty `Any` in them replaced by
+    /// `Generations(max_gens)`.
+    fn bounded(self, max_gens: usize) -> Termination {
+        match self {
+            Termination::Any(ref criteria) if criteria.is_empty() =>
+                Termination::Generations(max_gens),
+            Termination::Any(criteria) =>
+                Termination::Any(criteria.into_iter().map(|t| t.bounded(max_gens)).collect()),
+            Termination::All(criteria) =>
+                Termination::All(criteria.into_iter().map(|t| t.bounded(max_gens)).collect()),
+            termination => termination,
+        }
+    }
+}
+
+/// Strategies for selecting parents from a population.
+#[derive(Debug,Clone)]
+pub enum Selection {
+    /// Fitness proportionate (roulette wheel) selection.
+    Roulette,
+    /// Linear rank selection: the chance of being selected depends only on a
+    /// chromosome's rank by fitness. `pressure`, between 1 and 2, is the
+    /// expected number of times the fittest chromosome is selected per spin
+    /// of the whole population; 1 makes selection uniform.
+    Rank { pressure: f64 },
+    /// Stochastic universal sampling: like roulette wheel selection, but the
+    /// whole mating pool is picked in a single spin with evenly spaced
+    /// pointers, which keeps the number of times each chromosome is picked
+    /// close to its expected value.
+    StochasticUniversal,
+    /// Boltzmann selection: roulette wheel selection over `exp(f/T)`, where
+    /// `f` is the fitness and the temperature `T` starts at `temperature`
+    /// and is multiplied by `decay` every generation. High temperatures keep
+    /// selection close to uniform, preserving diversity early in the run,
+    /// while low temperatures favour the fittest ever more strongly.
+    Boltzmann { temperature: f64, decay: f64 },
+    /// Truncation selection: parents are picked uniformly from the fittest
+    /// `fraction` (between 0 and 1) of the population; at least one
+    /// chromosome always survives.
+    Truncation { fraction: f64 },
+    /// Lexicase selection: each parent is picked by going through the test
+    /// cases of the objective in random order, each time keeping only the
+    /// candidates scoring within `epsilon` of the best on that case, until
+    /// one is left. Chromosomes without cases are compared by fitness.
+    Lexicase { epsilon: f64 },
+}
+
+impl Selection {
+    /// Return the weight with which each chromosome in `population` is to be
+    /// selected at generation number `generation`.
+    fn weights<S: Scored>(&self, population: &[S], generation: usize) -> Vec<f64> {
+        match *self {
+            Selection::Roulette |
+            Selection::StochasticUniversal |
+            Selection::Lexicase { .. } => population.iter()
+                                                        .map(|c| c.score())
+                                                        .collect(),
+            Selection::Rank { pressure } => {
+                let n = population.len();
+                let order = rank_order(population);
+                let mut weights = vec![0f64; n];
+                for (rank, &i) in order.iter().enumerate() {
+                    let r = if n > 1 { rank as f64 / (n - 1) as f64 } else { 1f64 };
+                    weights[i] = 2f64 - pressure + 2f64 * (pressure - 1f64) * r;
+                }
+                weights
+            }
+            Selection::Boltzmann { temperature, decay } => {
+                let t = (temperature * decay.powi(generation as i32)).max(MIN_TEMPERATURE);
+                // Shifting by the maximum fitness keeps exp() from overflowing.
+                let max = population.iter().fold(0f64, |a, c| a.max(c.score()));
+                population.iter().map(|c| ((c.score() - max) / t).exp()).collect()
+            }
+            Selection::Truncation { fraction } => {
+                let n = population.len();
+                let survivors = cmp::max(1, (fraction * n as f64).ceil() as usize);
+                let mut weights = vec![0f64; n];
+                for &i in rank_order(population).iter().rev().take(survivors) {
+                    weights[i] = 1f64;
+                }
+                weights
+            }
+        }
+    }
+
+    /// Pick `n` parents from `population` at generation number `generation`.
+    fn mating_pool<'a, S: Scored>(&self, population: &'a [S], generation: usize, n: usize)
+            -> Vec<&'a S> {
+        if let Selection::Lexicase { epsilon } = *self {
+            return (0..n).map(|_| lexicase(population, epsilon)).collect();
+        }
+        let weights = self.weights(population, generation);
+        let total: f64 = weights.iter().fold(0f64, |a, b| a + b);
+        match *self {
+            Selection::StochasticUniversal => sus(population, &weights, total, n),
+            _ => (0..n).map(|_| select(population, &weights, total)).collect(),
+        }
+    }
+}
+
+/// Anything that can be picked by a `Selection`.
+pub trait Scored {
+    /// Return the fitness to select by.
+    fn score(&self) -> f64;
+    /// Return the scores on each test case, for lexicase selection.
+    fn case_scores(&self) -> &[f64];
+}
+
+impl<G> Scored for Individual<G> {
+    fn score(&self) -> f64 { self.fitness }
+    fn case_scores(&self) -> &[f64] { &self.cases }
+}
+
+/// A single phenotype: a genome together with its scores.
+#[derive(Clone)]
+// See the impls below
+pub struct Individual<G = Bits> {
+    pub genome: G,
+    pub fitness: f64,
+    /// Scores on each test case of the objective, if it has any.
+    pub cases: Vec<f64>,
+}
+
+/// An individual with a bit-string genome, as evolved by `ga()`.
+pub type Chromosome = Individual<Bits>;
+
+fn randrange(lo: f64, hi: f64) -> f64 { thread_rng().gen_range(lo, hi) }
+
+fn randbit() -> bool { randrange(0.0, 1.0) < 0.5 }
+
+/// Convert a number from its binary representation in a BitVec to a usize.
+pub fn from_binary(b: &BitVec) -> usize {
+    let bytes = b.to_bytes();
+    let n = bytes.len();
+    let mut acc: usize = 0;
+    for (i, byte) in bytes.iter().enumerate() {
+        acc |= (*byte as usize) << ((n - 1 - i) * 8);
+    }
+    acc
+}
+
+/// Convert a number to its binary representation.
+pub fn to_binary(x: usize) -> BitVec {
+    let mut n = x;
+    let mut bytes: Vec<u8> = Vec::new();
+    while n > 0 {
+        bytes.push((n & 0xff) as u8);
+        n >>= 8;
+    }
+    BitVec::from_bytes(&bytes)
+}
+
+/// Return a string of 0s and 1s, given a BitVec.
+pub fn bitstring(b: &BitVec) -> String {
+    let mut ret = String::new();
+    for bit in b.iter() {
+        ret.push(if bit { '1' } else { '0' });
+    }
+    ret
+}
+
+
+/// Return true if `fitness` is (within EPSILON of) 1.
+pub fn is_solution(fitness: f64) -> bool { (1f64 - fitness).abs() <= EPSILON }
+
+/// Return the values of the whole genes in `b` from bit `start` on.
+fn genes(b: &BitVec, start: usize) -> Vec<usize> {
+    (start..b.len()).step_by(GENE_BITS)
+                    .filter(|&i| i + GENE_BITS <= b.len())
+                    .map(|i| bits_at(b, i, GENE_BITS))
+                    .collect()
+}
+
+/// Return the bits of a gene holding `value`, most significant bit first.
+fn gene_bits(value: usize) -> Vec<bool> {
+    (0..GENE_BITS).rev().map(|i| value & (1 << i) != 0).collect()
+}
+
+/// Return the number held in the `n` bits of `b` starting at `start`, most
+/// significant bit first.
+fn bits_at(b: &BitVec, start: usize, n: usize) -> usize {
+    (start..start + n).fold(0, |acc, i| (acc << 1) | b.get(i).unwrap() as usize)
+}
+
+/// Return the mutation rate held in the control bits at the start of `b`.
+fn control_rate(b: &BitVec) -> f64 {
+    let x = bits_at(b, 0, CONTROL_BITS) as f64 / ((1 << CONTROL_BITS) - 1) as f64;
+    MIN_SELF_ADAPTIVE_RATE * (MAX_SELF_ADAPTIVE_RATE / MIN_SELF_ADAPTIVE_RATE).powf(x)
+}
+
+/// Return a copy of `b` with each bit from `start` onwards flipped with
+/// probability `rate`.
+fn flip_bits(b: &BitVec, start: usize, rate: f64) -> BitVec {
+    b.iter().enumerate().map(|(i, bit)| -> bool {
+        if i >= start && randrange(0f64, 1f64) <= rate { !bit } else { bit }
+    }).collect()
+}
+
+/// Return the indices of the chromosomes in `population`, least fit first.
+fn rank_order<S: Scored>(population: &[S]) -> Vec<usize> {
+    let mut order = (0..population.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| population[a].score()
+                                        .partial_cmp(&population[b].score())
+                                        .unwrap_or(Ordering::Equal));
+    order
+}
+
+/// Pick `n` chromosomes from a population by stochastic universal sampling,
+/// given the selection weight of each chromosome and their total. The picks
+/// are returned in random order. If all weights are zero, picks are uniform.
+fn sus<'a, S: Scored>(population: &'a [S], weights: &[f64], total: f64, n: usize)
+        -> Vec<&'a S> {
+    if n == 0 {
+        return Vec::new();
+    }
+    let uniform = vec![1f64; population.len()];
+    let (weights, total) = if total > 0f64 {
+        (weights, total)
+    } else {
+        (&uniform[..], population.len() as f64)
+    };
+    let step = total / n as f64;
+    let mut pointer = randrange(0.0, step);
+    let mut acc = 0f64;
+    let mut pool = Vec::with_capacity(n);
+    for (c, w) in population.iter().zip(weights) {
+        acc += *w;
+        while pointer < acc && pool.len() < n {
+            pool.push(c);
+            pointer += step;
+        }
+    }
+    // Rounding can leave the last pointer just past the end of the wheel.
+    while pool.len() < n {
+        pool.push(&population[population.len() - 1]);
+    }
+    thread_rng().shuffle(&mut pool);
+    pool
+}
+
+/// Roulette select a chromosome from a population, given the selection weight
+/// of each chromosome and their total.
+fn select<'a, S: Scored>(population: &'a [S], weights: &[f64], total: f64) -> &'a S {
+    loop {
+        let slice = randrange(0.0, 1.0) * total;
+        let mut acc = 0f64;
+        for (c, w) in population.iter().zip(weights) {
+            acc += *w;
+            if acc >= slice {
+                return c;
+            }
+        }
+    }
+}
+
+
+impl Chromosome {
+    /// Construct a new Chromosome from a bit pattern with the default
+    /// encoding, scored by `fitness`.
+    pub fn new<F: Fitness + ?Sized>(bits: BitVec, fitness: &F) -> Chromosome {
+        Chromosome::with_encoding(bits, Arc::new(Encoding::default()), fitness)
+    }
+
+    /// Construct a new Chromosome from a bit pattern laid out according to
+    /// `encoding`, scored by `fitness`.
+    pub fn with_encoding<F: Fitness + ?Sized>(bits: BitVec, encoding: Arc<Encoding>, fitness: &F)
+            -> Chromosome {
+        let bits = encoding.write_back(bits);
+        Individual::scored(Bits { bits, encoding }, fitness)
+    }
+
+    /// Construct a Chromosome with a random bit pattern and the default
+    /// encoding, scored by `fitness`.
+    pub fn random<F: Fitness + ?Sized>(fitness: &F) -> Chromosome {
+        Chromosome::random_with_encoding(Arc::new(Encoding::default()), fitness)
+    }
+
+    /// Construct a Chromosome with a random bit pattern laid out according to
+    /// `encoding`, scored by `fitness`.
+    pub fn random_with_encoding<F: Fitness + ?Sized>(encoding: Arc<Encoding>, fitness: &F)
+            -> Chromosome {
+        let genes = thread_rng().gen_range(CHROMOSOME_MIN, CHROMOSOME_MAX);
+        Chromosome::random_of_length(encoding, genes, fitness)
+    }
+
+    /// Construct a Chromosome of `genes` random genes laid out according to
+    /// `encoding`, scored by `fitness`.
+    pub fn random_of_length<F: Fitness + ?Sized>(encoding: Arc<Encoding>, genes: usize,
+                                                 fitness: &F) -> Chromosome {
+        let mut bits = BitVec::from_fn(encoding.control_bits(), |_| randbit());
+        for _ in 0..genes {
+            bits.extend(encoding.symbols.random_gene());
+        }
+        Chromosome::with_encoding(bits, encoding, fitness)
+    }
+
+    /// Construct a Chromosome encoding a random well-formed expression (see
+    /// `Initialization::WellFormed`) of about `genes` genes according to
+    /// `encoding`, scored by `fitness`.
+    pub fn well_formed<F: Fitness + ?Sized>(encoding: Arc<Encoding>, genes: usize, fitness: &F)
+            -> Chromosome {
+        match encoding.symbols.well_formed(genes) {
+            Some(genes) if encoding.grammar.is_none() => {
+                let bits = encoding.from_genes(&genes);
+                Chromosome::with_encoding(bits, encoding, fitness)
+            }
+            _ => Chromosome::random_of_length(encoding, genes, fitness),
+        }
+    }
+
+    /// Return the number of genes in this chromosome.
+    pub fn genes(&self) -> usize {
+        let Bits { ref bits, ref encoding } = self.genome;
+        (bits.len() - encoding.control_bits().min(bits.len())) / GENE_BITS
+    }
+
+    /// Return the value that the expression encoded by this chromosome evaluates
+    /// to. If the encoded expression is malformed, return None.
+    pub fn value(&self) -> Option<f64> { expr::eval(&self.decode()).ok() }
+
+    /// Return this chromosome's own mutation rate, if its encoding is
+    /// self-adaptive.
+    pub fn mutation_rate(&self) -> Option<f64> {
+        let Bits { ref bits, ref encoding } = self.genome;
+        if encoding.self_adaptive { Some(control_rate(bits)) } else { None }
+    }
+
+    /// Crossover two chromosomes with probability `rate`, using `strategy`.
+    /// This is one cause of variation in the gene pool.
+    pub fn crossover<F: Fitness + ?Sized>(&self, them: &Chromosome, rate: f64,
+                                          strategy: &CrossoverStrategy, fitness: &F)
+            -> (Chromosome, Chromosome) {
+        if randrange(0.0, 1.0) >= rate {
+            return ((*self).clone(), (*them).clone());
+        }
+        let (b1, b2) = strategy.recombine(&self.genome.bits, &them.genome.bits);
+        (Chromosome::with_encoding(b1, self.genome.encoding.clone(), fitness),
+         Chromosome::with_encoding(b2, self.genome.encoding.clone(), fitness))
+    }
+
+    /// Return a mutated chromosome, flipping each bit with probability `rate`.
+    /// This is another cause for variation in the gene pool (the other
+    /// being crossover), although mutations are comparatively very, very
+    /// rare (as reflected in the MUTATION_RATE constant).
+    ///
+    /// With a self-adaptive encoding, only the control bits are flipped with
+    /// probability `rate`; the rest are then flipped according to the
+    /// (possibly just mutated) rate held in the control bits.
+    pub fn mutate<F: Fitness + ?Sized>(&self, rate: f64, fitness: &F) -> Chromosome {
+        self.mutate_with(rate, &GeneMutation::default(), fitness)
+    }
+
+    /// Like `mutate()`, but also apply the gene-level mutations in `genes`.
+    pub fn mutate_with<F: Fitness + ?Sized>(&self, rate: f64, genes: &GeneMutation, fitness: &F)
+            -> Chromosome {
+        let b = self.genome.encoding.mutate(&self.genome.bits, rate, genes);
+        Chromosome::with_encoding(b, self.genome.encoding.clone(), fitness)
+    }
+}
+
+/// Pick a chromosome from `population` by lexicase selection.
+fn lexicase<S: Scored>(population: &[S], epsilon: f64) -> &S {
+    let mut rng = thread_rng();
+    let score = |c: &S, case: usize| {
+        if c.case_scores().is_empty() {
+            c.score()
+        } else {
+            c.case_scores().get(case).cloned().unwrap_or(0f64)
+        }
+    };
+    let ncases = population.iter().map(|c| c.case_scores().len()).max().unwrap_or(0).max(1);
+    let mut order = (0..ncases).collect::<Vec<_>>();
+    rng.shuffle(&mut order);
+    let mut candidates = population.iter().collect::<Vec<_>>();
+    for case in order {
+        if candidates.len() <= 1 {
+            break;
+        }
+        let best = candidates.iter().fold(0f64, |a, c| a.max(score(c, case)));
+        candidates.retain(|c| score(c, case) >= best - epsilon);
+    }
+    rng.choose(&candidates).cloned().unwrap_or(&population[0])
+}
+
+/// Cross over two bit vectors at the given sorted cut points. The first child
+/// takes bits from `a` up to the first cut, from `b` up to the next cut, and
+/// so on, alternating; the second child takes the complementary bits.
+fn crossover_at(a: &BitVec, b: &BitVec, cuts: &[usize]) -> (BitVec, BitVec) {
+    let mut swapped = false;
+    let mut cuts = cuts.iter().peekable();
+    crossover_by(a, b, |i| {
+        while cuts.peek().is_some_and(|&&c| c == i) {
+            swapped = !swapped;
+            cuts.next();
+        }
+        swapped
+    })
+}
+
+/// Cross over two bit vectors, bit by bit. For each position `i`, in order,
+/// the first child takes the bit from `b` if `swap(i)` is true and from `a`
+/// otherwise; the second child takes the other bit. Where the shorter parent
+/// has run out of bits, nothing is taken from it, so the total number of bits
+/// is preserved but the children's lengths may differ from their parents'.
+fn crossover_by<S: FnMut(usize) -> bool>(a: &BitVec, b: &BitVec, mut swap: S)
+        -> (BitVec, BitVec) {
+    let mut b1 = BitVec::new();
+    let mut b2 = BitVec::new();
+    for i in 0..cmp::max(a.len(), b.len()) {
+        let (x, y) = if swap(i) { (b, a) } else { (a, b) };
+        if let Some(bit) = x.get(i) {
+            b1.push(bit);
+        }
+        if let Some(bit) = y.get(i) {
+            b2.push(bit);
+        }
+    }
+    (b1, b2)
+}
+
+/// Return the mean pairwise Hamming distance between the chromosomes in a
+/// population, as a fraction of the length of the longest chromosome. Where
+/// one chromosome is longer than the other, its extra bits count as
+/// differences. This is 0 for a population of clones, and close to 0.5 for a
+/// random population of equal-length chromosomes.
+pub fn diversity(population: &[Chromosome]) -> f64 {
+    let n = population.len();
+    let len = population.iter().map(|c| c.genome.bits.len()).max().unwrap_or(0);
+    if n < 2 || len == 0 {
+        return 0f64;
+    }
+    let mut ones = vec![0usize; len];
+    let mut present = vec![0usize; len];
+    for c in population {
+        for (i, bit) in c.genome.bits.iter().enumerate() {
+            present[i] += 1;
+            ones[i] += bit as usize;
+        }
+    }
+    let differing: usize = (0..len).map(|i| {
+        ones[i] * (present[i] - ones[i]) + present[i] * (n - present[i])
+    }).sum();
+    let pairs = n * (n - 1) / 2;
+    differing as f64 / (pairs * len) as f64
+}
+
+/// Return (clones of) the `k` fittest individuals in a population, fittest
+/// first.
+pub fn fittest<G: Clone>(population: &[Individual<G>], k: usize) -> Vec<Individual<G>> {
+    let mut sorted = population.to_vec();
+    sorted.sort_by(|a, b| b.fitness.partial_cmp(&a.fitness).unwrap_or(Ordering::Equal));
+    sorted.truncate(k);
+    sorted
+}
+
+/// Return the initial population for a run configured by `config`: the
+/// seed expressions followed by random chromosomes.
+///
+/// Panics if a seed cannot be encoded; see `GaConfig::seed_chromosomes()`.
+pub fn initial_population<F: Fitness + ?Sized>(config: &GaConfig, fitness: &F)
+        -> Vec<Chromosome> {
+    let mut population = config.seed_chromosomes(fitness).unwrap_or_else(|e| panic!("{}", e));
+    population.truncate(config.popsize);
+    let missing = config.popsize - population.len();
+    population.extend(random_chromosomes(config, missing, fitness));
+    population
+}
+
+/// Return `population` restarted as configured by `restart`: its
+/// `restart.keep` fittest chromosomes, topped up with random ones.
+pub(crate) fn restarted<F: Fitness + ?Sized>(population: &[Chromosome], restart: &Restart,
+                                             config: &GaConfig, fitness: &F) -> Vec<Chromosome> {
+    let mut next = fittest(population, restart.keep);
+    let needed = population.len().saturating_sub(next.len());
+    next.extend(random_chromosomes(config, needed, fitness));
+    next
+}
+
+/// Return `n` random chromosomes encoded as configured by `config`.
+pub(crate) fn random_chromosomes<F: Fitness + ?Sized>(config: &GaConfig, n: usize, fitness: &F)
+        -> Vec<Chromosome> {
+    let encoding = Arc::new(config.encoding.clone());
+    (0..n).into_par_iter()
+          .map(|i| {
+              let genes = config.lengths.length(i, n);
+              let well_formed = match config.initialization {
+                  Initialization::Random => false,
+                  Initialization::WellFormed => true,
+                  Initialization::HalfAndHalf => i % 2 == 0,
+              };
+              if well_formed {
+                  Chromosome::well_formed(encoding.clone(), genes, fitness)
+              } else {
+                  Chromosome::random_of_length(encoding.clone(), genes, fitness)
+              }
+          })
+          .collect()
+}
+
+/// Breed the next generation of chromosomes from `population`, which is
+/// generation number `generation`, and return the new population.
+pub fn ga_epoch<F: Fitness + ?Sized>(population: &[Chromosome], generation: usize,
+                                 config: &GaConfig, fitness: &F) -> Vec<Chromosome> {
+    let next = breed_generation(population, generation, config, fitness);
+    let next = match config.dedup {
+        Some(ref dedup) => {
+            let mut next = dedup.dedup(next);
+            let missing = population.len().saturating_sub(next.len());
+            next.extend(random_chromosomes(config, missing, fitness));
+            next
+        }
+        None => next,
+    };
+    match config.local_search {
+        Some(ref local_search) => local_search.apply(next, fitness),
+        None => next,
+    }
+}
+
+/// Breed the next generation according to `config.mode`.
+fn breed_generation<F: Fitness + ?Sized>(population: &[Chromosome], generation: usize,
+                                         config: &GaConfig, fitness: &F) -> Vec<Chromosome> {
+    match config.mode {
+        GaMode::SteadyState { ref replacement } =>
+            return steady_state_epoch(population, generation, replacement, config, fitness),
+        GaMode::DeterministicCrowding =>
+            return crowding_epoch(population, config, fitness),
+        GaMode::Generational => {}
+    }
+    let mut new_population = fittest(population, config.elitism);
+    let needed = population.len().saturating_sub(new_population.len());
+    let shared = config.sharing.as_ref().map(|sharing| sharing.share(population));
+    let shared = shared.as_ref().map_or(population, |shared| &shared[..]);
+    let penalized = config.parsimonious(shared);
+    let candidates = penalized.as_ref().map_or(shared, |penalized| &penalized[..]);
+    let parents = config.selection.mating_pool(candidates, generation, needed + needed % 2);
+    let mutation_rate = config.mutation_rate_for(population);
+    let mut offspring = Vec::with_capacity(parents.len());
+    for pair in parents.chunks(2) {
+        let (b1, b2) = breed(pair[0], pair[1], mutation_rate, config);
+        offspring.push((b1, pair[0].genome.encoding.clone()));
+        offspring.push((b2, pair[0].genome.encoding.clone()));
+    }
+    offspring.truncate(needed);
+    new_population.extend(evaluate(offspring, fitness));
+    new_population
+}
+
+/// Breed `population.len()` offspring two at a time, replacing a member of
+/// the population with each as soon as it is born, and return the result.
+fn steady_state_epoch<F: Fitness + ?Sized>(population: &[Chromosome], generation: usize,
+                                           replacement: &Replacement, config: &GaConfig,
+                                           fitness: &F) -> Vec<Chromosome> {
+    let mut population = population.to_vec();
+    let mutation_rate = config.mutation_rate_for(&population);
+    for _ in 0..population.len().div_ceil(2) {
+        let (b1, b2, encoding) = {
+            let penalized = config.parsimonious(&population);
+            let candidates = penalized.as_ref().map_or(&population[..], |p| &p[..]);
+            let parents = config.selection.mating_pool(candidates, generation, 2);
+            let (b1, b2) = breed(parents[0], parents[1], mutation_rate, config);
+            (b1, b2, parents[0].genome.encoding.clone())
+        };
+        for b in [b1, b2] {
+            let i = replacement.victim(&population);
+            population[i] = Chromosome::with_encoding(b, encoding.clone(), fitness);
+        }
+    }
+    population
+}
+
+/// Breed the next generation from `population` by deterministic crowding.
+fn crowding_epoch<F: Fitness + ?Sized>(population: &[Chromosome], config: &GaConfig,
+                                       fitness: &F) -> Vec<Chromosome> {
+    let mut parents = population.iter().collect::<Vec<_>>();
+    thread_rng().shuffle(&mut parents);
+    let mutation_rate = config.mutation_rate_for(population);
+    let pairs = parents.chunks(2).filter(|pair| pair.len() == 2).collect::<Vec<_>>();
+    let mut offspring = Vec::with_capacity(2 * pairs.len());
+    for pair in &pairs {
+        let (b1, b2) = breed(pair[0], pair[1], mutation_rate, config);
+        offspring.push((b1, pair[0].genome.encoding.clone()));
+        offspring.push((b2, pair[0].genome.encoding.clone()));
+    }
+    let offspring = evaluate(offspring, fitness);
+
+    let d = |a: &Chromosome, b: &Chromosome| hamming(&a.genome.bits, &b.genome.bits);
+    let survivor = |parent: &Chromosome, child: &Chromosome| {
+        if config.penalized(child) >= config.penalized(parent) {
+            child.clone()
+        } else {
+            parent.clone()
+        }
+    };
+    let mut next = Vec::with_capacity(population.len());
+    for (pair, children) in pairs.iter().zip(offspring.chunks(2)) {
+        let (p1, p2, c1, c2) = (pair[0], pair[1], &children[0], &children[1]);
+        if d(p1, c1) + d(p2, c2) <= d(p1, c2) + d(p2, c1) {
+            next.push(survivor(p1, c1));
+            next.push(survivor(p2, c2));
+        } else {
+            next.push(survivor(p1, c2));
+            next.push(survivor(p2, c1));
+        }
+    }
+    // With an odd population, the parent left without a mate survives as is.
+    if parents.len() % 2 == 1 {
+        next.push(parents[parents.len() - 1].clone());
+    }
+    next
+}
+
+/// Cross over and mutate two parents according to `config`, returning the
+/// bits of the two offspring.
+fn breed(a: &Chromosome, b: &Chromosome, mutation_rate: f64, config: &GaConfig)
+        -> (BitVec, BitVec) {
+    let (b1, b2) = if randrange(0.0, 1.0) < config.crossover_rate {
+        config.crossover.recombine(&a.genome.bits, &b.genome.bits)
+    } else {
+        (a.genome.bits.clone(), b.genome.bits.clone())
+    };
+    let (b1, b2) = (a.genome.encoding.mutate(&b1, mutation_rate, &config.gene_mutation),
+                    a.genome.encoding.mutate(&b2, mutation_rate, &config.gene_mutation));
+    match config.constant_tuning {
+        Some(ref tuning) => (tuning.apply(b1, a, b), tuning.apply(b2, a, b)),
+        None => (b1, b2),
+    }
+}
+
+/// Score bit patterns laid out according to their encodings, in parallel.
+fn evaluate<F: Fitness + ?Sized>(bits: Vec<(BitVec, Arc<Encoding>)>, fitness: &F)
+        -> Vec<Chromosome> {
+    bits.into_par_iter()
+        .map(|(b, encoding)| Chromosome::with_encoding(b, encoding, fitness))
+        .collect()
+}
+
+/// A representation of candidate expressions that can be evolved by the GA
+/// driver (`Ga`, `evolve()`). Bit strings (`Bits`) are one; expression trees
+/// (`gp::Tree`) are another.
+///
+/// Populations are bred by the provided methods, which run a plain
+/// generational GA using the population size, rates, elitism and selection
+/// of the configuration; a genome can override them to make use of more.
+pub trait Genome: Clone + Send + Sync {
+    /// Return a random genome for a run configured by `config`.
+    fn random(config: &GaConfig) -> Self;
+    /// Return the (possibly malformed) expression this genome stands for.
+    fn expression(&self) -> String;
+    /// Recombine two genomes into two offspring.
+    fn crossover(&self, other: &Self, config: &GaConfig) -> (Self, Self);
+    /// Return a copy of this genome mutated at rate `rate`.
+    fn mutate(&self, rate: f64, config: &GaConfig) -> Self;
+
+    /// Return the initial population for a run configured by `config`.
+    fn initial_population<F: Fitness + ?Sized>(config: &GaConfig, fitness: &F)
+            -> Vec<Individual<Self>> {
+        (0..config.popsize).into_par_iter()
+                           .map(|_| Individual::scored(Self::random(config), fitness))
+                           .collect()
+    }
+
+    /// Breed the next generation from `population`, which is generation
+    /// number `generation`.
+    fn epoch<F: Fitness + ?Sized>(population: &[Individual<Self>], generation: usize,
+                                  config: &GaConfig, fitness: &F) -> Vec<Individual<Self>> {
+        let mut next = fittest(population, config.elitism);
+        let needed = population.len().saturating_sub(next.len());
+        let parents = config.selection.mating_pool(population, generation, needed + needed % 2);
+        let mut offspring = Vec::with_capacity(parents.len());
+        for pair in parents.chunks(2) {
+            let (a, b) = if randrange(0.0, 1.0) < config.crossover_rate {
+                pair[0].genome.crossover(&pair[1].genome, config)
+            } else {
+                (pair[0].genome.clone(), pair[1].genome.clone())
+            };
+            offspring.push(a.mutate(config.mutation_rate, config));
+            offspring.push(b.mutate(config.mutation_rate, config));
+        }
+        offspring.truncate(needed);
+        next.par_extend(offspring.into_par_iter().map(|g| Individual::scored(g, fitness)));
+        next
+    }
+
+    /// Return `population` restarted as configured by `restart`: its
+    /// `restart.keep` fittest members, topped up with random ones.
+    fn restart<F: Fitness + ?Sized>(population: &[Individual<Self>], restart: &Restart,
+                                    config: &GaConfig, fitness: &F) -> Vec<Individual<Self>> {
+        let mut next = fittest(population, restart.keep);
+        let needed = population.len().saturating_sub(next.len());
+        next.par_extend((0..needed).into_par_iter()
+                                   .map(|_| Individual::scored(Self::random(config), fitness)));
+        next
+    }
+}
+
+/// The bit-string genome used by `ga()`, laid out according to `encoding`.
+#[derive(Clone)]
+pub struct Bits {
+    pub bits: BitVec,
+    pub encoding: Arc<Encoding>,
+}
+
+impl Genome for Bits {
+    fn random(config: &GaConfig) -> Bits {
+        Chromosome::random_with_encoding(Arc::new(config.encoding.clone()), &|_: &str| 0f64)
+            .genome
+    }
+
+    fn expression(&self) -> String { self.encoding.decode(&self.bits) }
+
+    fn crossover(&self, other: &Bits, config: &GaConfig) -> (Bits, Bits) {
+        let (b1, b2) = config.crossover.recombine(&self.bits, &other.bits);
+        (Bits { bits: b1, encoding: self.encoding.clone() },
+         Bits { bits: b2, encoding: self.encoding.clone() })
+    }
+
+    fn mutate(&self, rate: f64, config: &GaConfig) -> Bits {
+        let bits = self.encoding.mutate(&self.bits, rate, &config.gene_mutation);
+        Bits { bits, encoding: self.encoding.clone() }
+    }
+
+    fn initial_population<F: Fitness + ?Sized>(config: &GaConfig, fitness: &F)
+            -> Vec<Chromosome> {
+        initial_population(config, fitness)
+    }
+
+    fn epoch<F: Fitness + ?Sized>(population: &[Chromosome], generation: usize,
+                                  config: &GaConfig, fitness: &F) -> Vec<Chromosome> {
+        ga_epoch(population, generation, config, fitness)
+    }
+
+    fn restart<F: Fitness + ?Sized>(population: &[Chromosome], restart: &Restart,
+                                    config: &GaConfig, fitness: &F) -> Vec<Chromosome> {
+        restarted(population, restart, config, fitness)
+    }
+}
+
+impl<G: Genome> Individual<G> {
+    /// Score `genome` by `fitness`.
+    pub fn scored<F: Fitness + ?Sized>(genome: G, fitness: &F) -> Individual<G> {
+        let e = genome.expression();
+        Individual { fitness: fitness.fitness(&e), cases: fitness.cases(&e), genome }
+    }
+
+    /// Return the expression (possibly malformed) represented by this
+    /// individual.
+    pub fn decode(&self) -> String { self.genome.expression() }
+
+    /// Return true if this individual's fitness is (within EPSILON of) 1.
+    pub fn is_solution(&self) -> bool { is_solution(self.fitness) }
+}
+
+/// The population of a GA run at some generation.
+#[derive(Clone)]
+pub struct Generation<G = Bits> {
+    /// Zero-based index of this generation.
+    pub index: usize,
+    pub population: Vec<Individual<G>>,
+    /// Number of times the population has been restarted so far.
+    pub restarts: usize,
+    /// Number of times the fitness function has been called so far.
+    pub evaluations: usize,
+    /// Wall-clock time since the run started.
+    pub elapsed: Duration,
+    /// Number of generations since the best fitness last improved.
+    pub stagnant: usize,
+    /// Hit statistics of the fitness cache so far, if there is one.
+    pub cache: Option<CacheStats>,
+    /// The distinct solutions found so far, in order of discovery.
+    pub solutions: Vec<Individual<G>>,
+}
+
+impl<G: Genome> Generation<G> {
+    /// Return the fittest individual in this generation, or None if the
+    /// population is empty.
+    pub fn best(&self) -> Option<&Individual<G>> {
+        let (first, rest) = self.population.split_first()?;
+        Some(rest.iter().fold(first, |a, b| if b.fitness > a.fitness { b } else { a }))
+    }
+
+    /// Return the first individual that solves the problem, if any.
+    pub fn solution(&self) -> Option<&Individual<G>> {
+        self.population.iter().find(|c| c.is_solution())
+    }
+}
+
+/// A GA run that can be stepped manually, one generation at a time.
+///
+/// The first item yielded is the initial random population, and every
+/// following item is bred from the one before it. The iterator never ends by
+/// itself (`config.max_gens` is ignored), so it is up to the caller to decide
+/// when to stop, e.g., with `take()` or by checking `Generation::solution()`.
+pub struct Ga<'a, F: Fitness + ?Sized + 'a, G: Genome = Bits> {
+    config: GaConfig,
+    fitness: Counted<'a, F>,
+    cache: Option<FitnessCache>,
+    started_at: Instant,
+    population: Vec<Individual<G>>,
+    index: usize,
+    started: bool,
+    best_fitness: f64,
+    stagnant: usize,
+    restarts: usize,
+    solutions: SolutionSet<G>,
+}
+
+impl<'a, F: Fitness + ?Sized> Ga<'a, F> {
+    /// Start a new run with a random initial population of bit strings.
+    pub fn new(config: GaConfig, fitness: &'a F) -> Ga<'a, F> { Ga::with_genome(config, fitness) }
+}
+
+impl<'a, F: Fitness + ?Sized, G: Genome> Ga<'a, F, G> {
+    /// Start a new run with a random initial population of `G`s.
+    pub fn with_genome(config: GaConfig, fitness: &'a F) -> Ga<'a, F, G> {
+        let started_at = Instant::now();
+        let fitness = Counted::new(fitness);
+        let cache = if config.cache { Some(FitnessCache::new()) } else { None };
+        let population = {
+            let graded = config.graded(&fitness);
+            G::initial_population(&config, &Memoized::new(&graded, cache.as_ref()))
+        };
+        Ga {
+            config,
+            fitness,
+            cache,
+            started_at,
+            population,
+            index: 0,
+            started: false,
+            best_fitness: 0f64,
+            stagnant: 0,
+            restarts: 0,
+            solutions: SolutionSet::new(),
+        }
+    }
+
+    /// Return the population of the generation most recently yielded.
+    pub fn population(&self) -> &[Individual<G>] { &self.population }
+
+    /// Restart the population if the best fitness has not improved for
+    /// `config.restart.window` generations.
+    fn restart_if_stagnant(&mut self) {
+        let best = self.population.iter().fold(0f64, |a, c| a.max(c.fitness));
+        if best > self.best_fitness {
+            self.best_fitness = best;
+            self.stagnant = 0;
+            return;
+        }
+        self.stagnant += 1;
+        if let Some(ref restart) = self.config.restart {
+            if self.stagnant >= restart.window {
+                let graded = self.config.graded(&self.fitness);
+                let fitness = Memoized::new(&graded, self.cache.as_ref());
+                self.population = G::restart(&self.population, restart, &self.config, &fitness);
+                self.stagnant = 0;
+                self.restarts += 1;
+            }
+        }
+    }
+}
+
+impl<'a, F: Fitness + ?Sized, G: Genome> Iterator for Ga<'a, F, G> {
+    type Item = Generation<G>;
+
+    fn next(&mut self) -> Option<Generation<G>> {
+        if self.started {
+            let graded = self.config.graded(&self.fitness);
+            let fitness = Memoized::new(&graded, self.cache.as_ref());
+            self.population = G::epoch(&self.population, self.index, &self.config, &fitness);
+            self.index += 1;
+        }
+        self.started = true;
+        self.solutions.update(&self.population);
+        self.restart_if_stagnant();
+        Some(Generation {
+            index: self.index,
+            population: self.population.clone(),
+            restarts: self.restarts,
+            evaluations: self.fitness.count(),
+            elapsed: self.started_at.elapsed(),
+            stagnant: self.stagnant,
+            cache: self.cache.as_ref().map(|c| c.stats()),
+            solutions: self.solutions.members().to_vec(),
+        })
+    }
+}
+
+/// The distinct solutions seen over the course of a run, told apart by the
+/// canonical form of their expressions (see `expr::Canonical`), and, for
+/// expressions with variables, by what they compute (see
+/// `expr::equivalent()`), so that `x*2` and `x+x` count once. Solutions
+/// without variables all have the target value, so those are only told
+/// apart by form.
+pub(crate) struct SolutionSet<G = Bits> {
+    members: Vec<Individual<G>>,
+    seen: HashSet<expr::Canonical>,
+}
+
+impl<G: Genome> SolutionSet<G> {
+    pub(crate) fn new() -> SolutionSet<G> {
+        SolutionSet { members: Vec::new(), seen: HashSet::new() }
+    }
+
+    /// Add the solutions in `population` not seen before.
+    pub(crate) fn update(&mut self, population: &[Individual<G>]) {
+        for c in population.iter().filter(|c| c.is_solution()) {
+            let e = c.decode();
+            if !self.seen.insert(expr::Canonical::new(&e)) {
+                continue;
+            }
+            let variables = expr::compile(&e).is_ok_and(|p| !p.variables().is_empty());
+            if !(variables && self.members.iter().any(|m| expr::equivalent(&m.decode(), &e))) {
+                self.members.push(c.clone());
+            }
+        }
+    }
+
+    pub(crate) fn members(&self) -> &[Individual<G>] { &self.members }
+}
+
+/// How often a fitness cache was hit.
+#[derive(Debug,Clone,Copy,PartialEq,Default)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+}
+
+impl CacheStats {
+    /// Return the fraction of lookups that were hits, or 0 if there were
+    /// none.
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 { 0f64 } else { self.hits as f64 / lookups as f64 }
+    }
+}
+
+/// Fitness values memoized by decoded expression, as spelt, since a fitness
+/// function may score spellings of the same expression differently.
+pub(crate) struct FitnessCache {
+    values: Mutex<HashMap<String, f64>>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl FitnessCache {
+    pub(crate) fn new() -> FitnessCache {
+        FitnessCache {
+            values: Mutex::new(HashMap::new()),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        }
+    }
+
+    /// Return the hit statistics so far.
+    pub(crate) fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(AtomicOrdering::Relaxed),
+            misses: self.misses.load(AtomicOrdering::Relaxed),
+        }
+    }
+}
+
+/// A fitness function that looks expressions up in a cache, if given one,
+/// before scoring them.
+pub(crate) struct Memoized<'a, F: Fitness + ?Sized + 'a> {
+    fitness: &'a F,
+    cache: Option<&'a FitnessCache>,
+}
+
+impl<'a, F: Fitness + ?Sized> Memoized<'a, F> {
+    pub(crate) fn new(fitness: &'a F, cache: Option<&'a FitnessCache>) -> Memoized<'a, F> {
+        Memoized { fitness, cache }
+    }
+}
+
+impl<'a, F: Fitness + ?Sized> Fitness for Memoized<'a, F> {
+    fn fitness(&self, expr: &str) -> f64 {
+        let cache = match self.cache {
+            Some(cache) => cache,
+            None => return self.fitness.fitness(expr),
+        };
+        if let Some(&v) = cache.values.lock().unwrap().get(expr) {
+            cache.hits.fetch_add(1, AtomicOrdering::Relaxed);
+            return v;
+        }
+        // Score without holding the lock, so that other threads can go on.
+        let v = self.fitness.fitness(expr);
+        cache.misses.fetch_add(1, AtomicOrdering::Relaxed);
+        cache.values.lock().unwrap().insert(expr.to_string(), v);
+        v
+    }
+
+    fn cases(&self, expr: &str) -> Vec<f64> { self.fitness.cases(expr) }
+}
+
+/// A fitness function that counts how many times it is called.
+pub(crate) struct Counted<'a, F: Fitness + ?Sized + 'a> {
+    fitness: &'a F,
+    count: AtomicUsize,
+}
+
+impl<'a, F: Fitness + ?Sized> Counted<'a, F> {
+    pub(crate) fn new(fitness: &'a F) -> Counted<'a, F> {
+        Counted { fitness, count: AtomicUsize::new(0) }
+    }
+
+    /// Return the number of calls so far.
+    pub(crate) fn count(&self) -> usize { self.count.load(AtomicOrdering::Relaxed) }
+}
+
+impl<'a, F: Fitness + ?Sized> Fitness for Counted<'a, F> {
+    fn fitness(&self, expr: &str) -> f64 {
+        self.count.fetch_add(1, AtomicOrdering::Relaxed);
+        self.fitness.fitness(expr)
+    }
+
+    fn cases(&self, expr: &str) -> Vec<f64> { self.fitness.cases(expr) }
+}
+
+/// The fittest chromosomes seen over the course of a run, no two of which
+/// decode to the same expression, up to formatting.
+#[derive(Clone)]
+pub struct HallOfFame<G = Bits> {
+    size: usize,
+    members: Vec<Individual<G>>,
+}
+
+impl<G: Genome> HallOfFame<G> {
+    /// Create an empty hall of fame holding at most `size` individuals.
+    pub fn new(size: usize) -> HallOfFame<G> {
+        HallOfFame { size, members: Vec::new() }
+    }
+
+    /// Consider the members of `population` for admission.
+    pub fn update(&mut self, population: &[Individual<G>]) {
+        let threshold = if self.members.len() < self.size {
+            f64::NEG_INFINITY
+        } else {
+            self.members[self.members.len() - 1].fitness
+        };
+        let mut candidates = self.members.clone();
+        candidates.extend(population.iter().filter(|c| c.fitness > threshold).cloned());
+        candidates.sort_by(|a, b| b.fitness.partial_cmp(&a.fitness).unwrap_or(Ordering::Equal));
+        let mut seen = HashSet::new();
+        self.members = candidates.into_iter()
+                                 .filter(|c| seen.insert(expr::Canonical::new(&c.decode())))
+                                 .take(self.size)
+                                 .collect();
+    }
+
+    /// Return the members, fittest first.
+    pub fn members(&self) -> &[Individual<G>] { &self.members }
+
+    /// Consume the hall of fame, returning its members, fittest first.
+    pub fn into_members(self) -> Vec<Individual<G>> { self.members }
+}
+
+/// Why a GA run stopped.
+#[derive(Debug,Clone)]
+pub enum StopReason {
+    /// This termination criterion was met.
+    Met(Termination),
+    /// The observer asked for the run to stop.
+    Aborted,
+    /// The population was empty, so there was nothing to evolve.
+    Empty,
+}
+
+/// The outcome of a GA run.
+pub struct GaResult<G = Bits> {
+    /// Number of generations run, counting the initial population.
+    pub generations: usize,
+    /// Number of times the fitness function was called.
+    pub evaluations: usize,
+    /// Wall-clock time taken by the run.
+    pub elapsed: Duration,
+    /// The solution found, if any.
+    pub solution: Option<Individual<G>>,
+    /// The fittest individual seen during the run.
+    pub best: Option<Individual<G>>,
+    /// The fitness of `best`, or 0 if there is none.
+    pub best_fitness: f64,
+    /// Number of times the population was restarted after stagnating.
+    pub restarts: usize,
+    /// The fittest distinct individuals seen during the run, fittest first.
+    /// Even when no solution was found, the first of these is the closest
+    /// miss.
+    pub hall_of_fame: Vec<Individual<G>>,
+    /// Why the run stopped.
+    pub reason: StopReason,
+    /// Hit statistics of the fitness cache, if `config.cache` was set.
+    pub cache: Option<CacheStats>,
+    /// The distinct solutions found, in order of discovery, starting with
+    /// `solution`.
+    pub solutions: Vec<Individual<G>>,
+}
+
+impl<G: Genome> GaResult<G> {
+    /// Return a result for a run that stopped for `reason`, filling in the
+    /// best individual from `hall_of_fame`.
+    pub(crate) fn new(generations: usize, evaluations: usize, elapsed: Duration,
+                      solution: Option<Individual<G>>, restarts: usize,
+                      hall_of_fame: HallOfFame<G>, reason: StopReason) -> GaResult<G> {
+        let hall_of_fame = hall_of_fame.into_members();
+        let best = hall_of_fame.first().cloned();
+        GaResult {
+            generations,
+            evaluations,
+            elapsed,
+            solutions: solution.iter().cloned().collect(),
+            solution,
+            best_fitness: best.as_ref().map_or(0f64, |c| c.fitness),
+            best,
+            restarts,
+            hall_of_fame,
+            reason,
+            cache: None,
+        }
+    }
+}
+
+/// Run the GA, maximising `fitness`, until `config.termination()` is met,
+/// printing progress every 10 generations.
+pub fn ga<F: Fitness + ?Sized>(config: &GaConfig, fitness: &F) -> GaResult {
+    let max_gens = config.max_gens;
+    ga_with_observer(config, fitness, |i, _, _| {
+        if i % 10 == 9 || i + 10 >= max_gens {
+            println!("Generation {} of {}", i+1, max_gens);
+        }
+        true
+    })
+}
+
+/// Like `ga`, but silent, calling `observer` with the generation index, the
+/// population and its fittest chromosome once every generation. If the
+/// observer returns false, the run is aborted.
+pub fn ga_with_observer<F, O>(config: &GaConfig, fitness: &F, observer: O) -> GaResult
+        where F: Fitness + ?Sized,
+              O: FnMut(usize, &[Chromosome], &Chromosome) -> bool {
+    run(Ga::new(config.clone(), fitness), config, observer)
+}
+
+/// Like `ga`, but silent and evolving genomes of any kind, e.g.,
+/// `evolve::<gp::Tree, _>(&config, &fitness)`.
+pub fn evolve<G: Genome, F: Fitness + ?Sized>(config: &GaConfig, fitness: &F) -> GaResult<G> {
+    run(Ga::with_genome(config.clone(), fitness), config, |_, _, _| true)
+}
+
+/// Step through `ga` until `config.termination()` is met or `observer`
+/// returns false, as described for `ga_with_observer`.
+fn run<F, G, O>(ga: Ga<F, G>, config: &GaConfig, mut observer: O) -> GaResult<G>
+        where F: Fitness + ?Sized,
+              G: Genome,
+              O: FnMut(usize, &[Individual<G>], &Individual<G>) -> bool {
+    let mut hall_of_fame = HallOfFame::new(config.hall_of_fame);
+    let termination = config.termination();
+    for gen in ga {
+        hall_of_fame.update(&gen.population);
+        let stop = match gen.best() {
+            // An empty population has no fittest individual, and never
+            // changes.
+            None => Some(StopReason::Empty),
+            Some(best) if !observer(gen.index, &gen.population, best) => {
+                Some(StopReason::Aborted)
+            }
+            Some(_) => termination.met_by(&gen).map(|met| StopReason::Met(met.clone())),
+        };
+        if let Some(reason) = stop {
+            // The first solution found heads the distinct solutions.
+            let solution = gen.solutions.first().cloned();
+            let mut result = GaResult::new(gen.index + 1, gen.evaluations, gen.elapsed, solution,
+                                           gen.restarts, hall_of_fame, reason);
+            result.cache = gen.cache;
+            result.solutions = gen.solutions;
+            return result;
+        }
+    }
+    unreachable!("a GA run never runs out of generations")
+}
+
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// Decode `b` with the default symbol table.
+    fn decode(b: &BitVec) -> String { SymbolTable::default().decode(b, 0) }
+
+    /// Return an empty chromosome with the given fitness.
+    fn scored(fitness: f64) -> Chromosome {
+        Chromosome::new(BitVec::new(), &move |_: &str| fitness)
+    }
+
+    #[test]
+    pub fn test_closeness() {
+        let f = Closeness::new(42f64);
+        assert_eq!(f.fitness("6*7"), 1f64);
+        assert_eq!(f.fitness("40"), 1f64 / 3f64);
+        assert_eq!(f.fitness("**"), 0f64);
+    }
+
+    #[test]
+    pub fn test_tolerance() {
+        use std::f64::consts::PI;
+        let f = Closeness::within(PI, Tolerance { absolute: 1e-3, relative: 0f64 });
+        assert_eq!(f.fitness("22/7"), 1f64 / (1f64 + (22f64 / 7f64 - PI).abs()));
+        assert_eq!(f.fitness("355/113"), 1f64);
+        let f = Closeness::within(1e6, Tolerance { absolute: 0f64, relative: 1e-3 });
+        assert_eq!(f.fitness("999500"), 1f64);
+        assert!(f.fitness("998000") < 1f64);
+        assert!(Tolerance::default().accepts(2.0, 2.0));
+    }
+
+    #[test]
+    pub fn test_big_closeness() {
+        let f = BigCloseness::new(expr::eval_big("2**200").unwrap());
+        assert_eq!(f.fitness("4**100"), 1f64);
+        assert!(!is_solution(f.fitness("2**200+1")));
+        assert_eq!(f.fitness("2**200+1"), 0.5);
+        assert!(f.fitness("2**199") > 0f64);
+        assert_eq!(f.fitness("2**"), 0f64);
+        // In floating point, the two are indistinguishable.
+        assert!(is_solution(Closeness::new(2f64.powi(200)).fitness("2**200+1")));
+    }
+
+    #[test]
+    pub fn test_exact_closeness() {
+        let f = Closeness::exact(1f64);
+        assert_eq!(f.fitness("(1/3)*3"), 1f64);
+        assert_eq!(f.fitness("2-1"), 1f64);
+        assert_eq!(f.fitness("2"), 0.5);
+        assert_eq!(f.fitness("sqrt(1)"), 0f64);
+        assert_eq!(f.fitness("1/0"), 0f64);
+        // Within a float epsilon, but not a solution.
+        assert!(!is_solution(f.fitness("1+1/10**12")));
+        assert!(is_solution(Closeness::new(1f64).fitness("1+1/10**12")));
+        assert!(is_solution(Closeness::exact(0.3).fitness("0.1*3")));
+    }
+
+    #[test]
+    pub fn test_integer_closeness() {
+        let f = Closeness::integer(4f64);
+        assert_eq!(f.fitness("(8/2)"), 1f64);
+        assert_eq!(f.fitness("(9/2)*2"), 0f64);
+        assert_eq!(Closeness::new(9f64).fitness("(9/2)*2"), 1f64);
+        assert_eq!(f.fitness("3"), 0.5);
+        assert_eq!(f.fitness("9**30/9**29-5"), 0f64);
+        assert_eq!(f.fitness("2**2.0"), 1f64);
+        assert!(!is_solution(Closeness::integer(0.5).fitness("1/2")));
+    }
+
+    #[test]
+    pub fn test_custom_fitness() {
+        let shortest = |e: &str| 1f64 / (1f64 + e.len() as f64);
+        let c = Chromosome::new(to_binary(0x12), &shortest);
+        assert_eq!(c.decode(), "12");
+        assert_eq!(c.fitness, 1f64 / 3f64);
+    }
+
+    #[test]
+    pub fn test_ga_iterator() {
+        let config = GaConfig { popsize: 20, ..Default::default() };
+        let gens = Ga::new(config, &Closeness::new(42f64)).take(5).collect::<Vec<_>>();
+        assert_eq!(gens.len(), 5);
+        for (i, gen) in gens.iter().enumerate() {
+            assert_eq!(gen.index, i);
+            assert!(gen.population.len() >= 20);
+            assert!(gen.population.iter().all(|c| c.fitness <= gen.best().unwrap().fitness));
+        }
+    }
+
+    #[test]
+    pub fn test_elitism() {
+        let config = GaConfig { popsize: 20, elitism: 2, ..Default::default() };
+        let fitness = Closeness::new(1e6);
+        let mut ga = Ga::new(config, &fitness);
+        let mut best = ga.next().unwrap().best().unwrap().fitness;
+        for gen in ga.take(10) {
+            assert_eq!(gen.population.len(), 20);
+            assert!(gen.best().unwrap().fitness >= best);
+            best = gen.best().unwrap().fitness;
+        }
+    }
+
+    #[test]
+    pub fn test_rank_weights() {
+        let pop = [0.5, 0.1, 0.9].iter()
+                                 .map(|&f| scored(f))
+                                 .collect::<Vec<_>>();
+        let weights = Selection::Rank { pressure: 1.5 }.weights(&pop, 0);
+        assert_eq!(weights, vec![1.0, 0.5, 1.5]);
+    }
+
+    #[test]
+    pub fn test_truncation_weights() {
+        let pop = [0.5, 0.1, 0.9, 0.7].iter()
+                                      .map(|&f| scored(f))
+                                      .collect::<Vec<_>>();
+        let weights = Selection::Truncation { fraction: 0.5 }.weights(&pop, 0);
+        assert_eq!(weights, vec![0.0, 0.0, 1.0, 1.0]);
+        let weights = Selection::Truncation { fraction: 0.0 }.weights(&pop, 0);
+        assert_eq!(weights, vec![0.0, 0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    pub fn test_boltzmann_weights() {
+        let pop = [0.5, 1.0].iter()
+                            .map(|&f| scored(f))
+                            .collect::<Vec<_>>();
+        let boltzmann = Selection::Boltzmann { temperature: 1.0, decay: 0.5 };
+        let early = boltzmann.weights(&pop, 0);
+        let late = boltzmann.weights(&pop, 3);
+        assert_eq!(early[1], 1.0);
+        assert_eq!(late[1], 1.0);
+        assert!((early[0] - (-0.5f64).exp()).abs() < 1e-12);
+        assert!((late[0] - (-4f64).exp()).abs() < 1e-12);
+    }
+
+    #[test]
+    pub fn test_sus() {
+        let pop = [0.0, 3.0, 1.0].iter()
+                                 .map(|&f| scored(f))
+                                 .collect::<Vec<_>>();
+        let pool = Selection::StochasticUniversal.mating_pool(&pop, 0, 4);
+        assert_eq!(pool.iter().filter(|c| c.fitness == 3.0).count(), 3);
+        assert_eq!(pool.iter().filter(|c| c.fitness == 1.0).count(), 1);
+
+        let zeros = vec![scored(0.0); 3];
+        assert_eq!(Selection::StochasticUniversal.mating_pool(&zeros, 0, 6).len(), 6);
+    }
+
+    #[test]
+    pub fn test_crossover_at() {
+        let a = BitVec::from_elem(8, true);
+        let b = BitVec::from_elem(12, false);
+        let (c1, c2) = crossover_at(&a, &b, &[2, 5]);
+        assert_eq!(bitstring(&c1), "11000111");
+        assert_eq!(bitstring(&c2), "001110000000");
+
+        let (c1, c2) = crossover_at(&a, &b, &[4]);
+        assert_eq!(bitstring(&c1), "111100000000");
+        assert_eq!(bitstring(&c2), "00001111");
+
+        let (c1, c2) = crossover_at(&a, &b, &[1, 3, 9]);
+        assert_eq!(bitstring(&c1), "10011111000");
+        assert_eq!(bitstring(&c2), "011000000");
+    }
+
+    #[test]
+    pub fn test_gene_aligned_crossover() {
+        let a = to_binary(0x12345678);
+        let b = to_binary(0xabcdef);
+        let strategies = [CrossoverStrategy::SinglePoint,
+                          CrossoverStrategy::NPoint(3),
+                          CrossoverStrategy::Uniform { p: 0.5 }];
+        let sorted = |s: String| { let mut v = s.into_bytes(); v.sort(); v };
+        for strategy in strategies.iter().cloned() {
+            let aligned = CrossoverStrategy::GeneAligned(Box::new(strategy));
+            for _ in 0..20 {
+                let (c1, c2) = aligned.recombine(&a, &b);
+                assert_eq!(c1.len() % GENE_BITS, 0);
+                assert_eq!(c2.len() % GENE_BITS, 0);
+                assert_eq!(sorted(decode(&c1) + &decode(&c2)),
+                           sorted(decode(&a) + &decode(&b)));
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_diversity() {
+        let c = |x| Chromosome::new(to_binary(x), &|_: &str| 0f64);
+        assert_eq!(diversity(&[c(0xf0), c(0xf0), c(0xf0)]), 0.0);
+        assert_eq!(diversity(&[c(0xf0), c(0x0f)]), 1.0);
+        assert_eq!(diversity(&[c(0xff), c(0xfff0)]), 0.75);
+
+        let adaptive = AdaptiveMutation { threshold: 0.2, max_rate: 0.11 };
+        assert_eq!(adaptive.rate(0.01, 0.3), 0.01);
+        assert!((adaptive.rate(0.01, 0.1) - 0.06).abs() < 1e-12);
+        assert_eq!(adaptive.rate(0.01, 0.0), 0.11);
+    }
+
+    #[test]
+    pub fn test_self_adaptive_encoding() {
+        let encoding = Arc::new(Encoding { self_adaptive: true, ..Default::default() });
+        let never = |_: &str| 0f64;
+        let c = Chromosome::with_encoding(to_binary(0x1200), encoding.clone(), &never);
+        assert_eq!(c.decode(), "12");
+        assert_eq!(c.mutation_rate(), Some(MIN_SELF_ADAPTIVE_RATE));
+        let c = Chromosome::with_encoding(to_binary(0x12ff), encoding.clone(), &never);
+        assert!((c.mutation_rate().unwrap() - MAX_SELF_ADAPTIVE_RATE).abs() < 1e-12);
+        assert_eq!(c.mutate(0.0, &never).mutation_rate(), c.mutation_rate());
+
+        let r = Chromosome::random_with_encoding(encoding, &never);
+        assert_eq!((r.genome.bits.len() - CONTROL_BITS) % GENE_BITS, 0);
+    }
+
+    #[test]
+    pub fn test_gene_insertion_deletion() {
+        let b = to_binary(0x123456);
+        let insert = GeneMutation { insertion: 1.0, ..Default::default() };
+        let delete = GeneMutation { deletion: 1.0, ..Default::default() };
+        assert_eq!(insert.apply(&b, 0).len(), 28);
+        assert_eq!(delete.apply(&b, 0).len(), 20);
+        assert_eq!(delete.apply(&b, 8).len(), 20);
+
+        let shortest = BitVec::from_elem(CHROMOSOME_MIN * GENE_BITS, false);
+        assert_eq!(delete.apply(&shortest, 0), shortest);
+
+        let deleted = decode(&delete.apply(&b, 8));
+        assert!(["56412", "56312", "56342", "56341"].contains(&&deleted[..]));
+    }
+
+    #[test]
+    pub fn test_gene_inversion() {
+        let invert = GeneMutation { inversion: 1.0, ..Default::default() };
+        let body = "563412";
+        let inversions = (0..6).flat_map(|i| (i..6).map(move |j| {
+            let middle = body[i..j + 1].chars().rev().collect::<String>();
+            format!("78{}{}{}", &body[..i], middle, &body[j + 1..])
+        })).collect::<Vec<_>>();
+        for _ in 0..20 {
+            let e = decode(&invert.apply(&to_binary(0x12345678), 8));
+            assert!(inversions.contains(&e), "{}", e);
+        }
+    }
+
+    #[test]
+    pub fn test_gene_swap_transposition() {
+        let sorted = |s: String| { let mut v = s.into_bytes(); v.sort(); v };
+        let b = to_binary(0x12345678);
+        let swap = GeneMutation { swap: 1.0, ..Default::default() };
+        let transpose = GeneMutation { transposition: 1.0, ..Default::default() };
+        for _ in 0..20 {
+            for m in &[&swap, &transpose] {
+                let e = decode(&m.apply(&b, 0));
+                assert_eq!(sorted(e), sorted(String::from("78563412")));
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_crossover_by() {
+        let a = BitVec::from_elem(6, true);
+        let b = BitVec::from_elem(4, false);
+        let (c1, c2) = crossover_by(&a, &b, |i| i % 2 == 1);
+        assert_eq!(bitstring(&c1), "10101");
+        assert_eq!(bitstring(&c2), "01011");
+    }
+
+    #[test]
+    pub fn test_n_point_crossover_lengths() {
+        let fitness = |_: &str| 0f64;
+        let a = Chromosome::new(BitVec::from_elem(8, true), &fitness);
+        let b = Chromosome::new(BitVec::from_elem(20, false), &fitness);
+        for strategy in &[CrossoverStrategy::SinglePoint,
+                          CrossoverStrategy::TwoPoint,
+                          CrossoverStrategy::NPoint(5),
+                          CrossoverStrategy::Uniform { p: 0.5 }] {
+            let (c1, c2) = a.crossover(&b, 1.0, strategy, &fitness);
+            assert_eq!(c1.genome.bits.len() + c2.genome.bits.len(), 28);
+            let ones = c1.genome.bits.iter().chain(c2.genome.bits.iter()).filter(|&b| b).count();
+            assert_eq!(ones, 8);
+        }
+        assert_eq!(CrossoverStrategy::NPoint(5).cut_points(20).len(), 5);
+        assert_eq!(CrossoverStrategy::NPoint(50).cut_points(20).len(), 19);
+    }
+
+    #[test]
+    pub fn test_steady_state() {
+        let config = GaConfig {
+            popsize: 20,
+            mode: GaMode::SteadyState { replacement: Replacement::Worst },
+            ..Default::default()
+        };
+        let fitness = Closeness::new(1e6);
+        let mut ga = Ga::new(config, &fitness);
+        let mut best = ga.next().unwrap().best().unwrap().fitness;
+        for gen in ga.take(10) {
+            assert_eq!(gen.population.len(), 20);
+            assert!(gen.best().unwrap().fitness >= best);
+            best = gen.best().unwrap().fitness;
+        }
+    }
+
+    #[test]
+    pub fn test_hamming() {
+        let a = BitVec::from_fn(70, |i| i % 3 == 0);
+        let b = BitVec::from_fn(40, |i| i % 3 == 0 || i == 35);
+        assert_eq!(hamming(&a, &a), 0);
+        assert_eq!(hamming(&a, &b), 31);
+        assert_eq!(hamming(&b, &a), 31);
+        assert_eq!(hamming(&to_binary(0xf0), &to_binary(0x0f)), 8);
+    }
+
+    #[test]
+    pub fn test_sharing() {
+        let c = |x| Chromosome::new(to_binary(x), &|_: &str| 0.5);
+        let pop = [c(0x42), c(0x42), c(0x67), c(0x99)];
+        let sharing = Sharing { radius: 1.0, similarity: Similarity::Value };
+        let shared = sharing.share(&pop).iter().map(|c| c.fitness).collect::<Vec<_>>();
+        assert_eq!(shared, vec![0.25, 0.25, 0.5, 0.5]);
+    }
+
+    #[test]
+    pub fn test_parsimony() {
+        let c = |x| Chromosome::new(to_binary(x), &|_: &str| 0.5);
+        let pop = [c(0x42), c(0x4242), c(0x42424242)];
+        assert_eq!(pop.iter().map(|c| c.genes()).collect::<Vec<_>>(), vec![2, 4, 8]);
+        let config = GaConfig { parsimony: 0.0625, ..Default::default() };
+        let penalized = config.parsimonious(&pop).unwrap();
+        assert_eq!(penalized.iter().map(|c| c.fitness).collect::<Vec<_>>(), vec![0.375, 0.25, 0.0]);
+        assert!(GaConfig::default().parsimonious(&pop).is_none());
+
+        // "6*7" has 3 nodes, and "++" is charged for its 2 genes.
+        let pop = [c(0x42), c(0x5f6c), c(0xaa)];
+        let config = GaConfig { parsimony: 0.0625, parsimony_size: Size::Nodes,
+                                ..Default::default() };
+        let penalized = config.parsimonious(&pop).unwrap();
+        assert_eq!(penalized.iter().map(|c| c.fitness).collect::<Vec<_>>(),
+                   vec![0.4375, 0.3125, 0.375]);
+    }
+
+    /// Scores "1" and "2" perfectly on one case each, and "3" middling on both.
+    struct TwoCases;
+
+    impl Fitness for TwoCases {
+        fn fitness(&self, expr: &str) -> f64 {
+            let cases = self.cases(expr);
+            cases.iter().fold(0f64, |a, b| a + b) / cases.len() as f64
+        }
+
+        fn cases(&self, expr: &str) -> Vec<f64> {
+            match expr {
+                "1" => vec![1.0, 0.0],
+                "2" => vec![0.0, 1.0],
+                _ => vec![0.6, 0.6],
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_lexicase() {
+        let c = |x| Chromosome::new(to_binary(x), &TwoCases);
+        let pop = [c(0x1f), c(0x2f), c(0x3f)];
+        assert_eq!(pop[2].cases, vec![0.6, 0.6]);
+        let picked = Selection::Lexicase { epsilon: 0.0 }.mating_pool(&pop, 0, 200)
+                                                           .iter()
+                                                           .map(|c| c.decode())
+                                                           .collect::<HashSet<_>>();
+        let expected = ["1", "2"].iter().map(|s| s.to_string()).collect::<HashSet<_>>();
+        assert_eq!(picked, expected);
+    }
+
+    #[test]
+    pub fn test_termination() {
+        let never = |_: &str| 0.5;
+        let run = |termination| {
+            let config = GaConfig { popsize: 20, termination: Some(termination),
+                                    ..Default::default() };
+            ga_with_observer(&config, &never, |_, _, _| true).generations
+        };
+        // Every generation of 20 takes 20 evaluations.
+        assert_eq!(run(Termination::Evaluations(50)), 3);
+        assert_eq!(run(Termination::Time(Duration::from_secs(0))), 1);
+        assert_eq!(run(Termination::FitnessThreshold(0.5)), 1);
+        // The best fitness never improves after the initial population.
+        assert_eq!(run(Termination::NoImprovement(4)), 5);
+        assert_eq!(run(Termination::Any(vec![Termination::Evaluations(50),
+                                             Termination::Generations(10)])), 3);
+        assert_eq!(run(Termination::All(vec![Termination::Evaluations(50),
+                                             Termination::Generations(10)])), 10);
+        // An empty Any would never be met, so it stands for max_gens.
+        let run = |termination| {
+            let config = GaConfig { popsize: 20, max_gens: 7, termination: Some(termination),
+                                    ..Default::default() };
+            ga_with_observer(&config, &never, |_, _, _| true).generations
+        };
+        assert_eq!(run(Termination::Any(vec![])), 7);
+        assert_eq!(run(Termination::All(vec![Termination::Any(vec![]),
+                                             Termination::Evaluations(50)])), 7);
+
+        let config = GaConfig { popsize: 20, termination: Some(Termination::Evaluations(50)),
+                                ..Default::default() };
+        let result = ga_with_observer(&config, &never, |_, _, _| true);
+        assert_eq!(result.evaluations, 60);
+        assert_eq!(result.best_fitness, 0.5);
+        assert!(result.best.is_some());
+        assert!(matches!(result.reason, StopReason::Met(Termination::Evaluations(50))));
+    }
+
+    #[test]
+    pub fn test_with_operators() {
+        let config = GaConfig { popsize: 50, ..Default::default() };
+        let restricted = config.clone().with_operators(&["+", "*"]).unwrap();
+        assert_eq!(restricted.encoding.symbols.operators(), vec!["+", "*"]);
+        assert_eq!(restricted.encoding.symbols.len(), 13);
+        let result = ga_with_observer(&GaConfig { max_gens: 5, ..restricted.clone() },
+                                      &Closeness::new(1e6), |_, population, _| {
+            assert!(population.iter().all(|c| !c.decode().contains(|c| "-/".contains(c))));
+            true
+        });
+        assert!(result.best.is_some());
+        let seeded = GaConfig { seeds: vec!["6/2".to_string()], ..restricted };
+        assert!(seeded.seed_chromosomes(&Closeness::new(3.0)).is_err());
+        assert!(config.clone().with_operators(&["%"]).is_err());
+        let grammar = Grammar::parse(::grammar::ARITHMETIC).ok();
+        let grammar = Encoding { grammar, ..Default::default() };
+        assert!(GaConfig { encoding: grammar, ..config }.with_operators(&["+"]).is_err());
+    }
+
+    #[test]
+    pub fn test_graded() {
+        let closeness = Closeness::new(42f64);
+        let f = Graded::new(&closeness, Some(0.5));
+        assert_eq!(f.fitness("6*7"), 1f64);
+        // "40" scores 1/3 and is half of "40+*".
+        assert_eq!(f.fitness("40+*"), 0.5 * (1f64 / 3f64) * 0.5);
+        assert_eq!(f.fitness("6*7-"), 0.5 * 0.75);
+        assert_eq!(f.fitness("**"), 0f64);
+        assert_eq!(Graded::new(&closeness, None).fitness("6*7-"), 0f64);
+
+        let f = Graded::new(&closeness, None).with_undefined(UndefinedPolicy::Penalty(0.5));
+        assert_eq!(f.fitness("6*7/0"), 0.5 * 0.6);
+        assert!(f.fitness("6*7/0") > f.fitness("1/0"));
+        assert_eq!(f.fitness("6*7-"), 0f64);
+        assert_eq!(Graded::new(&closeness, Some(0.5)).fitness("6*7/0"), 0f64);
+
+        // Expressions in x are well-formed, even though eval() cannot value them.
+        let regression = ::regression::Regression::new(vec![(1.0, 2.0), (2.0, 5.0)]);
+        let f = Graded::new(&regression, Some(0.5));
+        assert_eq!(f.fitness("x*x+1"), 1f64);
+        assert_eq!(f.fitness("x*x+1+"), 0.5 * 5f64 / 6f64);
+
+        let config = GaConfig { popsize: 20, max_gens: 3, partial_credit: Some(0.5),
+                                ..Default::default() };
+        let result = ga_with_observer(&config, &closeness, |_, _, _| true);
+        assert!(result.best_fitness > 0f64);
+    }
+
+    #[test]
+    pub fn test_solutions() {
+        // Restarts keep a population that has converged on one solution
+        // looking for others.
+        let config = GaConfig { popsize: 50, solutions: 3, dedup: Some(Dedup::Expression),
+                                restart: Some(Restart { window: 20, keep: 1 }),
+                                ..Default::default() };
+        assert!(matches!(GaConfig::default().solved(), Termination::Solved));
+        assert!(matches!(config.solved(), Termination::Solutions(3)));
+        let result = ga_with_observer(&config, &Closeness::new(3f64), |_, _, _| true);
+        assert!(result.solutions.len() >= 3);
+        assert_eq!(result.solutions[0].decode(), result.solution.unwrap().decode());
+        let forms = result.solutions.iter()
+                                    .map(|c| expr::canonical(&c.decode()).unwrap())
+                                    .collect::<HashSet<_>>();
+        assert_eq!(forms.len(), result.solutions.len());
+        assert!(result.solutions.iter().all(|c| c.is_solution()));
+    }
+
+    #[test]
+    pub fn test_fitness_cache() {
+        // With 16 symbols of one gene, most expressions repeat right away.
+        let config = GaConfig { popsize: 50, lengths: LengthDistribution::Fixed(1),
+                                termination: Some(Termination::Generations(5)), cache: true,
+                                ..Default::default() };
+        let calls = AtomicUsize::new(0);
+        let fitness = |_: &str| { calls.fetch_add(1, AtomicOrdering::Relaxed); 0.5 };
+        let result = ga_with_observer(&config, &fitness, |_, _, _| true);
+        let stats = result.cache.unwrap();
+        assert_eq!(stats.misses, calls.load(AtomicOrdering::Relaxed));
+        assert_eq!(stats.misses, result.evaluations);
+        // Threads scoring the same new expression at once may all miss.
+        assert!(stats.misses < 50);
+        assert_eq!(stats.hits + stats.misses, 5 * 50);
+        assert!(stats.hit_rate() > 0.8);
+
+        let config = GaConfig { cache: false, ..config };
+        assert!(ga_with_observer(&config, &fitness, |_, _, _| true).cache.is_none());
+
+        // Spellings of the same expression are scored apart.
+        let cache = FitnessCache::new();
+        let length = |e: &str| e.len() as f64;
+        let memoized = Memoized::new(&length, Some(&cache));
+        assert_eq!(memoized.fitness("6*07"), 4f64);
+        assert_eq!(memoized.fitness("6*7"), 3f64);
+        assert_eq!(memoized.fitness("6*07"), 4f64);
+        assert_eq!(cache.stats().hits, 1);
+    }
+
+    #[test]
+    pub fn test_symbol_table() {
+        let default = SymbolTable::default();
+        assert_eq!(default.len(), 16);
+        assert_eq!(default.decode(&to_binary(0xdca), 0), "*+0/");
+
+        let symbols = SymbolTable::new(vec!["6", "7", "*"]);
+        assert_eq!(symbols.decode(&to_binary(0x2a01), 0), "67*7");
+        assert_eq!(default.without(&["/", "**", ""]).with(&["(", ")"]).symbols().len(), 15);
+
+        let modular = default.with_modulo().unwrap();
+        assert_eq!(modular.symbol(15), "%");
+        assert!(modular.is_operator(15));
+        assert_eq!(modular.encode("7%3"), Ok(vec![7, 15, 3]));
+        assert!(SymbolTable::parenthesized().with_modulo().is_err());
+
+        // Constants like pi are operands, and score like numbers.
+        let circle = default.without(&[""]).with(&["pi"]);
+        assert!(circle.is_variable(15));
+        assert_eq!(circle.encode("2*pi"), Ok(vec![2, 12, 15]));
+        assert_eq!(Closeness::new(2f64 * ::std::f64::consts::PI).fitness("2*pi"), 1f64);
+
+        let encoding = Arc::new(Encoding { symbols, ..Default::default() });
+        let c = Chromosome::random_with_encoding(encoding, &|_: &str| 0f64);
+        let bits = &c.genome.bits;
+        assert!((0..bits.len()).step_by(GENE_BITS).all(|i| bits_at(bits, i, GENE_BITS) < 3));
+    }
+
+    #[test]
+    pub fn test_evolve_bits() {
+        let config = GaConfig { popsize: 20, max_gens: 5, ..Default::default() };
+        let result = evolve::<Bits, _>(&config, &|e: &str| if e == "42" { 1.0 } else { 0.5 });
+        assert!(result.solution.is_some() || result.generations == 5);
+        assert!(result.best.unwrap().fitness >= 0.5);
+    }
+
+    #[test]
+    pub fn test_repair() {
+        let symbols = SymbolTable::default();
+        // "+3*/" "" "4-" ""
+        let b = to_binary(0xbff4cda3);
+        assert_eq!(symbols.concat(&symbols.repair(&genes(&b, 0))), "3*4");
+
+        let parens = SymbolTable::parenthesized();
+        assert_eq!(parens.concat(&parens.repair(&[14, 10, 3, 12, 15, 11])), "(3)");
+        let repair = |e: &str| parens.concat(&parens.repair(&parens.encode(e).unwrap()));
+        assert_eq!(repair(")1+(2*3"), "1+2*3");
+        assert_eq!(repair("(1+2))*3("), "(1+2)*3");
+        assert_eq!(repair("1+(()+)"), "1");
+        assert_eq!(repair("((1)-(2+3))"), "((1)-(2+3))");
+
+        let fitness = |e: &str| if e == "3*4" { 1.0 } else { 0.0 };
+        let decoded = Arc::new(Encoding { repair: Some(Repair::Decoded), ..Default::default() });
+        let c = Chromosome::with_encoding(b.clone(), decoded, &fitness);
+        assert!(c.is_solution());
+        assert_eq!(c.genome.bits, b);
+
+        let write_back = Arc::new(Encoding { repair: Some(Repair::WriteBack),
+                                             ..Default::default() });
+        let c = Chromosome::with_encoding(b, write_back, &fitness);
+        assert!(c.is_solution());
+        assert_eq!(decode(&c.genome.bits), "3*4");
+    }
+
+    #[test]
+    pub fn test_well_formed() {
+        let config = GaConfig { popsize: 50, initialization: Initialization::WellFormed,
+                                ..Default::default() };
+        for c in initial_population(&config, &Closeness::new(42.0)) {
+            assert!(expr::validate(&c.decode()).is_ok(), "{}", c.decode());
+            assert_eq!(c.genes() % 2, 1);
+        }
+        assert!(SymbolTable::new(vec!["1", "2"]).well_formed(5).is_none());
+    }
+
+    #[test]
+    pub fn test_lengths() {
+        let ramped = LengthDistribution::Ramped { min: 3, max: 13 };
+        assert_eq!((0..5).map(|i| ramped.length(i, 5)).collect::<Vec<_>>(), vec![3, 5, 7, 9, 11]);
+        let uniform = LengthDistribution::Uniform { min: 3, max: 5 };
+        assert!((0..20).all(|i| [3, 4].contains(&uniform.length(i, 20))));
+
+        let config = GaConfig { popsize: 10, lengths: LengthDistribution::Fixed(7),
+                                initialization: Initialization::HalfAndHalf,
+                                ..Default::default() };
+        let population = initial_population(&config, &Closeness::new(42.0));
+        assert!(population.iter().all(|c| c.genes() == 7));
+        assert!(population.iter().step_by(2).all(|c| expr::validate(&c.decode()).is_ok()));
+    }
+
+    #[test]
+    pub fn test_local_search() {
+        let closeness = Closeness::new(42.0);
+        // "40"
+        let c = Chromosome::new(to_binary(0x40), &closeness);
+        for neighbourhood in [Neighbourhood::Bit, Neighbourhood::Gene] {
+            let search = LocalSearch { top: 1, steps: 200, neighbourhood };
+            let climbed = search.climb(&c, &closeness);
+            assert!(climbed.fitness >= c.fitness);
+            assert_eq!(climbed.genes(), 2);
+        }
+
+        let search = LocalSearch { top: 1, steps: 500, neighbourhood: Neighbourhood::Gene };
+        let population = search.apply(vec![c.clone(), Chromosome::new(to_binary(0x11), &closeness)],
+                                      &closeness);
+        assert!(population[0].is_solution(), "{}", population[0].decode());
+        assert_eq!(population[1].decode(), "11");
+    }
+
+    #[test]
+    pub fn test_constant_tuning() {
+        let never = |_: &str| 0f64;
+        let encode = |e: &str| Encoding::default().encode(e).unwrap();
+        let constants = constants(&SymbolTable::default(), &genes(&encode("12+3*456"), 0));
+        assert_eq!(constants, vec![(0, 2, 12.0), (3, 4, 3.0), (5, 8, 456.0)]);
+
+        // With one constant per parent, 100 becomes 100 + 0.5 * (30 - 10).
+        let tuning = ConstantTuning { rate: 1.0, weight: 0.5 };
+        let (a, b) = (Chromosome::new(encode("30"), &never), Chromosome::new(encode("10"), &never));
+        assert_eq!(decode(&tuning.apply(encode("100"), &a, &b)), "110");
+        let tuned = decode(&tuning.apply(encode("100*2"), &a, &b));
+        assert!(tuned == "110*2" || tuned == "100*12", "{}", tuned);
+        // Equal parents move the constant by one.
+        let tuned = decode(&tuning.apply(encode("7"), &a, &a));
+        assert!(tuned == "6" || tuned == "8");
+    }
+
+    #[test]
+    pub fn test_seeds() {
+        assert_eq!(SymbolTable::default().encode("6 ** 2*7"), Ok(vec![6, 14, 2, 12, 7]));
+        assert!(SymbolTable::default().encode("6 % 7").is_err());
+
+        let config = GaConfig { popsize: 10, seeds: vec!["6*7".to_string(), "40+2".to_string()],
+                                ..Default::default() };
+        let population = initial_population(&config, &Closeness::new(42.0));
+        assert_eq!(population.len(), 10);
+        assert_eq!(population[0].decode(), "6*7");
+        assert!(population[1].is_solution());
+
+        let config = GaConfig { seeds: vec!["x".to_string()], ..Default::default() };
+        assert!(config.seed_chromosomes(&Closeness::new(42.0)).is_err());
+    }
+
+    #[test]
+    pub fn test_hall_of_fame() {
+        let fitness = |e: &str| 1f64 / (1f64 + e.len() as f64);
+        let c = |x| Chromosome::new(to_binary(x), &fitness);
+        let mut hof = HallOfFame::new(2);
+        hof.update(&[c(0x123), c(0x12345)]);
+        hof.update(&[c(0x12), c(0x12), c(0x1234567)]);
+        let members = hof.members().iter().map(|c| c.decode()).collect::<Vec<_>>();
+        assert_eq!(members, vec!["12", "2301"]);
+    }
+
+    #[test]
+    pub fn test_restart() {
+        let config = GaConfig {
+            popsize: 20,
+            max_gens: 10,
+            restart: Some(Restart { window: 3, keep: 2 }),
+            ..Default::default()
+        };
+        let flat = |_: &str| 0.5;
+        let result = ga_with_observer(&config, &flat, |_, pop, _| pop.len() == 20);
+        assert_eq!(result.restarts, 3);
+    }
+
+    #[test]
+    pub fn test_deterministic_crowding() {
+        let config = GaConfig {
+            popsize: 21,
+            mode: GaMode::DeterministicCrowding,
+            ..Default::default()
+        };
+        let fitness = Closeness::new(1e6);
+        let mut ga = Ga::new(config, &fitness);
+        let mut best = ga.next().unwrap().best().unwrap().fitness;
+        for gen in ga.take(10) {
+            assert_eq!(gen.population.len(), 21);
+            assert!(gen.best().unwrap().fitness >= best);
+            best = gen.best().unwrap().fitness;
+        }
+    }
+
+    #[test]
+    pub fn test_dedup() {
+        let c = |x| Chromosome::new(to_binary(x), &|_: &str| 0.5);
+        let pop = vec![c(0x42), c(0x2f4f), c(0x42), c(0x67)];
+        let exprs = |pop: Vec<Chromosome>| pop.iter().map(|c| c.decode()).collect::<Vec<_>>();
+        assert_eq!(exprs(Dedup::Bits.dedup(pop.clone())), vec!["42", "42", "67"]);
+        assert_eq!(exprs(Dedup::Expression.dedup(pop)), vec!["42", "67"]);
+
+        let config = GaConfig { popsize: 20, dedup: Some(Dedup::Bits), ..Default::default() };
+        for gen in Ga::new(config, &|_: &str| 0.5).take(5) {
+            assert_eq!(gen.population.len(), 20);
+        }
+    }
+
+    #[test]
+    pub fn test_observer_abort() {
+        let config = GaConfig { popsize: 20, ..Default::default() };
+        let never = |_: &str| 0f64;
+        let mut seen = Vec::new();
+        let result = ga_with_observer(&config, &never, |i, pop, _| {
+            seen.push((i, pop.len()));
+            i < 3
+        });
+        assert_eq!(result.generations, 4);
+        assert!(result.solution.is_none());
+        assert!(matches!(result.reason, StopReason::Aborted));
+        assert_eq!(seen, vec![(0, 20), (1, 20), (2, 20), (3, 20)]);
+    }
+
+    #[test]
+    pub fn test_empty_population() {
+        let config = GaConfig { popsize: 0, max_gens: 5, ..Default::default() };
+        let mut observed = false;
+        let result = ga_with_observer(&config, &|_: &str| 0.5, |_, _, _| { observed = true; true });
+        assert!(!observed);
+        assert_eq!(result.generations, 1);
+        assert!(result.solution.is_none() && result.best.is_none());
+        assert!(matches!(result.reason, StopReason::Empty));
+        assert!(Ga::new(config, &|_: &str| 0.5).next().unwrap().best().is_none());
+    }
+}
@@ -0,0 +1,170 @@
+use rand::{Rng, thread_rng};
+use expr::{self, Op, SymbolPolicy};
+use expr::ast::Expr;
+use genetic::{GaConfig, Genome};
+
+/// Maximum depth of the random trees in an initial population.
+const INITIAL_DEPTH: usize = 5;
+/// Offspring deeper than this are discarded in favour of their parents, to
+/// keep trees from bloating.
+const MAX_DEPTH: usize = 12;
+const OPS: [Op; 5] = [Op::Add, Op::Sub, Op::Mul, Op::Div, Op::Exp];
+
+/// An expression tree over single digits and binary operators, for
+/// tree-based genetic programming with `genetic::evolve()`.
+#[derive(Debug,Clone,PartialEq)]
+pub enum Tree {
+    Digit(u8),
+    Op(Op, Box<Tree>, Box<Tree>),
+}
+
+impl Tree {
+    /// Return a random tree no deeper than `depth`, growing each branch to a
+    /// random depth.
+    pub fn grow(depth: usize) -> Tree {
+        let policy = SymbolPolicy { operators: OPS.to_vec(), ..Default::default() };
+        Tree::from_expr(expr::random_expr(&mut thread_rng(), depth, &policy))
+    }
+
+    /// Return the tree of `e`, which is made of digits and binary operators.
+    fn from_expr(e: Expr) -> Tree {
+        match e {
+            Expr::Num(n) => Tree::Digit(n as u8),
+            Expr::Binary(op, l, r) => {
+                Tree::Op(op, Box::new(Tree::from_expr(*l)), Box::new(Tree::from_expr(*r)))
+            }
+            _ => panic!("{} is not a tree of digits", e),
+        }
+    }
+
+    /// Return the number of nodes in this tree.
+    pub fn size(&self) -> usize {
+        match *self {
+            Tree::Digit(_) => 1,
+            Tree::Op(_, ref l, ref r) => 1 + l.size() + r.size(),
+        }
+    }
+
+    /// Return the depth of this tree; a single digit has depth 1.
+    pub fn depth(&self) -> usize {
+        match *self {
+            Tree::Digit(_) => 1,
+            Tree::Op(_, ref l, ref r) => 1 + l.depth().max(r.depth()),
+        }
+    }
+
+    /// Return the subtree rooted at node `n`, numbering nodes in preorder.
+    pub fn subtree(&self, n: usize) -> &Tree {
+        match *self {
+            _ if n == 0 => self,
+            Tree::Op(_, ref l, ref r) => {
+                let left = l.size();
+                if n <= left { l.subtree(n - 1) } else { r.subtree(n - 1 - left) }
+            }
+            Tree::Digit(_) => panic!("No node {} in a leaf", n),
+        }
+    }
+
+    /// Return a copy of this tree with the subtree at node `n` replaced by
+    /// `replacement`.
+    pub fn replace(&self, n: usize, replacement: &Tree) -> Tree {
+        match *self {
+            _ if n == 0 => replacement.clone(),
+            Tree::Op(ref op, ref l, ref r) => {
+                let left = l.size();
+                if n <= left {
+                    Tree::Op(op.clone(), Box::new(l.replace(n - 1, replacement)), r.clone())
+                } else {
+                    Tree::Op(op.clone(), l.clone(), Box::new(r.replace(n - 1 - left, replacement)))
+                }
+            }
+            Tree::Digit(_) => panic!("No node {} in a leaf", n),
+        }
+    }
+
+    /// Return a copy of this tree in which every node is, with probability
+    /// `rate`, replaced by a random node of the same arity.
+    pub fn point_mutate(&self, rate: f64) -> Tree {
+        let mut rng = thread_rng();
+        let mutate = rng.gen_range(0f64, 1f64) < rate;
+        match *self {
+            Tree::Digit(d) => Tree::Digit(if mutate { rng.gen_range(0, 10) } else { d }),
+            Tree::Op(ref op, ref l, ref r) => {
+                let op = if mutate { random_op() } else { op.clone() };
+                Tree::Op(op, Box::new(l.point_mutate(rate)), Box::new(r.point_mutate(rate)))
+            }
+        }
+    }
+}
+
+/// Return a random binary operator.
+fn random_op() -> Op { thread_rng().choose(&OPS).unwrap().clone() }
+
+
+impl Genome for Tree {
+    fn random(_: &GaConfig) -> Tree { Tree::grow(INITIAL_DEPTH) }
+
+    /// Return the expression for this tree, with every operation in
+    /// parentheses.
+    fn expression(&self) -> String {
+        match *self {
+            Tree::Digit(d) => d.to_string(),
+            Tree::Op(ref op, ref l, ref r) =>
+                format!("({}{}{})", l.expression(), op.symbol(), r.expression()),
+        }
+    }
+
+    /// Swap a random subtree of each parent; offspring deeper than the limit
+    /// are replaced by their parents.
+    fn crossover(&self, other: &Tree, _: &GaConfig) -> (Tree, Tree) {
+        let mut rng = thread_rng();
+        let (i, j) = (rng.gen_range(0, self.size()), rng.gen_range(0, other.size()));
+        let a = self.replace(i, other.subtree(j));
+        let b = other.replace(j, self.subtree(i));
+        (if a.depth() <= MAX_DEPTH { a } else { self.clone() },
+         if b.depth() <= MAX_DEPTH { b } else { other.clone() })
+    }
+
+    fn mutate(&self, rate: f64, _: &GaConfig) -> Tree { self.point_mutate(rate) }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use expr;
+    use genetic::{self, Closeness};
+
+    fn op(op: Op, l: Tree, r: Tree) -> Tree { Tree::Op(op, Box::new(l), Box::new(r)) }
+
+    #[test]
+    pub fn test_tree() {
+        // (6*(3+4))
+        let t = op(Op::Mul, Tree::Digit(6), op(Op::Add, Tree::Digit(3), Tree::Digit(4)));
+        assert_eq!(t.expression(), "(6*(3+4))");
+        assert_eq!((t.size(), t.depth()), (5, 3));
+        assert_eq!(t.subtree(3), &Tree::Digit(3));
+        assert_eq!(t.replace(2, &Tree::Digit(7)).expression(), "(6*7)");
+        assert_eq!(t.point_mutate(0.0), t);
+
+        let config = GaConfig::default();
+        for _ in 0..20 {
+            let (a, b) = (Tree::random(&config), Tree::random(&config));
+            assert!(expr::validate(&a.expression()).is_ok());
+            let (c, d) = a.crossover(&b, &config);
+            assert_eq!(c.size() + d.size(), a.size() + b.size());
+        }
+    }
+
+    #[test]
+    pub fn test_evolve_trees() {
+        let config = GaConfig { popsize: 100, max_gens: 50, elitism: 1, ..Default::default() };
+        let result = genetic::evolve::<Tree, _>(&config, &Closeness::new(42.0));
+        if let Some(solution) = result.solution {
+            // Fractional powers can land within EPSILON of 42 rather than on it.
+            let v = expr::eval(&solution.genome.expression()).unwrap();
+            assert!(genetic::is_solution(1f64 / (1f64 + (v - 42f64).abs())));
+        }
+        assert!(result.best.unwrap().fitness > 0.0);
+    }
+}
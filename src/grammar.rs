@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use bit_vec::BitVec;
+
+/// Number of bits in each codon read by the grammatical-evolution decoder.
+pub const CODON_BITS: usize = 8;
+
+/// A BNF grammar for arithmetic expressions over single-digit numbers.
+pub const ARITHMETIC: &str = "
+    <expr>  ::= <expr> <op> <expr> | ( <expr> <op> <expr> ) | <num>
+    <op>    ::= + | - | * | / | **
+    <num>   ::= <digit> | <digit> <num>
+    <digit> ::= 0 | 1 | 2 | 3 | 4 | 5 | 6 | 7 | 8 | 9
+";
+
+/// A symbol on the right hand side of a production.
+#[derive(Debug,Clone,PartialEq)]
+pub enum Symbol {
+    Terminal(String),
+    /// A reference to a rule, by index.
+    Rule(usize),
+}
+
+/// A context-free grammar used to decode chromosomes by grammatical
+/// evolution.
+///
+/// Decoding starts from the first rule and repeatedly expands the leftmost
+/// nonterminal, picking the production given by the next codon (8 bits)
+/// modulo the number of productions. Rules with a single production consume
+/// no codon. When the codons run out they are reused from the start, up to
+/// `wraps` times; any nonterminals still left are then expanded along the
+/// quickest way to terminals. Every chromosome hence decodes to a sentence of
+/// the grammar.
+#[derive(Debug,Clone)]
+pub struct Grammar {
+    rules: Vec<Vec<Vec<Symbol>>>,
+    /// For each rule, the production that terminates soonest.
+    shortest: Vec<usize>,
+    /// Number of times the codons may be reused.
+    pub wraps: usize,
+}
+
+impl Default for Grammar {
+    fn default() -> Grammar { Grammar::parse(ARITHMETIC).unwrap() }
+}
+
+impl Grammar {
+    /// Parse a grammar written as one `<rule> ::= alternative | ...` per line.
+    /// Nonterminals are written in angle brackets, and any other run of
+    /// non-blank characters is a terminal. The first rule is the start rule.
+    pub fn parse(bnf: &str) -> Result<Grammar, String> {
+        let lines = bnf.lines().map(|l| l.trim()).filter(|l| !l.is_empty()).collect::<Vec<_>>();
+        let mut names = HashMap::new();
+        for line in &lines {
+            let lhs = line.split("::=").next().unwrap().trim();
+            if !line.contains("::=") || !lhs.starts_with('<') || !lhs.ends_with('>') {
+                return Err(format!("Malformed rule: {}", line));
+            }
+            let n = names.len();
+            names.entry(lhs.to_string()).or_insert(n);
+        }
+        let mut rules = vec![Vec::new(); names.len()];
+        for line in &lines {
+            let mut sides = line.splitn(2, "::=");
+            let lhs = sides.next().unwrap().trim();
+            for alternative in sides.next().unwrap().split('|') {
+                let production = parse_production(alternative, &names)?;
+                rules[names[lhs]].push(production);
+            }
+        }
+        let shortest = shortest_productions(&rules)?;
+        Ok(Grammar { rules, shortest, wraps: 2 })
+    }
+
+    /// Return the sentence selected by the codons in `b` from bit `start`.
+    pub fn decode(&self, b: &BitVec, start: usize) -> String {
+        let codons = (start..b.len()).step_by(CODON_BITS)
+                                     .filter(|&i| i + CODON_BITS <= b.len())
+                                     .map(|i| (i..i + CODON_BITS)
+                                              .fold(0, |acc, j| (acc << 1) | b[j] as usize))
+                                     .collect::<Vec<_>>();
+        let budget = codons.len() * (1 + self.wraps);
+        let mut used = 0;
+        let mut sentence = String::new();
+        let mut stack = vec![Symbol::Rule(0)];
+        while let Some(symbol) = stack.pop() {
+            match symbol {
+                Symbol::Terminal(t) => sentence.push_str(&t),
+                Symbol::Rule(r) => {
+                    let productions = &self.rules[r];
+                    let choice = if productions.len() == 1 {
+                        0
+                    } else if used < budget {
+                        used += 1;
+                        codons[(used - 1) % codons.len()] % productions.len()
+                    } else {
+                        self.shortest[r]
+                    };
+                    stack.extend(productions[choice].iter().rev().cloned());
+                }
+            }
+        }
+        sentence
+    }
+}
+
+/// Parse one alternative of a rule into a production.
+fn parse_production(alternative: &str, names: &HashMap<String, usize>)
+        -> Result<Vec<Symbol>, String> {
+    let mut production = Vec::new();
+    let mut rest = alternative.trim();
+    while !rest.is_empty() {
+        let end = if rest.starts_with('<') {
+            rest.find('>').map(|i| i + 1).ok_or(format!("Unclosed nonterminal: {}", rest))?
+        } else {
+            rest.find(|c: char| c.is_whitespace() || c == '<').unwrap_or(rest.len())
+        };
+        let (token, tail) = rest.split_at(end);
+        if token.starts_with('<') {
+            let rule = names.get(token).ok_or(format!("Undefined nonterminal: {}", token))?;
+            production.push(Symbol::Rule(*rule));
+        } else {
+            production.push(Symbol::Terminal(token.to_string()));
+        }
+        rest = tail.trim_start();
+    }
+    Ok(production)
+}
+
+/// Return, for each rule, the index of the production leading to terminals
+/// in the fewest expansions, or an error if some rule can never terminate.
+fn shortest_productions(rules: &[Vec<Vec<Symbol>>]) -> Result<Vec<usize>, String> {
+    let mut depth: Vec<Option<usize>> = vec![None; rules.len()];
+    let mut shortest = vec![0; rules.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (r, productions) in rules.iter().enumerate() {
+            for (p, production) in productions.iter().enumerate() {
+                let d = production.iter().try_fold(0, |acc, s| match *s {
+                    Symbol::Terminal(_) => Some(acc),
+                    Symbol::Rule(other) => depth[other].map(|d| acc.max(d)),
+                }).map(|d| d + 1);
+                if let Some(d) = d {
+                    if depth[r].is_none_or(|current| d < current) {
+                        depth[r] = Some(d);
+                        shortest[r] = p;
+                        changed = true;
+                    }
+                }
+            }
+        }
+    }
+    match depth.iter().position(|d| d.is_none()) {
+        Some(r) => Err(format!("Rule {} never terminates", r)),
+        None => Ok(shortest),
+    }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use expr;
+    use genetic::to_binary;
+
+    #[test]
+    pub fn test_grammar() {
+        let grammar = Grammar::default();
+        // expr -> num (2), num -> digit (0), digit -> 7 (7).
+        assert_eq!(grammar.decode(&to_binary(0x070002), 0), "7");
+        // Out of codons, everything is finished off as quickly as possible.
+        assert_eq!(grammar.decode(&BitVec::new(), 0), "0");
+        // expr -> expr op expr, expr -> num -> digit -> 6, op -> **, etc.
+        assert_eq!(grammar.decode(&to_binary(0x0300_0204_0600_0200), 0), "6**3");
+        for _ in 0..100 {
+            let b = BitVec::from_fn(64, |_| ::rand::random());
+            let e = grammar.decode(&b, 0);
+            assert!(expr::postfix(&e).is_ok(), "{}", e);
+        }
+
+        assert!(Grammar::parse("<a> ::= <a> x").is_err());
+        assert!(Grammar::parse("<a> ::= <b>").is_err());
+    }
+}
@@ -0,0 +1,314 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use genetic::{self, Chromosome, Fitness, GaConfig};
+
+/// Which islands send migrants to which.
+#[derive(Debug,Clone)]
+pub enum Topology {
+    /// Island `i` sends migrants to island `i + 1`, and the last island to the
+    /// first.
+    Ring,
+    /// Every island sends migrants to every other island.
+    FullyConnected,
+}
+
+/// Parameters controlling an island model run.
+#[derive(Debug,Clone)]
+pub struct IslandConfig {
+    /// Number of islands, i.e., independent sub-populations.
+    pub islands: usize,
+    /// Number of generations between migrations.
+    pub migration_interval: usize,
+    /// Number of its fittest chromosomes each island sends to each of its
+    /// destinations on every migration. Migrants replace the least fit
+    /// chromosomes of the receiving island.
+    pub migrants: usize,
+    pub topology: Topology,
+    /// Parameters for the GA run on each island. `ga.popsize` is the size of
+    /// each island, and `ga.max_gens` bounds the whole run.
+    pub ga: GaConfig,
+}
+
+impl Default for IslandConfig {
+    fn default() -> IslandConfig {
+        IslandConfig {
+            islands: 4,
+            migration_interval: 10,
+            migrants: 2,
+            topology: Topology::Ring,
+            ga: GaConfig { popsize: 125, ..Default::default() },
+        }
+    }
+}
+
+/// The outcome of an island model run.
+pub struct IslandResult {
+    /// Number of generations run.
+    pub generations: usize,
+    /// The solution found, if any, and the index of the island it was found on.
+    pub solution: Option<(usize, Chromosome)>,
+    /// The fittest chromosome on each island at the end of the run.
+    pub best: Vec<Chromosome>,
+}
+
+/// Run the island model, maximising `fitness`, until a solution is found on
+/// any island or `config.ga.max_gens` generations have passed.
+pub fn evolve<F: Fitness + ?Sized>(config: &IslandConfig, fitness: &F) -> IslandResult {
+    if config.ga.popsize == 0 {
+        return result(0, None, &[]);
+    }
+    let mut islands = (0..config.islands)
+        .map(|_| genetic::initial_population(&config.ga, fitness))
+        .collect::<Vec<_>>();
+    let mut generation = 0;
+    loop {
+        if let Some(found) = solution(&islands) {
+            return result(generation, Some(found), &islands);
+        }
+        if generation >= config.ga.max_gens {
+            return result(generation, None, &islands);
+        }
+        islands = islands.iter()
+                         .map(|pop| genetic::ga_epoch(pop, generation, &config.ga, fitness))
+                         .collect();
+        generation += 1;
+        if config.migration_interval > 0 && generation % config.migration_interval == 0 {
+            migrate(&mut islands, config.migrants, &config.topology);
+        }
+    }
+}
+
+/// Return the first solution found on any island, with the island's index.
+fn solution(islands: &[Vec<Chromosome>]) -> Option<(usize, Chromosome)> {
+    islands.iter().enumerate().filter_map(|(i, pop)| {
+        pop.iter().find(|c| c.is_solution()).map(|c| (i, c.clone()))
+    }).next()
+}
+
+fn result(generations: usize, solution: Option<(usize, Chromosome)>,
+          islands: &[Vec<Chromosome>]) -> IslandResult {
+    let best = islands.iter()
+                      .filter_map(|pop| genetic::fittest(pop, 1).pop())
+                      .collect();
+    IslandResult { generations, solution, best }
+}
+
+/// Return the indices of the islands that island `i` of `n` sends migrants to.
+fn destinations(topology: &Topology, i: usize, n: usize) -> Vec<usize> {
+    match *topology {
+        Topology::Ring if n > 1 => vec![(i + 1) % n],
+        Topology::Ring => Vec::new(),
+        Topology::FullyConnected => (0..n).filter(|&j| j != i).collect(),
+    }
+}
+
+/// Send clones of the `migrants` fittest chromosomes of each island to its
+/// destinations, where they replace the least fit chromosomes.
+pub fn migrate(islands: &mut [Vec<Chromosome>], migrants: usize, topology: &Topology) {
+    let n = islands.len();
+    let mut incoming = vec![Vec::new(); n];
+    for (i, pop) in islands.iter().enumerate() {
+        let emigrants = genetic::fittest(pop, migrants);
+        for j in destinations(topology, i, n) {
+            incoming[j].extend(emigrants.iter().cloned());
+        }
+    }
+    for (pop, arrivals) in islands.iter_mut().zip(incoming) {
+        settle(pop, arrivals);
+    }
+}
+
+/// Replace the least fit chromosomes of `pop` with `arrivals`.
+fn settle(pop: &mut Vec<Chromosome>, arrivals: Vec<Chromosome>) {
+    let stay = pop.len().saturating_sub(arrivals.len());
+    let mut next = genetic::fittest(pop, stay);
+    next.extend(arrivals.into_iter().take(pop.len()));
+    *pop = next;
+}
+
+/// Progress reports sent by island threads in a threaded run.
+#[derive(Clone)]
+pub enum Event {
+    /// An island finished breeding a generation.
+    Progress { island: usize, generation: usize, best: Chromosome },
+    /// An island found a solution.
+    Solved { island: usize, generation: usize, solution: Chromosome },
+    /// An island stopped, either because the run was over or because it was
+    /// told to shut down.
+    Finished { island: usize, generation: usize, best: Chromosome },
+}
+
+/// Like `evolve()`, but run each island on its own thread. Islands exchange
+/// migrants over channels without waiting for each other, and all of them
+/// are shut down as soon as any finds a solution.
+pub fn evolve_threaded<F: Fitness + ?Sized>(config: &IslandConfig, fitness: &F) -> IslandResult {
+    evolve_threaded_with_observer(config, fitness, |_| {})
+}
+
+/// Like `evolve_threaded()`, but call `observer` with every event sent by
+/// the islands, in the order in which they arrive.
+pub fn evolve_threaded_with_observer<F, O>(config: &IslandConfig, fitness: &F, mut observer: O)
+        -> IslandResult
+        where F: Fitness + ?Sized,
+              O: FnMut(&Event) {
+    // Empty islands have no fittest chromosome to report.
+    if config.ga.popsize == 0 {
+        return result(0, None, &[]);
+    }
+    let n = config.islands;
+    let stop = AtomicBool::new(false);
+    let (events, reports) = mpsc::channel();
+    let (senders, inboxes): (Vec<_>, Vec<_>) = (0..n).map(|_| mpsc::channel()).unzip();
+
+    thread::scope(|scope| {
+        for (i, inbox) in inboxes.into_iter().enumerate() {
+            let outboxes = destinations(&config.topology, i, n).into_iter()
+                                                                .map(|j| senders[j].clone())
+                                                                .collect();
+            let events = events.clone();
+            let stop = &stop;
+            scope.spawn(move || island(i, config, fitness, inbox, outboxes, events, stop));
+        }
+        drop(events);
+
+        let mut generations = 0;
+        let mut solution = None;
+        let mut best = vec![None; n];
+        for event in reports {
+            observer(&event);
+            match event {
+                Event::Solved { island, generation, solution: c } => {
+                    stop.store(true, Ordering::SeqCst);
+                    if solution.is_none() {
+                        solution = Some((island, c));
+                        generations = generation;
+                    }
+                }
+                Event::Finished { island, generation, best: c } => {
+                    best[island] = Some(c);
+                    if solution.is_none() {
+                        generations = generations.max(generation);
+                    }
+                }
+                Event::Progress { .. } => {}
+            }
+        }
+        IslandResult { generations, solution, best: best.into_iter().flatten().collect() }
+    })
+}
+
+/// Evolve island number `i`, sending migrants to `outboxes`, receiving them
+/// from `inbox`, and reporting to `events`, until the run is over or `stop`
+/// is set.
+fn island<F: Fitness + ?Sized>(i: usize, config: &IslandConfig, fitness: &F,
+                               inbox: Receiver<Vec<Chromosome>>,
+                               outboxes: Vec<Sender<Vec<Chromosome>>>,
+                               events: Sender<Event>, stop: &AtomicBool) {
+    let mut pop = genetic::initial_population(&config.ga, fitness);
+    let mut generation = 0;
+    loop {
+        if let Some(c) = pop.iter().find(|c| c.is_solution()) {
+            let _ = events.send(Event::Solved { island: i, generation, solution: c.clone() });
+            break;
+        }
+        if generation >= config.ga.max_gens || stop.load(Ordering::SeqCst) {
+            break;
+        }
+        pop = genetic::ga_epoch(&pop, generation, &config.ga, fitness);
+        generation += 1;
+        if config.migration_interval > 0 && generation % config.migration_interval == 0 {
+            let emigrants = genetic::fittest(&pop, config.migrants);
+            for outbox in &outboxes {
+                // The receiving island may already have shut down.
+                let _ = outbox.send(emigrants.clone());
+            }
+        }
+        while let Ok(arrivals) = inbox.try_recv() {
+            settle(&mut pop, arrivals);
+        }
+        let best = genetic::fittest(&pop, 1).remove(0);
+        let _ = events.send(Event::Progress { island: i, generation, best });
+    }
+    let best = genetic::fittest(&pop, 1).remove(0);
+    let _ = events.send(Event::Finished { island: i, generation, best });
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use genetic;
+
+    fn island(fitnesses: &[f64]) -> Vec<Chromosome> {
+        fitnesses.iter().map(|&f| {
+            Chromosome::new(genetic::to_binary(1), &move |_: &str| f)
+        }).collect()
+    }
+
+    fn fitnesses(pop: &[Chromosome]) -> Vec<f64> {
+        let mut f = pop.iter().map(|c| c.fitness).collect::<Vec<_>>();
+        f.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        f
+    }
+
+    #[test]
+    pub fn test_ring_migration() {
+        let mut islands = vec![island(&[0.1, 0.2, 0.3]),
+                               island(&[0.4, 0.5, 0.6]),
+                               island(&[0.7, 0.8, 0.9])];
+        migrate(&mut islands, 1, &Topology::Ring);
+        assert_eq!(fitnesses(&islands[0]), vec![0.2, 0.3, 0.9]);
+        assert_eq!(fitnesses(&islands[1]), vec![0.3, 0.5, 0.6]);
+        assert_eq!(fitnesses(&islands[2]), vec![0.6, 0.8, 0.9]);
+    }
+
+    #[test]
+    pub fn test_threaded_shutdown() {
+        let config = IslandConfig {
+            islands: 3,
+            ga: GaConfig { popsize: 10, max_gens: 5, ..Default::default() },
+            ..Default::default()
+        };
+        let mut finished = 0;
+        let result = evolve_threaded_with_observer(&config, &|_: &str| 0.5, |e| {
+            if let Event::Finished { .. } = *e {
+                finished += 1;
+            }
+        });
+        assert_eq!(finished, 3);
+        assert_eq!(result.generations, 5);
+        assert!(result.solution.is_none());
+        assert_eq!(result.best.len(), 3);
+
+        let result = evolve_threaded(&config, &|_: &str| 1.0);
+        assert_eq!(result.generations, 0);
+        assert!(result.solution.is_some());
+    }
+
+    #[test]
+    pub fn test_empty_islands() {
+        let config = IslandConfig {
+            islands: 2,
+            ga: GaConfig { popsize: 0, max_gens: 5, ..Default::default() },
+            ..Default::default()
+        };
+        for result in [evolve(&config, &|_: &str| 0.5),
+                           evolve_threaded(&config, &|_: &str| 0.5)] {
+            assert_eq!(result.generations, 0);
+            assert!(result.solution.is_none());
+            assert!(result.best.is_empty());
+        }
+    }
+
+    #[test]
+    pub fn test_fully_connected_migration() {
+        let mut islands = vec![island(&[0.1, 0.2, 0.3]),
+                               island(&[0.4, 0.5, 0.6]),
+                               island(&[0.7, 0.8, 0.9])];
+        migrate(&mut islands, 1, &Topology::FullyConnected);
+        assert_eq!(fitnesses(&islands[0]), vec![0.3, 0.6, 0.9]);
+        assert_eq!(fitnesses(&islands[1]), vec![0.3, 0.6, 0.9]);
+        assert_eq!(fitnesses(&islands[2]), vec![0.3, 0.6, 0.9]);
+    }
+}
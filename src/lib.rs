@@ -1,6 +1,23 @@
 extern crate num;
 extern crate rand;
 extern crate bit_vec;
+extern crate rayon;
+#[cfg(feature = "serde")]
+extern crate serde;
+pub mod bench;
+pub mod countdown;
+pub mod digits;
 pub mod expr;
 pub mod genetic;
+pub mod gp;
+pub mod grammar;
+pub mod islands;
+pub mod map_elites;
+pub mod multi;
+pub mod novelty;
+pub mod nsga2;
+pub mod reformulation;
+pub mod regression;
+pub mod solvers;
+pub mod tune;
 
@@ -1,29 +1,429 @@
 extern crate exprolution;
+extern crate num;
 
 use std::env;
-use exprolution::genetic;
+use std::fs::File;
+use std::io::BufReader;
+use std::process;
+use num::BigInt;
+use exprolution::bench::{self, BenchConfig};
+use exprolution::digits::DigitLimits;
+use exprolution::expr::{self, ast};
+use exprolution::genetic::{Dedup, Encoding, GaConfig, Initialization, LengthDistribution,
+                           Repair, SymbolTable, Tolerance, UndefinedPolicy};
+use exprolution::multi::{Aggregate, Constraint, MultiCloseness};
+use exprolution::reformulation::Reformulation;
+use exprolution::regression;
+use exprolution::solvers::{Algorithm, AnnealingSolver, Budget, ExhaustiveSolver, GaSolver,
+                           RandomSearchSolver, Solver, Target};
+use exprolution::solvers::annealing::AnnealingConfig;
+use exprolution::solvers::exhaustive::EnumerationConfig;
 
+const USAGE: &str = "Usage: exprolution [--solver ga|annealing|random|exhaustive] \
+                     [--parens] [--operators OP,OP,...] [--digits DIGITS] [--max-uses N] \
+                     [--partial-credit C] [--undefined-penalty P] [--tolerance ABS] \
+                     [--rel-tolerance REL] [--exact | --integer] [--solutions K] \
+                     [--simplify] [--budget EVALUATIONS] [--seed-expr EXPR]... \
+                     [--numbers N,N,...] TARGET
+       exprolution [--solver ...] [--budget EVALUATIONS] [--seed-expr EXPR]... \
+                     [--weights W,W,...] [--aggregate mean|worst] [X:]TARGET...
+       exprolution [--solver ...] [--budget EVALUATIONS] [--seed-expr EXPR]... EXPR
+       exprolution [--solver ...] [--budget EVALUATIONS] [--seed-expr EXPR]... --big N
+       exprolution [--solver ...] [--budget EVALUATIONS] [--seed-expr EXPR]... --data FILE
+       exprolution bench [--repeats N] [--budget EVALUATIONS] [--csv FILE] [TARGET]...";
 
-fn main() {
-    let args = env::args().collect::<Vec<_>>();
+/// Command line options.
+struct Options {
+    target: Target,
+    seeds: Vec<String>,
+    algorithm: Algorithm,
+    budget: Budget,
+    /// The operators expressions may use, if restricted.
+    operators: Option<Vec<String>>,
+    /// Partial credit for malformed expressions, if any.
+    partial_credit: Option<f64>,
+    /// How expressions without a value, like `1/0`, are scored.
+    undefined: UndefinedPolicy,
+    /// Number of distinct solutions to look for.
+    solutions: usize,
+    /// If true, expressions may use parentheses.
+    parens: bool,
+    /// If true, solutions are also shown simplified.
+    simplify: bool,
+}
+
+/// Parse the command line arguments (without the program name).
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut constraints = Vec::new();
+    let mut weights = None;
+    let mut aggregate = Aggregate::Mean;
+    let mut expression = None;
+    let mut operators = None;
+    let mut limits = DigitLimits::default();
+    let mut partial_credit = None;
+    let mut undefined = UndefinedPolicy::Zero;
+    let mut tolerance = Tolerance::default();
+    let mut solutions = 1;
+    let mut parens = false;
+    let mut simplify = false;
+    let mut exact = false;
+    let mut integer = false;
+    let mut big = None;
+    let mut seeds = Vec::new();
+    let mut algorithm = Algorithm::Ga;
+    let mut budget = Budget::default();
+    let mut numbers = None;
+    let mut data = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--numbers" => {
+                let ns = args.next().ok_or("--numbers needs a comma-separated list")?;
+                numbers = Some(ns.split(',')
+                                 .map(|n| n.trim().parse::<u32>()
+                                           .map_err(|_| format!("{} is not a valid number", n)))
+                                 .collect::<Result<Vec<_>, _>>()?);
+            }
+            "--data" => {
+                let path = args.next().ok_or("--data needs a file name")?;
+                let file = File::open(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+                data = Some(regression::read_data(BufReader::new(file))
+                                .map_err(|e| format!("{}: {}", path, e))?);
+            }
+            "--weights" => {
+                let ws = args.next().ok_or("--weights needs a comma-separated list")?;
+                weights = Some(ws.split(',')
+                                 .map(|w| w.trim().parse::<f64>()
+                                           .map_err(|_| format!("{} is not a valid weight", w)))
+                                 .collect::<Result<Vec<_>, _>>()?);
+            }
+            "--aggregate" => {
+                aggregate = match &args.next().ok_or("--aggregate needs mean or worst")?[..] {
+                    "mean" => Aggregate::Mean,
+                    "worst" => Aggregate::Worst,
+                    a => return Err(format!("Unknown aggregate: {}", a)),
+                };
+            }
+            "--operators" => {
+                let ops = args.next().ok_or("--operators needs a comma-separated list")?;
+                operators = Some(ops.split(',').map(|op| op.trim().to_string()).collect());
+            }
+            "--digits" => {
+                let digits = args.next().ok_or("--digits needs the digits allowed")?;
+                if let Some(c) = digits.chars().find(|c| !c.is_ascii_digit()) {
+                    return Err(format!("{} is not a digit", c));
+                }
+                limits.allowed = digits.chars().collect();
+            }
+            "--max-uses" => {
+                let n = args.next().ok_or("--max-uses needs a number")?;
+                limits.max_uses =
+                    Some(n.parse().map_err(|_| format!("{} is not a valid count", n))?);
+            }
+            "--partial-credit" => {
+                let c = args.next().ok_or("--partial-credit needs a number")?;
+                partial_credit =
+                    Some(c.parse().map_err(|_| format!("{} is not a valid credit", c))?);
+            }
+            "--undefined-penalty" => {
+                let p = args.next().ok_or("--undefined-penalty needs a number")?;
+                undefined = UndefinedPolicy::Penalty(
+                    p.parse().map_err(|_| format!("{} is not a valid penalty", p))?);
+            }
+            "--tolerance" | "--rel-tolerance" => {
+                let t = args.next().ok_or_else(|| format!("{} needs a number", arg))?;
+                let t = t.parse().map_err(|_| format!("{} is not a valid tolerance", t))?;
+                if arg == "--tolerance" { tolerance.absolute = t } else { tolerance.relative = t }
+            }
+            "--parens" => parens = true,
+            "--exact" => exact = true,
+            "--integer" => integer = true,
+            "--big" => {
+                let n = args.next().ok_or("--big needs a whole number")?;
+                big = Some(n.parse::<BigInt>()
+                            .map_err(|_| format!("{} is not a whole number", n))?);
+            }
+            "--simplify" => simplify = true,
+            "--solutions" => {
+                let n = args.next().ok_or("--solutions needs a number")?;
+                solutions = n.parse().map_err(|_| format!("{} is not a valid count", n))?;
+            }
+            "--seed-expr" => {
+                let e = args.next().ok_or("--seed-expr needs an expression")?;
+                if e.trim_start().starts_with('[') {
+                    let tree = expr::serial::from_json(e).map_err(|err| err.diagnostic(e))?;
+                    seeds.push(tree.to_string());
+                } else {
+                    expr::validate(e).map_err(|err| err.diagnostic(e))?;
+                    seeds.push(e.clone());
+                }
+            }
+            "--solver" => {
+                algorithm = args.next().ok_or("--solver needs a name")?.parse()?;
+            }
+            "--budget" => {
+                let n = args.next().ok_or("--budget needs a number of evaluations")?;
+                budget.evaluations = n.parse().map_err(|_| format!("{} is not a valid budget", n))?;
+            }
+            _ => match parse_constraint(arg) {
+                Ok(c) => constraints.push(c),
+                Err(_) if expression.is_none() && Reformulation::new(arg).is_ok() => {
+                    expression = Reformulation::new(arg).ok();
+                }
+                Err(e) => return Err(e),
+            },
+        }
+    }
+    if let Some(n) = big {
+        if !constraints.is_empty() || expression.is_some() || data.is_some() ||
+           numbers.is_some() {
+            return Err("--big takes no other targets".to_string());
+        }
+        let target = limited(Target::Big(n), limits);
+        return Ok(Options { target, seeds, algorithm, budget, operators, partial_credit,
+                            undefined, solutions, parens, simplify });
+    }
+    if let Some(objective) = expression {
+        if !constraints.is_empty() || data.is_some() || numbers.is_some() {
+            return Err("A target expression must be the only target".to_string());
+        }
+        let target = limited(Target::Reformulation(objective), limits);
+        return Ok(Options { target, seeds, algorithm, budget, operators, partial_credit,
+                            undefined, solutions, parens, simplify });
+    }
+    if let Some(weights) = weights {
+        if weights.len() != constraints.len() {
+            return Err("Need as many weights as targets".to_string());
+        }
+        for (c, w) in constraints.iter_mut().zip(weights) {
+            c.weight = w;
+        }
+    }
+    let target = match (data, numbers) {
+        (Some(_), Some(_)) => return Err("--data and --numbers do not mix".to_string()),
+        (Some(_), None) if !constraints.is_empty() => {
+            return Err("--data takes no targets".to_string());
+        }
+        (Some(data), None) => Target::Regression(data),
+        (None, _) if constraints.is_empty() => return Err("Need a number".to_string()),
+        (None, Some(numbers)) => match constraints[..] {
+            [Constraint { x: None, target, .. }] => Target::Countdown { numbers, target },
+            _ => return Err("--numbers takes a single target".to_string()),
+        },
+        (None, None) => match constraints[..] {
+            [Constraint { x: None, target, .. }] => Target::Value(target),
+            _ => Target::Multi(MultiCloseness::new(constraints, aggregate)),
+        },
+    };
+    if exact && integer {
+        return Err("--exact and --integer do not mix".to_string());
+    }
+    let flag = if integer { "--integer" } else { "--exact" };
+    let target = match target {
+        _ if tolerance == Tolerance::default() && !exact && !integer => target,
+        Target::Value(_) if (exact || integer) && tolerance != Tolerance::default() => {
+            return Err("An exact target takes no tolerance".to_string());
+        }
+        Target::Value(target) if exact => Target::Exact(target),
+        Target::Value(target) if integer => match expr::eval_i64(&target.to_string()) {
+            Ok(n) => Target::Integer(n),
+            Err(_) => return Err(format!("--integer needs a whole number, not {}", target)),
+        },
+        Target::Value(target) => Target::Approx { target, tolerance },
+        _ if exact || integer => return Err(format!("{} needs a single target number", flag)),
+        _ => return Err("A tolerance needs a single target number".to_string()),
+    };
+    let target = limited(target, limits);
+    Ok(Options { target, seeds, algorithm, budget, operators, partial_credit, undefined,
+                 solutions, parens, simplify })
+}
+
+/// Print `e` in readable form, with consistent spacing and no redundant
+/// parentheses, unless it is malformed or is wanted `verbatim`, followed by
+/// its simplified form if asked to `simplify` and that differs.
+fn show(e: &str, verbatim: bool, simplify: bool) {
+    let tree = match ast::parse(e) {
+        Ok(tree) => tree,
+        Err(_) => return println!("\t{}", e),
+    };
+    let readable = if verbatim { e.to_string() } else { tree.to_string() };
+    println!("\t{}", readable);
+    if simplify {
+        let simplified = expr::simplify(tree).to_string();
+        if simplified != readable {
+            println!("\t= {}", simplified);
+        }
+    }
+}
 
-    if args.len() < 2 {
-        println!("Need a number");
+/// Return `target` subject to `limits`, unless they allow anything.
+fn limited(target: Target, limits: DigitLimits) -> Target {
+    if limits == DigitLimits::default() {
+        target
+    } else {
+        Target::Digits { target: Box::new(target), limits }
+    }
+}
+
+/// Parse a target, given as `TARGET` or as `X:TARGET` to hit it when the
+/// variable `x` is `X`.
+fn parse_constraint(arg: &str) -> Result<Constraint, String> {
+    let number = |s: &str| s.parse::<f64>().map_err(|_| format!("{} is not a valid number", s));
+    match arg.find(':') {
+        Some(i) => Ok(Constraint::new(Some(number(&arg[..i])?), number(&arg[i + 1..])?)),
+        None => Ok(Constraint::new(None, number(arg)?)),
+    }
+}
+
+/// Parse the arguments of the bench subcommand, returning the benchmark
+/// configuration and the CSV file to write, if any.
+fn parse_bench_args(args: &[String]) -> Result<(BenchConfig, Option<String>), String> {
+    let mut config = BenchConfig::default();
+    let mut targets = Vec::new();
+    let mut csv = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--repeats" => {
+                let n = args.next().ok_or("--repeats needs a number")?;
+                config.repeats = n.parse().map_err(|_| format!("{} is not a valid count", n))?;
+            }
+            "--budget" => {
+                let n = args.next().ok_or("--budget needs a number of evaluations")?;
+                config.budget.evaluations =
+                    n.parse().map_err(|_| format!("{} is not a valid budget", n))?;
+            }
+            "--csv" => csv = Some(args.next().ok_or("--csv needs a file name")?.clone()),
+            _ => targets.push(arg.parse::<f64>()
+                                 .map_err(|_| format!("{} is not a valid number", arg))?),
+        }
+    }
+    if !targets.is_empty() {
+        config.targets = targets;
+    }
+    Ok((config, csv))
+}
+
+/// Run the bench subcommand.
+fn bench_main(args: &[String]) {
+    let (config, csv) = parse_bench_args(args).unwrap_or_else(|e| {
+        println!("{}\n{}", e, USAGE);
+        process::exit(1);
+    });
+    let runs = bench::bench(&config);
+    println!("{:<12}{:>10}{:>14}{:>12}", "solver", "success", "generations", "seconds");
+    for s in bench::summarize(&runs) {
+        println!("{:<12}{:>9.0}%{:>14}{:>12.3}", s.solver.to_string(), 100f64 * s.success_rate,
+                 s.median_generations, s.median_seconds);
+    }
+    if let Some(path) = csv {
+        let written = File::create(&path).and_then(|mut f| bench::write_csv(&runs, &mut f));
+        if let Err(e) = written {
+            println!("Could not write {}: {}", path, e);
+            process::exit(1);
+        }
+    }
+}
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    if args.first().is_some_and(|a| a == "bench") {
+        bench_main(&args[1..]);
         return;
     }
+    let options = parse_args(&args).unwrap_or_else(|e| {
+        println!("{}\n{}", e, USAGE);
+        process::exit(1);
+    });
 
-    let num = args[1].parse::<f64>().expect(
-        &format!("{} is not a valid number", args[1])
-    );
+    let target = options.target;
+    let mut encoding = Encoding::default();
+    if options.parens {
+        encoding.symbols = SymbolTable::parenthesized();
+        encoding.repair = Some(Repair::Decoded);
+    }
+    if options.operators.as_ref().is_some_and(|ops| ops.iter().any(|op| op == "%")) {
+        encoding.symbols = encoding.symbols.with_modulo().unwrap_or_else(|e| {
+            println!("{}", e);
+            process::exit(1);
+        });
+    }
+    let operators = options.operators.unwrap_or_else(|| {
+        encoding.symbols.operators().into_iter().map(|op| op.to_string()).collect()
+    });
+    let operators = operators.iter().map(|op| &op[..]).collect::<Vec<_>>();
+    let config = GaConfig {
+        encoding,
+        partial_credit: options.partial_credit,
+        undefined: options.undefined,
+        solutions: options.solutions,
+        // Keep copies of the first solution from taking over the population.
+        dedup: if options.solutions > 1 { Some(Dedup::Expression) } else { None },
+        ..Default::default()
+    };
+    let restricted = config.with_operators(&operators).unwrap_or_else(|e| {
+        println!("{}", e);
+        process::exit(1);
+    });
+    let encoding = restricted.encoding.clone();
+    let mut solver: Box<dyn Solver> = match options.algorithm {
+        Algorithm::Ga => {
+            let mut config = GaConfig { seeds: options.seeds, ..restricted };
+            if let Some(table) = target.symbols() {
+                config.encoding.symbols = table.with_operators(&operators);
+            }
+            match target {
+                Target::Countdown { ref numbers, .. } => {
+                    // No solution needs more genes than the numbers and the
+                    // operators between them.
+                    config.initialization = Initialization::WellFormed;
+                    config.lengths =
+                        LengthDistribution::Uniform { min: 1, max: 2 * numbers.len() };
+                }
+                Target::Regression(_) | Target::Multi(_) if target.symbols().is_some() => {
+                    // Few random genes that mention x are well formed.
+                    config.initialization = Initialization::WellFormed;
+                    config.lengths = LengthDistribution::Ramped { min: 1, max: 22 };
+                }
+                _ => {}
+            }
+            if let Err(e) = config.seed_chromosomes(&*target.fitness()) {
+                println!("{}", e);
+                process::exit(1);
+            }
+            Box::new(GaSolver { config, verbose: true })
+        }
+        Algorithm::Annealing => {
+            Box::new(AnnealingSolver { config: AnnealingConfig { encoding, ..Default::default() } })
+        }
+        Algorithm::RandomSearch => Box::new(RandomSearchSolver { config: restricted }),
+        Algorithm::Exhaustive => {
+            let config = EnumerationConfig { symbols: encoding.symbols, ..Default::default() };
+            Box::new(ExhaustiveSolver { config })
+        }
+    };
 
-    match genetic::ga(500, num) {
-        (ngens, Some(ref c)) => {
-            println!("Found a solution in {} generations:", ngens);
-            println!("\t{}", c.decode());
+    // Digit limits and expression targets are about the exact spelling.
+    let verbatim = matches!(target, Target::Digits { .. } | Target::Reformulation(_));
+    let result = solver.solve(target, options.budget);
+    match result.solution {
+        Some(_) if result.solutions.len() > 1 => {
+            println!("Found {} solutions in {} generations:", result.solutions.len(),
+                     result.generations);
+            for e in &result.solutions {
+                show(e, verbatim, options.simplify);
+            }
         },
-        (ngens, None) => {
-            println!("Could not find a solution in {} generations.", ngens);
+        Some(ref e) => {
+            println!("Found a solution in {} generations:", result.generations);
+            show(e, verbatim, options.simplify);
+        },
+        None => {
+            println!("Could not find a solution in {} generations.", result.generations);
+            if let Some(ref e) = result.best {
+                println!("The closest miss was:");
+                show(e, verbatim, options.simplify);
+            }
         }
     };    
 }
-
@@ -0,0 +1,180 @@
+use std::collections::BTreeMap;
+use rand::{Rng, thread_rng};
+use rayon::prelude::*;
+use expr::{self, Metrics, Tok};
+use genetic::{self, Chromosome, Fitness, GaConfig};
+
+/// A feature of an expression, used to place chromosomes in the archive.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Feature {
+    /// The number of tokens in the expression.
+    Length,
+    /// The number of operators in the expression.
+    Operators,
+    /// The number of distinct operators in the expression; 1 means, e.g.,
+    /// "multiplication only".
+    DistinctOperators,
+    /// The depth of the expression tree.
+    Depth,
+    /// The number of numbers in the expression.
+    Constants,
+}
+
+impl Feature {
+    /// Return the value of this feature for an expression, given its tokens
+    /// and the metrics of its tree.
+    pub fn measure(&self, toks: &[Tok], metrics: &Metrics) -> usize {
+        match *self {
+            Feature::Length => toks.len(),
+            Feature::Operators => metrics.operators.values().sum(),
+            Feature::DistinctOperators => metrics.operators.len(),
+            Feature::Depth => metrics.depth,
+            Feature::Constants => metrics.constants,
+        }
+    }
+}
+
+/// Parameters for a MAP-Elites run.
+#[derive(Debug,Clone)]
+pub struct MapElitesConfig {
+    /// Parameters for breeding. `popsize` offspring are bred in each of
+    /// `max_gens` iterations.
+    pub ga: GaConfig,
+    /// The features binning chromosomes, each with the width of its bins.
+    pub features: Vec<(Feature, usize)>,
+}
+
+impl Default for MapElitesConfig {
+    fn default() -> MapElitesConfig {
+        MapElitesConfig {
+            ga: GaConfig { max_gens: 100, ..Default::default() },
+            features: vec![(Feature::Length, 2), (Feature::Operators, 1)],
+        }
+    }
+}
+
+/// The fittest chromosome found in each bin of the feature space.
+#[derive(Clone)]
+pub struct EliteMap {
+    features: Vec<(Feature, usize)>,
+    elites: BTreeMap<Vec<usize>, Chromosome>,
+}
+
+impl EliteMap {
+    pub fn new(features: Vec<(Feature, usize)>) -> EliteMap {
+        EliteMap { features, elites: BTreeMap::new() }
+    }
+
+    /// Return the bin a chromosome belongs to, or None if its expression is
+    /// malformed. The bin is given as the index along each feature.
+    pub fn bin(&self, c: &Chromosome) -> Option<Vec<usize>> {
+        c.value()?;
+        let e = c.decode();
+        let (toks, metrics) = (expr::tok(&e).ok()?, expr::metrics(&expr::ast::parse(&e).ok()?));
+        Some(self.features.iter().map(|&(f, width)| f.measure(&toks, &metrics) / width.max(1))
+                              .collect())
+    }
+
+    /// Put `c` in its bin if that is empty or holds a less fit chromosome.
+    /// Return true if `c` was kept.
+    pub fn insert(&mut self, c: Chromosome) -> bool {
+        let bin = match self.bin(&c) {
+            Some(bin) => bin,
+            None => return false,
+        };
+        if self.elites.get(&bin).is_some_and(|e| e.fitness >= c.fitness) {
+            return false;
+        }
+        self.elites.insert(bin, c);
+        true
+    }
+
+    /// Return the elite in a bin, if any.
+    pub fn get(&self, bin: &[usize]) -> Option<&Chromosome> { self.elites.get(bin) }
+
+    /// Iterate over the occupied bins and their elites, in order of bin.
+    pub fn iter(&self) -> ::std::collections::btree_map::Iter<'_, Vec<usize>, Chromosome> {
+        self.elites.iter()
+    }
+
+    /// Return the number of occupied bins.
+    pub fn len(&self) -> usize { self.elites.len() }
+
+    pub fn is_empty(&self) -> bool { self.elites.is_empty() }
+
+    /// Return the fittest elite in the whole map.
+    pub fn best(&self) -> Option<&Chromosome> {
+        self.elites.values().fold(None, |best: Option<&Chromosome>, c| match best {
+            Some(b) if b.fitness >= c.fitness => Some(b),
+            _ => Some(c),
+        })
+    }
+}
+
+/// Fill an EliteMap for `fitness`: start from a random population, then
+/// repeatedly breed offspring from random pairs of elites and keep those
+/// that beat the elite of their bin.
+pub fn map_elites<F: Fitness + ?Sized>(config: &MapElitesConfig, fitness: &F) -> EliteMap {
+    let mut map = EliteMap::new(config.features.clone());
+    let mut offspring = genetic::initial_population(&config.ga, fitness);
+    for _ in 0..config.ga.max_gens {
+        for c in &offspring {
+            map.insert(c.clone());
+        }
+        // Until some well-formed expression turns up, breed from the last
+        // batch of offspring instead.
+        let elites = if map.is_empty() {
+            offspring
+        } else {
+            map.elites.values().cloned().collect::<Vec<_>>()
+        };
+        offspring = (0..config.ga.popsize).into_par_iter().map(|_| {
+            let mut rng = thread_rng();
+            let a = rng.choose(&elites).unwrap();
+            let b = rng.choose(&elites).unwrap();
+            let (c, _) = a.crossover(b, config.ga.crossover_rate, &config.ga.crossover, fitness);
+            c.mutate_with(config.ga.mutation_rate, &config.ga.gene_mutation, fitness)
+        }).collect::<Vec<_>>();
+    }
+    for c in offspring {
+        map.insert(c);
+    }
+    map
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use genetic::{self, Closeness};
+
+    #[test]
+    pub fn test_elite_map() {
+        let closeness = Closeness::new(42.0);
+        let c = |x| Chromosome::new(genetic::to_binary(x), &closeness);
+        let mut map = EliteMap::new(vec![(Feature::Length, 1), (Feature::Operators, 1)]);
+
+        // "6*7" and "6*5" share a bin; only the fitter is kept.
+        assert!(map.insert(c(0x5f6c)));
+        assert!(map.insert(c(0x7f6c)));
+        assert!(!map.insert(c(0x5f6c)));
+        // "42" is alone in its bin; "++" is malformed.
+        assert!(map.insert(c(0x42)));
+        assert!(!map.insert(c(0xaa)));
+
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get(&[3, 1]).unwrap().decode(), "6*7");
+        assert_eq!(map.get(&[1, 0]).unwrap().decode(), "42");
+
+        let map = EliteMap::new(vec![(Feature::Depth, 1), (Feature::Constants, 1),
+                                     (Feature::DistinctOperators, 1)]);
+        assert_eq!(map.bin(&c(0x5f6c)), Some(vec![2, 2, 1]));
+
+        let map = map_elites(&MapElitesConfig {
+            ga: GaConfig { popsize: 100, max_gens: 5, ..Default::default() },
+            ..Default::default()
+        }, &closeness);
+        assert!(!map.is_empty());
+        assert!(map.iter().all(|(bin, c)| map.bin(c).as_ref() == Some(bin)));
+    }
+}
@@ -0,0 +1,109 @@
+use expr;
+use genetic::Fitness;
+
+/// One of the targets of a `MultiCloseness` objective.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Constraint {
+    /// The value of the variable `x` under which the expression must hit
+    /// `target`, if it uses a variable at all.
+    pub x: Option<f64>,
+    pub target: f64,
+    /// How much this target counts towards a weighted mean.
+    pub weight: f64,
+}
+
+impl Constraint {
+    /// Return a constraint with weight 1.
+    pub fn new(x: Option<f64>, target: f64) -> Constraint {
+        Constraint { x, target, weight: 1f64 }
+    }
+
+    /// Return the closeness `1 / (1 + |v - target|)` of the value `v` of `e`
+    /// to the target, or 0 if `e` is malformed or evaluates to NaN.
+    pub fn closeness(&self, e: &str) -> f64 {
+        let v = match self.x {
+            Some(x) => expr::eval_at(e, x),
+            None => expr::eval(e),
+        };
+        match v {
+            Ok(v) if !v.is_nan() => 1f64 / (1f64 + (v - self.target).abs()),
+            _ => 0f64,
+        }
+    }
+}
+
+/// How `MultiCloseness` combines the closeness to each target.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Aggregate {
+    /// The weighted mean closeness.
+    Mean,
+    /// The closeness to the target missed by the most, ignoring weights.
+    Worst,
+}
+
+/// Closeness to several targets at once, each possibly under a different
+/// value of `x`, so that a solution satisfies a small system of constraints.
+/// Either way, only an expression hitting every target scores 1. Each
+/// target is also a test case for lexicase selection.
+#[derive(Debug,Clone,PartialEq)]
+pub struct MultiCloseness {
+    pub constraints: Vec<Constraint>,
+    pub aggregate: Aggregate,
+}
+
+impl MultiCloseness {
+    pub fn new(constraints: Vec<Constraint>, aggregate: Aggregate) -> MultiCloseness {
+        MultiCloseness { constraints, aggregate }
+    }
+
+    /// Return true if some constraint assigns a value to `x`.
+    pub fn uses_variable(&self) -> bool { self.constraints.iter().any(|c| c.x.is_some()) }
+}
+
+impl Fitness for MultiCloseness {
+    fn fitness(&self, e: &str) -> f64 {
+        let scores = self.cases(e);
+        match self.aggregate {
+            Aggregate::Mean => {
+                let total = self.constraints.iter().map(|c| c.weight).sum::<f64>();
+                let weighted = self.constraints.iter()
+                                               .zip(scores)
+                                               .map(|(c, s)| c.weight * s)
+                                               .sum::<f64>();
+                if total > 0f64 { weighted / total } else { 0f64 }
+            }
+            Aggregate::Worst => scores.into_iter().fold(f64::NAN, f64::min).max(0f64),
+        }
+    }
+
+    fn cases(&self, e: &str) -> Vec<f64> {
+        self.constraints.iter().map(|c| c.closeness(e)).collect()
+    }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_multi_closeness() {
+        let constraints = vec![Constraint::new(Some(1.0), 5.0), Constraint::new(Some(2.0), 8.0)];
+        let mean = MultiCloseness::new(constraints.clone(), Aggregate::Mean);
+        let worst = MultiCloseness::new(constraints, Aggregate::Worst);
+        assert!(mean.uses_variable());
+        assert_eq!(mean.fitness("x*3+2"), 1f64);
+        assert_eq!(worst.fitness("x*3+2"), 1f64);
+        assert_eq!(mean.cases("5"), vec![1f64, 1f64 / 4f64]);
+        assert_eq!(mean.fitness("5"), 5f64 / 8f64);
+        assert_eq!(worst.fitness("5"), 1f64 / 4f64);
+        assert_eq!(worst.fitness("x+"), 0f64);
+
+        let weighted = MultiCloseness::new(vec![Constraint { x: None, target: 10.0, weight: 3.0 },
+                                                Constraint::new(None, 12.0)],
+                                           Aggregate::Mean);
+        assert!(!weighted.uses_variable());
+        assert_eq!(weighted.fitness("10"), (3f64 + 1f64 / 3f64) / 4f64);
+        assert_eq!(weighted.fitness("11"), 0.5);
+    }
+}
@@ -0,0 +1,204 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::time::Instant;
+use genetic::{self, Chromosome, Counted, Fitness, GaConfig, GaResult, Generation, HallOfFame,
+              SolutionSet, StopReason};
+
+/// Parameters controlling a novelty search.
+///
+/// Novelty search selects parents for how unlike anything seen before their
+/// behaviour is, rather than (only) for their fitness. This keeps the search
+/// moving across plateaus where fitness gives no guidance. The behaviour of a
+/// chromosome is the value of its expression, compressed with `asinh()` so
+/// that huge values do not dwarf everything else.
+#[derive(Debug,Clone)]
+pub struct NoveltyConfig {
+    /// Parameters for breeding. Fitness is still computed as usual and used
+    /// to recognise a solution.
+    pub ga: GaConfig,
+    /// The novelty of a behaviour is its mean distance to its `k` nearest
+    /// neighbours among the current population and the archive.
+    pub k: usize,
+    /// Behaviours with novelty above this are added to the archive.
+    pub threshold: f64,
+    /// How much selection is driven by novelty rather than fitness, from 0
+    /// (fitness only) to 1 (novelty only).
+    pub weight: f64,
+}
+
+impl Default for NoveltyConfig {
+    fn default() -> NoveltyConfig {
+        NoveltyConfig { ga: Default::default(), k: 15, threshold: 1.0, weight: 1.0 }
+    }
+}
+
+/// Behaviours seen so far that were novel at the time.
+#[derive(Debug,Clone,Default)]
+pub struct Archive {
+    behaviours: Vec<f64>,
+}
+
+impl Archive {
+    pub fn new() -> Archive { Archive::default() }
+
+    /// Return the archived behaviours.
+    pub fn behaviours(&self) -> &[f64] { &self.behaviours }
+}
+
+/// Return the behaviour of a chromosome, or None if it is malformed or its
+/// value is not finite: the distance between two infinite values is NaN.
+pub fn behaviour(c: &Chromosome) -> Option<f64> {
+    c.value().filter(|v| v.is_finite()).map(|v| v.asinh())
+}
+
+/// Return the novelty of each chromosome in `population` with respect to the
+/// rest of the population and `archive`, adding the novel ones to the
+/// archive. Malformed chromosomes have no novelty.
+pub fn novelty(population: &[Chromosome], archive: &mut Archive, k: usize, threshold: f64)
+        -> Vec<f64> {
+    let behaviours = population.iter().map(behaviour).collect::<Vec<_>>();
+    let mut others = behaviours.iter().filter_map(|&b| b).collect::<Vec<_>>();
+    others.extend(archive.behaviours.iter().cloned());
+
+    let scores = behaviours.iter().map(|b| match *b {
+        Some(b) => {
+            let mut distances = others.iter().map(|o| (o - b).abs()).collect::<Vec<_>>();
+            distances.sort_by(|x, y| x.partial_cmp(y).unwrap_or(Ordering::Equal));
+            // The closest "other" is the behaviour itself, at distance 0.
+            let nearest = distances.iter().skip(1).take(k).collect::<Vec<_>>();
+            if nearest.is_empty() {
+                0f64
+            } else {
+                nearest.iter().fold(0f64, |a, &&d| a + d) / nearest.len() as f64
+            }
+        }
+        None => 0f64,
+    }).collect::<Vec<_>>();
+
+    for (b, &score) in behaviours.iter().zip(&scores) {
+        if let Some(b) = *b {
+            if score > threshold {
+                archive.behaviours.push(b);
+            }
+        }
+    }
+    scores
+}
+
+/// Run a novelty search until `config.ga.termination()` is met, restarting
+/// the population if it stagnates as `config.ga.restart` says.
+///
+/// Novelty only steers selection: the chromosomes in the population, the
+/// hall of fame and the result all carry their fitness on `fitness`.
+pub fn novelty_search<F: Fitness + ?Sized>(config: &NoveltyConfig, fitness: &F) -> GaResult {
+    let started_at = Instant::now();
+    let fitness = &Counted::new(fitness);
+    let termination = config.ga.termination();
+    let mut archive = Archive::new();
+    let mut hall_of_fame = HallOfFame::new(config.ga.hall_of_fame);
+    let mut solutions = SolutionSet::new();
+    let mut population = genetic::initial_population(&config.ga, fitness);
+    let (mut index, mut best_fitness, mut stagnant, mut restarts) = (0, 0f64, 0, 0);
+    loop {
+        solutions.update(&population);
+        let best = population.iter().fold(0f64, |a, c| a.max(c.fitness));
+        if best > best_fitness {
+            best_fitness = best;
+            stagnant = 0;
+        } else {
+            stagnant += 1;
+            if let Some(ref restart) = config.ga.restart {
+                if stagnant >= restart.window {
+                    population = genetic::restarted(&population, restart, &config.ga, fitness);
+                    stagnant = 0;
+                    restarts += 1;
+                }
+            }
+        }
+        let gen = Generation {
+            index,
+            population,
+            restarts,
+            evaluations: fitness.count(),
+            elapsed: started_at.elapsed(),
+            stagnant,
+            cache: None,
+            solutions: solutions.members().to_vec(),
+        };
+        hall_of_fame.update(&gen.population);
+        if let Some(met) = termination.met_by(&gen) {
+            let solution = gen.solutions.first().cloned();
+            let mut result = GaResult::new(gen.index + 1, gen.evaluations, gen.elapsed, solution,
+                                           restarts, hall_of_fame, StopReason::Met(met.clone()));
+            result.solutions = gen.solutions;
+            return result;
+        }
+        population = gen.population;
+
+        // Breed from copies of the population scored for selection by a
+        // blend of novelty (squashed into [0, 1)) and fitness, then give the
+        // survivors their own fitness back.
+        let scores = novelty(&population, &mut archive, config.k, config.threshold);
+        let raw = population.iter()
+                            .map(|c| (c.genome.bits.clone(), c.fitness))
+                            .collect::<HashMap<_, _>>();
+        let scored = population.iter().zip(scores).map(|(c, n)| {
+            let mut c = c.clone();
+            c.fitness = (1f64 - config.weight) * c.fitness + config.weight * n / (1f64 + n);
+            c
+        }).collect::<Vec<_>>();
+        population = genetic::ga_epoch(&scored, index, &config.ga, fitness);
+        for c in &mut population {
+            if let Some(&f) = raw.get(&c.genome.bits) {
+                c.fitness = f;
+            }
+        }
+        index += 1;
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use genetic::{self, Encoding};
+
+    #[test]
+    pub fn test_novelty() {
+        let never = |_: &str| 0f64;
+        let c = |x| Chromosome::new(genetic::to_binary(x), &never);
+        // Three elevens, a ninety-nine and a malformed expression.
+        let pop = [c(0x11), c(0x11), c(0x11), c(0x99), c(0xaa)];
+        let mut archive = Archive::new();
+        let scores = novelty(&pop, &mut archive, 2, 1.0);
+        let far = 99f64.asinh() - 11f64.asinh();
+        assert_eq!(scores[0], 0.0);
+        assert!((scores[3] - far).abs() < 1e-12, "{:?}", scores);
+        assert_eq!(scores[4], 0.0);
+        assert_eq!(archive.behaviours(), &[99f64.asinh()]);
+
+        // Once archived, a behaviour is no longer novel.
+        let scores = novelty(&pop[3..4], &mut archive, 2, 1.0);
+        assert_eq!(scores, vec![0.0]);
+
+        let bits = Encoding::default().encode("9**999").unwrap();
+        assert_eq!(behaviour(&Chromosome::new(bits, &never)), None);
+    }
+
+    #[test]
+    pub fn test_novelty_search() {
+        use genetic::Termination;
+        let fitness = |e: &str| 1f64 / (2f64 + e.len() as f64);
+        let config = NoveltyConfig {
+            ga: GaConfig { popsize: 20, termination: Some(Termination::Generations(3)),
+                           ..Default::default() },
+            ..Default::default()
+        };
+        let result = novelty_search(&config, &fitness);
+        assert_eq!(result.generations, 3);
+        // Novelty does not leak into the reported fitness.
+        for c in &result.hall_of_fame {
+            assert_eq!(c.fitness, fitness(&c.decode()));
+        }
+        assert_eq!(result.best_fitness, fitness(&result.best.unwrap().decode()));
+    }
+}
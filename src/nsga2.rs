@@ -0,0 +1,221 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::f64;
+use rand::{Rng, thread_rng};
+use rayon::prelude::*;
+use expr;
+use genetic::{self, Chromosome, Closeness, GaConfig};
+
+/// The two objectives minimized by NSGA-II: how far an expression's value is
+/// from the target, and how long the expression is.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Objectives {
+    /// |value - target|, or infinity for a malformed expression.
+    pub error: f64,
+    /// The number of tokens in the expression.
+    pub length: usize,
+}
+
+impl Objectives {
+    /// Return the objectives of a chromosome for `target`.
+    pub fn of(c: &Chromosome, target: f64) -> Objectives {
+        let e = c.decode();
+        match (expr::eval(&e), expr::tok(&e)) {
+            (Ok(v), Ok(toks)) if !v.is_nan() =>
+                Objectives { error: (v - target).abs(), length: toks.len() },
+            _ => Objectives { error: f64::INFINITY, length: usize::MAX },
+        }
+    }
+
+    /// Return true if these objectives are no worse than `other` in both
+    /// respects and better in at least one.
+    pub fn dominates(&self, other: &Objectives) -> bool {
+        self.error <= other.error && self.length <= other.length
+            && (self.error < other.error || self.length < other.length)
+    }
+}
+
+/// Parameters for an NSGA-II run.
+#[derive(Debug,Clone)]
+pub struct Nsga2Config {
+    /// Parameters for breeding. The selection strategy is ignored in favour
+    /// of crowded tournaments.
+    pub ga: GaConfig,
+    pub target: f64,
+}
+
+/// Sort indices into `objectives` into successive non-dominated fronts: the
+/// first front holds the indices nothing dominates, the second those only
+/// dominated by the first, and so on.
+pub fn non_dominated_sort(objectives: &[Objectives]) -> Vec<Vec<usize>> {
+    let n = objectives.len();
+    let mut dominated = vec![Vec::new(); n];
+    let mut counts = vec![0; n];
+    for i in 0..n {
+        for j in 0..n {
+            if objectives[i].dominates(&objectives[j]) {
+                dominated[i].push(j);
+            } else if objectives[j].dominates(&objectives[i]) {
+                counts[i] += 1;
+            }
+        }
+    }
+    let mut fronts = Vec::new();
+    let mut front = (0..n).filter(|&i| counts[i] == 0).collect::<Vec<_>>();
+    while !front.is_empty() {
+        let mut next = Vec::new();
+        for &i in &front {
+            for &j in &dominated[i] {
+                counts[j] -= 1;
+                if counts[j] == 0 {
+                    next.push(j);
+                }
+            }
+        }
+        fronts.push(front);
+        front = next;
+    }
+    fronts
+}
+
+/// Return the crowding distance of each member of `front` (indices into
+/// `objectives`): the sum over objectives of the normalized gap between its
+/// neighbours. The extremes of each objective get an infinite distance.
+pub fn crowding_distance(objectives: &[Objectives], front: &[usize]) -> Vec<f64> {
+    let mut distance = vec![0f64; front.len()];
+    let keys: [&dyn Fn(&Objectives) -> f64; 2] = [&|o| o.error, &|o| o.length as f64];
+    for key in &keys {
+        let mut order = (0..front.len()).collect::<Vec<_>>();
+        order.sort_by(|&a, &b| key(&objectives[front[a]]).partial_cmp(&key(&objectives[front[b]]))
+                                                     .unwrap_or(Ordering::Equal));
+        let (first, last) = match (order.first(), order.last()) {
+            (Some(&f), Some(&l)) => (f, l),
+            _ => return distance,
+        };
+        distance[first] = f64::INFINITY;
+        distance[last] = f64::INFINITY;
+        let range = key(&objectives[front[last]]) - key(&objectives[front[first]]);
+        if !range.is_finite() || range <= 0f64 {
+            continue;
+        }
+        for w in order.windows(3) {
+            let gap = key(&objectives[front[w[2]]]) - key(&objectives[front[w[0]]]);
+            if gap.is_finite() {
+                distance[w[1]] += gap / range;
+            }
+        }
+    }
+    distance
+}
+
+/// Return the (rank, crowding distance) of each of `objectives`.
+fn rank_and_crowd(objectives: &[Objectives]) -> (Vec<Vec<usize>>, Vec<(usize, f64)>) {
+    let fronts = non_dominated_sort(objectives);
+    let mut rank = vec![(0, 0f64); objectives.len()];
+    for (r, front) in fronts.iter().enumerate() {
+        for (&i, d) in front.iter().zip(crowding_distance(objectives, front)) {
+            rank[i] = (r, d);
+        }
+    }
+    (fronts, rank)
+}
+
+/// Pick the better of two random members by rank, then crowding distance.
+fn tournament(rank: &[(usize, f64)]) -> usize {
+    let mut rng = thread_rng();
+    let (a, b) = (rng.gen_range(0, rank.len()), rng.gen_range(0, rank.len()));
+    if rank[a].0 < rank[b].0 || (rank[a].0 == rank[b].0 && rank[a].1 > rank[b].1) { a } else { b }
+}
+
+/// Run NSGA-II for `config.ga.max_gens` generations and return the final
+/// Pareto front of distinct expressions, shortest first.
+pub fn nsga2(config: &Nsga2Config) -> Vec<(Chromosome, Objectives)> {
+    let fitness = Closeness::new(config.target);
+    let n = config.ga.popsize;
+    // Tournaments need someone to pick, and an empty population has no front.
+    if n == 0 {
+        return Vec::new();
+    }
+    let mut population = genetic::initial_population(&config.ga, &fitness);
+    for _ in 0..config.ga.max_gens {
+        let objectives = population.iter().map(|c| Objectives::of(c, config.target))
+                                   .collect::<Vec<_>>();
+        let (_, rank) = rank_and_crowd(&objectives);
+        let offspring = (0..n.div_ceil(2)).into_par_iter().flat_map(|_| {
+            let (a, b) = (&population[tournament(&rank)], &population[tournament(&rank)]);
+            let (c1, c2) = a.crossover(b, config.ga.crossover_rate, &config.ga.crossover,
+                                       &fitness);
+            let mutate = |c: Chromosome| c.mutate_with(config.ga.mutation_rate,
+                                                       &config.ga.gene_mutation, &fitness);
+            vec![mutate(c1), mutate(c2)]
+        }).collect::<Vec<_>>();
+
+        population.extend(offspring);
+        let objectives = population.iter().map(|c| Objectives::of(c, config.target))
+                                   .collect::<Vec<_>>();
+        let (fronts, rank) = rank_and_crowd(&objectives);
+        let mut survivors = Vec::with_capacity(n);
+        for mut front in fronts {
+            if survivors.len() + front.len() > n {
+                front.sort_by(|&a, &b| rank[b].1.partial_cmp(&rank[a].1)
+                                                 .unwrap_or(Ordering::Equal));
+                front.truncate(n - survivors.len());
+            }
+            survivors.extend(front);
+            if survivors.len() == n {
+                break;
+            }
+        }
+        population = survivors.into_iter().map(|i| population[i].clone()).collect();
+    }
+
+    let objectives = population.iter().map(|c| Objectives::of(c, config.target))
+                               .collect::<Vec<_>>();
+    let mut seen = HashSet::new();
+    let mut front = non_dominated_sort(&objectives).into_iter().next().unwrap_or_default()
+        .into_iter()
+        .filter(|&i| objectives[i].error.is_finite() && seen.insert(population[i].decode()))
+        .map(|i| (population[i].clone(), objectives[i]))
+        .collect::<Vec<_>>();
+    front.sort_by_key(|&(_, o)| o.length);
+    front
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_non_dominated_sort() {
+        let o = |error, length| Objectives { error, length };
+        let objectives = [o(0.0, 5), o(1.0, 1), o(1.0, 5), o(2.0, 6), o(0.5, 3)];
+        assert_eq!(non_dominated_sort(&objectives), vec![vec![0, 1, 4], vec![2], vec![3]]);
+
+        let distance = crowding_distance(&objectives, &[0, 1, 4]);
+        assert!(distance[0].is_infinite() && distance[1].is_infinite());
+        assert!((distance[2] - (1.0 / 1.0 + 4.0 / 4.0)).abs() < 1e-12);
+    }
+
+    #[test]
+    pub fn test_nsga2() {
+        let front = nsga2(&Nsga2Config {
+            ga: GaConfig { popsize: 100, max_gens: 10, ..Default::default() },
+            target: 42.0,
+        });
+        assert!(!front.is_empty());
+        for w in front.windows(2) {
+            assert!(w[0].1.length <= w[1].1.length);
+            assert!(!w[0].1.dominates(&w[1].1) && !w[1].1.dominates(&w[0].1));
+        }
+    }
+
+    #[test]
+    pub fn test_nsga2_empty() {
+        let config = Nsga2Config {
+            ga: GaConfig { popsize: 0, max_gens: 3, ..Default::default() },
+            target: 42.0,
+        };
+        assert!(nsga2(&config).is_empty());
+    }
+}
@@ -0,0 +1,64 @@
+use expr::{self, Tok};
+use genetic::Fitness;
+
+/// A target given as an expression, e.g., `(3+4)*6`: find other expressions
+/// with the same value.
+///
+/// Expressions score `1 / (1 + |v - value|)` like `Closeness`, where `value`
+/// is the value of the target expression, except that the target expression
+/// itself, or any expression made up of the same tokens, scores 0.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Reformulation {
+    pub target: String,
+    pub value: f64,
+    tokens: Vec<Tok>,
+}
+
+impl Reformulation {
+    /// Return the objective for the target expression `target`, or an error
+    /// if it is malformed.
+    pub fn new(target: &str) -> Result<Reformulation, String> {
+        let value = expr::eval(target)?;
+        if value.is_nan() {
+            return Err(format!("{} is not a number", target));
+        }
+        Ok(Reformulation { target: target.to_string(), value, tokens: expr::tok(target)? })
+    }
+
+    /// Return true if `e` is a mere respelling of the target expression,
+    /// e.g., with different whitespace or leading zeros.
+    pub fn is_target(&self, e: &str) -> bool {
+        expr::tok(e).is_ok_and(|tokens| tokens == self.tokens)
+    }
+}
+
+impl Fitness for Reformulation {
+    fn fitness(&self, e: &str) -> f64 {
+        if self.is_target(e) {
+            return 0f64;
+        }
+        match expr::eval(e) {
+            Ok(v) if !v.is_nan() => 1f64 / (1f64 + (v - self.value).abs()),
+            _ => 0f64,
+        }
+    }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_reformulation() {
+        let f = Reformulation::new("(3+4)*6").unwrap();
+        assert_eq!(f.value, 42f64);
+        assert_eq!(f.fitness("6*7"), 1f64);
+        assert_eq!(f.fitness("40"), 1f64 / 3f64);
+        assert_eq!(f.fitness("(3+4)*6"), 0f64);
+        assert_eq!(f.fitness(" ( 3 + 04 ) * 6"), 0f64);
+        assert_eq!(f.fitness("(4+3)*6"), 1f64);
+        assert_eq!(f.fitness("**"), 0f64);
+        assert!(Reformulation::new("3+").is_err());
+    }
+}
@@ -0,0 +1,112 @@
+use std::io::BufRead;
+use expr;
+use genetic::{Fitness, SymbolTable};
+
+/// Return the symbol table for symbolic regression: the default table with
+/// the variable `x` in place of the empty symbol.
+pub fn symbol_table() -> SymbolTable {
+    SymbolTable::default().without(&[""]).with(&["x"])
+}
+
+/// Symbolic regression: fit an expression in `x` to a dataset of `(x, y)`
+/// points.
+///
+/// Expressions score `1 / (1 + mse)`, where `mse` is their mean squared error
+/// over the dataset, so that only an exact fit is a solution. Expressions
+/// that are malformed, or evaluate to NaN at any point, score 0. Each point
+/// is also a test case for lexicase selection.
+#[derive(Debug,Clone)]
+pub struct Regression {
+    pub data: Vec<(f64, f64)>,
+}
+
+impl Regression {
+    pub fn new(data: Vec<(f64, f64)>) -> Regression { Regression { data } }
+
+    /// Return the squared error of `e` at each point, or None if `e` is
+    /// malformed, uses variables other than `x` or evaluates to NaN
+    /// anywhere. `e` is compiled once for all the points.
+    fn squared_errors(&self, e: &str) -> Option<Vec<f64>> {
+        let program = expr::compile(e).ok()?;
+        if program.variables().iter().any(|v| v != "x") {
+            return None;
+        }
+        let n = program.variables().len();
+        self.data.iter().map(|&(x, y)| {
+            match program.exec(&[x][..n]) {
+                Ok(v) if !v.is_nan() => Some((v - y) * (v - y)),
+                _ => None,
+            }
+        }).collect()
+    }
+
+    /// Return the mean squared error of `e` over the dataset, or None if `e`
+    /// is malformed or evaluates to NaN anywhere.
+    pub fn mse(&self, e: &str) -> Option<f64> {
+        let errors = self.squared_errors(e)?;
+        Some(errors.iter().sum::<f64>() / errors.len().max(1) as f64)
+    }
+}
+
+impl Fitness for Regression {
+    fn fitness(&self, e: &str) -> f64 {
+        self.mse(e).map_or(0f64, |mse| 1f64 / (1f64 + mse))
+    }
+
+    fn cases(&self, e: &str) -> Vec<f64> {
+        match self.squared_errors(e) {
+            Some(errors) => errors.iter().map(|err| 1f64 / (1f64 + err)).collect(),
+            None => vec![0f64; self.data.len()],
+        }
+    }
+}
+
+/// Read a dataset with one `x,y` point per line. Blank lines and lines
+/// starting with `#` are skipped. A dataset without any points is an error,
+/// since every expression would fit it.
+pub fn read_data<R: BufRead>(input: R) -> Result<Vec<(f64, f64)>, String> {
+    let mut data = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        let line = line.map_err(|e| e.to_string())?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields = line.split(',').map(|f| f.trim().parse::<f64>()).collect::<Vec<_>>();
+        match fields[..] {
+            [Ok(x), Ok(y)] => data.push((x, y)),
+            _ => return Err(format!("Line {}: expected x,y but got \"{}\"", i + 1, line)),
+        }
+    }
+    if data.is_empty() {
+        return Err("No data points".to_string());
+    }
+    Ok(data)
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_regression() {
+        let f = Regression::new(vec![(0.0, 1.0), (1.0, 2.0), (2.0, 5.0)]);
+        assert_eq!(f.fitness("x*x+1"), 1f64);
+        assert_eq!(f.mse("x+1"), Some(4f64 / 3f64));
+        assert_eq!(f.fitness("x+1"), 1f64 / (1f64 + 4f64 / 3f64));
+        assert_eq!(f.cases("x+1"), vec![1f64, 1f64, 0.2]);
+        assert_eq!(f.fitness("x/x"), 0f64);
+        assert_eq!(f.fitness("x+"), 0f64);
+        assert_eq!(symbol_table().encode("x*x+1"), Ok(vec![15, 12, 15, 10, 1]));
+    }
+
+    #[test]
+    pub fn test_read_data() {
+        let data = read_data("# x,y\n0,1\n\n1.5, 2\n".as_bytes());
+        assert_eq!(data, Ok(vec![(0.0, 1.0), (1.5, 2.0)]));
+        assert!(read_data("1,2,3\n".as_bytes()).is_err());
+        assert!(read_data("1,a\n".as_bytes()).is_err());
+        assert_eq!(read_data("# x,y\n\n".as_bytes()), Err("No data points".to_string()));
+    }
+}
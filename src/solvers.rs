@@ -0,0 +1,425 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use countdown::{self, Countdown};
+use digits::{DigitLimits, Limited};
+use genetic::{self, BigCloseness, Closeness, Counted, Encoding, Fitness, GaConfig, GaResult,
+              SymbolTable, Termination, Tolerance};
+use multi::MultiCloseness;
+use num::BigInt;
+use reformulation::Reformulation;
+use regression::{self, Regression};
+
+pub mod annealing;
+pub mod exhaustive;
+pub mod random_search;
+
+use self::annealing::AnnealingConfig;
+use self::exhaustive::EnumerationConfig;
+
+/// What a solver searches for.
+#[derive(Debug,Clone,PartialEq)]
+pub enum Target {
+    /// An expression evaluating to this number.
+    Value(f64),
+    /// An expression evaluating to `target`, give or take `tolerance`.
+    Approx { target: f64, tolerance: Tolerance },
+    /// An expression evaluating to exactly this number in rational
+    /// arithmetic.
+    Exact(f64),
+    /// An expression evaluating to exactly this number in checked integer
+    /// arithmetic.
+    Integer(i64),
+    /// An expression evaluating to exactly this whole number in arbitrary
+    /// precision.
+    Big(BigInt),
+    /// A Countdown game: an expression evaluating to `target` that uses only
+    /// `numbers`, each at most as many times as it is given.
+    Countdown { numbers: Vec<u32>, target: f64 },
+    /// Symbolic regression: an expression in `x` fitting these `(x, y)`
+    /// points.
+    Regression(Vec<(f64, f64)>),
+    /// Several values to hit at once (see `MultiCloseness`).
+    Multi(MultiCloseness),
+    /// Another expression with the value of a given one (see
+    /// `Reformulation`).
+    Reformulation(Reformulation),
+    /// Another target, hit only with the digits allowed by `limits`.
+    Digits { target: Box<Target>, limits: DigitLimits },
+}
+
+impl Target {
+    /// Return the fitness function for this target.
+    pub fn fitness(&self) -> Box<dyn Fitness> {
+        match *self {
+            Target::Value(v) => Box::new(Closeness::new(v)),
+            Target::Approx { target, tolerance } => Box::new(Closeness::within(target, tolerance)),
+            Target::Exact(v) => Box::new(Closeness::exact(v)),
+            Target::Integer(n) => Box::new(Closeness::integer(n as f64)),
+            Target::Big(ref v) => Box::new(BigCloseness::new(v.clone())),
+            Target::Countdown { ref numbers, target } => Box::new(Countdown::new(numbers, target)),
+            Target::Regression(ref data) => Box::new(Regression::new(data.clone())),
+            Target::Multi(ref objective) => Box::new(objective.clone()),
+            Target::Reformulation(ref objective) => Box::new(objective.clone()),
+            Target::Digits { ref target, ref limits } => {
+                Box::new(Limited { fitness: target.fitness(), limits: limits.clone() })
+            }
+        }
+    }
+
+    /// Return the symbol table that expressions for this target must be
+    /// built from, if it needs one other than the solver's own.
+    pub fn symbols(&self) -> Option<SymbolTable> {
+        match *self {
+            Target::Value(_) | Target::Approx { .. } | Target::Exact(_) | Target::Integer(_) |
+            Target::Big(_) => None,
+            Target::Countdown { ref numbers, .. } => Some(countdown::symbol_table(numbers)),
+            Target::Regression(_) => Some(regression::symbol_table()),
+            Target::Multi(ref objective) if objective.uses_variable() => {
+                Some(regression::symbol_table())
+            }
+            Target::Multi(_) | Target::Reformulation(_) => None,
+            Target::Digits { ref target, ref limits } => {
+                Some(limits.symbols(&target.symbols().unwrap_or_default()))
+            }
+        }
+    }
+
+    /// Return the symbol table to build expressions for this target from,
+    /// given the solver's own `symbols`: the table of this target, if any,
+    /// keeping only the operators that `symbols` has.
+    fn symbols_for(&self, symbols: &SymbolTable) -> SymbolTable {
+        if let Target::Digits { ref target, ref limits } = *self {
+            return limits.symbols(&target.symbols_for(symbols));
+        }
+        match self.symbols() {
+            Some(table) => table.with_operators(&symbols.operators()),
+            None => symbols.clone(),
+        }
+    }
+
+    /// Return `encoding` with the symbol table of this target, if any.
+    fn encoding(&self, encoding: &Encoding) -> Encoding {
+        Encoding { symbols: self.symbols_for(&encoding.symbols), ..encoding.clone() }
+    }
+}
+
+/// Limits on how much work a solver may do.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Budget {
+    /// Maximum number of fitness evaluations.
+    pub evaluations: usize,
+    /// If set, maximum wall-clock time.
+    pub time: Option<Duration>,
+}
+
+impl Default for Budget {
+    fn default() -> Budget {
+        let config = GaConfig::default();
+        Budget { evaluations: config.popsize * config.max_gens, time: None }
+    }
+}
+
+impl Budget {
+    /// Return the termination criteria of a GA run that stops on a solution
+    /// or once this budget is spent.
+    pub fn termination(&self) -> Termination { self.until(Termination::Solved) }
+
+    /// Return the termination criteria of a GA run that stops once `solved`
+    /// is met or this budget is spent.
+    pub fn until(&self, solved: Termination) -> Termination {
+        let mut criteria = vec![solved, Termination::Evaluations(self.evaluations)];
+        criteria.extend(self.time.map(Termination::Time));
+        Termination::Any(criteria)
+    }
+}
+
+/// The outcome of `Solver::solve()`, common to all solvers.
+#[derive(Debug,Clone)]
+pub struct SolveResult {
+    /// The expression found to hit the target, if any.
+    pub solution: Option<String>,
+    /// All the distinct expressions found to hit it, starting with
+    /// `solution`.
+    pub solutions: Vec<String>,
+    /// The best expression seen, if any.
+    pub best: Option<String>,
+    pub best_fitness: f64,
+    /// Number of generations or, for solvers without any, iterations.
+    pub generations: usize,
+    /// Number of fitness evaluations.
+    pub evaluations: usize,
+    pub elapsed: Duration,
+}
+
+impl From<GaResult> for SolveResult {
+    fn from(result: GaResult) -> SolveResult {
+        SolveResult {
+            solution: result.solution.map(|c| c.decode()),
+            solutions: result.solutions.iter().map(|c| c.decode()).collect(),
+            best: result.best.map(|c| c.decode()),
+            best_fitness: result.best_fitness,
+            generations: result.generations,
+            evaluations: result.evaluations,
+            elapsed: result.elapsed,
+        }
+    }
+}
+
+/// A search algorithm for expressions hitting a target.
+pub trait Solver {
+    /// Search for `target` within `budget`.
+    fn solve(&mut self, target: Target, budget: Budget) -> SolveResult;
+}
+
+/// The GA, configured by `config`; its termination criteria are replaced by
+/// the budget.
+#[derive(Debug,Clone,Default)]
+pub struct GaSolver {
+    pub config: GaConfig,
+    /// If true, progress is printed every 10 generations, as `ga()` does.
+    pub verbose: bool,
+}
+
+impl Solver for GaSolver {
+    fn solve(&mut self, target: Target, budget: Budget) -> SolveResult {
+        let config = GaConfig {
+            termination: Some(budget.until(self.config.solved())),
+            encoding: target.encoding(&self.config.encoding),
+            ..self.config.clone()
+        };
+        let verbose = self.verbose;
+        genetic::ga_with_observer(&config, &*target.fitness(), |i, _, best| {
+            if verbose && i % 10 == 9 {
+                println!("Generation {}: best fitness {}", i + 1, best.fitness);
+            }
+            true
+        }).into()
+    }
+}
+
+/// Simulated annealing; the budget caps its iterations and time.
+#[derive(Debug,Clone,Default)]
+pub struct AnnealingSolver {
+    pub config: AnnealingConfig,
+}
+
+impl Solver for AnnealingSolver {
+    fn solve(&mut self, target: Target, budget: Budget) -> SolveResult {
+        let config = AnnealingConfig {
+            iterations: budget.evaluations.saturating_sub(1),
+            time_limit: budget.time,
+            encoding: target.encoding(&self.config.encoding),
+            ..self.config.clone()
+        };
+        annealing::anneal(&config, &*target.fitness()).into()
+    }
+}
+
+/// Random search with the population size and initialization of `config`.
+#[derive(Debug,Clone,Default)]
+pub struct RandomSearchSolver {
+    pub config: GaConfig,
+}
+
+impl Solver for RandomSearchSolver {
+    fn solve(&mut self, target: Target, budget: Budget) -> SolveResult {
+        let config = GaConfig {
+            termination: Some(budget.until(self.config.solved())),
+            encoding: target.encoding(&self.config.encoding),
+            ..self.config.clone()
+        };
+        random_search::random_search(&config, &*target.fitness()).into()
+    }
+}
+
+/// Exhaustive enumeration, limited by `config.max_symbols` and the budget's
+/// evaluations.
+#[derive(Debug,Clone,Default)]
+pub struct ExhaustiveSolver {
+    pub config: EnumerationConfig,
+}
+
+impl Solver for ExhaustiveSolver {
+    fn solve(&mut self, target: Target, budget: Budget) -> SolveResult {
+        let started_at = Instant::now();
+        let config = EnumerationConfig {
+            symbols: target.symbols_for(&self.config.symbols),
+            max_evaluations: Some(budget.evaluations),
+            ..self.config.clone()
+        };
+        let fitness = target.fitness();
+        let fitness = Counted::new(&*fitness);
+        let solution = exhaustive::enumerate(&config, &fitness);
+        SolveResult {
+            best_fitness: solution.as_ref().map_or(0f64, |c| c.fitness),
+            solution: solution.as_ref().map(|c| c.decode()),
+            solutions: solution.iter().map(|c| c.decode()).collect(),
+            best: solution.map(|c| c.decode()),
+            generations: 0,
+            evaluations: fitness.count(),
+            elapsed: started_at.elapsed(),
+        }
+    }
+}
+
+/// The available solvers, for picking one by name.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Algorithm {
+    Ga,
+    Annealing,
+    RandomSearch,
+    Exhaustive,
+}
+
+impl Algorithm {
+    /// All the algorithms.
+    pub const ALL: [Algorithm; 4] =
+        [Algorithm::Ga, Algorithm::Annealing, Algorithm::RandomSearch, Algorithm::Exhaustive];
+
+    /// Return a solver running this algorithm with default parameters.
+    pub fn solver(&self) -> Box<dyn Solver> {
+        match *self {
+            Algorithm::Ga => Box::new(GaSolver::default()),
+            Algorithm::Annealing => Box::new(AnnealingSolver::default()),
+            Algorithm::RandomSearch => Box::new(RandomSearchSolver::default()),
+            Algorithm::Exhaustive => Box::new(ExhaustiveSolver::default()),
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Algorithm::Ga => "ga",
+            Algorithm::Annealing => "annealing",
+            Algorithm::RandomSearch => "random",
+            Algorithm::Exhaustive => "exhaustive",
+        })
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Algorithm, String> {
+        Algorithm::ALL.iter()
+                      .find(|a| a.to_string() == s)
+                      .cloned()
+                      .ok_or_else(|| format!("Unknown solver: {}", s))
+    }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+    use expr::{self, exact};
+    use multi::{Aggregate, Constraint};
+
+    #[test]
+    pub fn test_solvers() {
+        assert_eq!("random".parse::<Algorithm>(), Ok(Algorithm::RandomSearch));
+        assert!("magic".parse::<Algorithm>().is_err());
+
+        let budget = Budget { evaluations: 2000, time: None };
+        for algorithm in Algorithm::ALL.iter() {
+            let result = algorithm.solver().solve(Target::Value(42.0), budget.clone());
+            assert!(result.evaluations <= 2000 + GaConfig::default().popsize, "{}", algorithm);
+            // Fractional powers can land within EPSILON of 42 rather than on it.
+            if let Some(ref e) = result.solution {
+                assert!(genetic::is_solution(Closeness::new(42.0).fitness(e)), "{}", e);
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_approx_target() {
+        let tolerance = Tolerance { absolute: 1e-2, relative: 0f64 };
+        let target = Target::Approx { target: PI, tolerance };
+        let result = ExhaustiveSolver::default().solve(target.clone(), Budget::default());
+        let solution = result.solution.expect("22/7 is within reach");
+        assert!((expr::eval(&solution).unwrap() - PI).abs() <= 1e-2);
+    }
+
+    #[test]
+    pub fn test_exact_target() {
+        let target = Target::Exact(2.0);
+        assert_eq!(target.fitness().fitness("(2/3)*3"), 1.0);
+        let result = ExhaustiveSolver::default().solve(target.clone(), Budget::default());
+        assert_eq!(expr::eval_exact(&result.solution.unwrap()).map(|v| exact::to_f64(&v)),
+                   Ok(2.0));
+    }
+
+    #[test]
+    pub fn test_integer_target() {
+        let target = Target::Integer(12);
+        assert_eq!(target.fitness().fitness("(7/2)*4"), 0.0);
+        assert_eq!(target.fitness().fitness("(8/2)*3"), 1.0);
+        let result = ExhaustiveSolver::default().solve(target.clone(), Budget::default());
+        assert_eq!(expr::eval_i64(&result.solution.unwrap()), Ok(12));
+    }
+
+    #[test]
+    pub fn test_big_target() {
+        let target = Target::Big(expr::eval_big("9**30").unwrap());
+        let result = ExhaustiveSolver::default().solve(target.clone(), Budget::default());
+        assert_eq!(expr::eval_big(&result.solution.unwrap()), expr::eval_big("9**30"));
+    }
+
+    #[test]
+    pub fn test_countdown_target() {
+        let target = Target::Countdown { numbers: vec![25, 50, 3], target: 78.0 };
+        assert_eq!(target.symbols(), Some(countdown::symbol_table(&[25, 50, 3])));
+        let result = ExhaustiveSolver::default().solve(target.clone(), Budget::default());
+        let solution = result.solution.expect("25 + 50 + 3 is within reach");
+        assert_eq!(target.fitness().fitness(&solution), 1.0);
+
+        let symbols = SymbolTable::default().with_operators(&["+", "-"]);
+        assert_eq!(target.symbols_for(&symbols).operators(), vec!["+", "-"]);
+    }
+
+    #[test]
+    pub fn test_regression_target() {
+        let target = Target::Regression(vec![(0.0, 1.0), (1.0, 2.0), (2.0, 5.0), (3.0, 10.0)]);
+        let result = ExhaustiveSolver::default().solve(target.clone(), Budget::default());
+        let solution = result.solution.expect("x*x+1 is within reach");
+        assert_eq!(target.fitness().fitness(&solution), 1.0);
+    }
+
+    #[test]
+    pub fn test_multi_target() {
+        let constraints = vec![Constraint::new(Some(1.0), 5.0), Constraint::new(Some(2.0), 8.0)];
+        let target = Target::Multi(MultiCloseness::new(constraints, Aggregate::Worst));
+        assert_eq!(target.symbols(), Some(regression::symbol_table()));
+        let result = ExhaustiveSolver::default().solve(target.clone(), Budget::default());
+        let solution = result.solution.expect("3*x+2 is within reach");
+        assert_eq!(target.fitness().fitness(&solution), 1.0);
+
+        let target = Target::Multi(MultiCloseness::new(vec![Constraint::new(None, 1.0)],
+                                                       Aggregate::Mean));
+        assert_eq!(target.symbols(), None);
+    }
+
+    #[test]
+    pub fn test_reformulation_target() {
+        let target = Target::Reformulation(Reformulation::new("6*7").unwrap());
+        let result = ExhaustiveSolver::default().solve(target.clone(), Budget::default());
+        let solution = result.solution.expect("42 is within reach");
+        assert_ne!(solution, "6*7");
+        assert_eq!(Closeness::new(42.0).fitness(&solution), 1.0);
+    }
+
+    #[test]
+    pub fn test_digits_target() {
+        let limits = DigitLimits { allowed: vec!['2', '3'], max_uses: Some(1) };
+        let target = Target::Digits { target: Box::new(Target::Value(6.0)), limits };
+        let symbols = SymbolTable::default().with_operators(&["*"]);
+        assert_eq!(target.symbols_for(&symbols).symbols(), &["2", "3", "*", ""]);
+        assert_eq!(target.fitness().fitness("2*3"), 1.0);
+        assert_eq!(target.fitness().fitness("3+3"), 0.5);
+        let result = ExhaustiveSolver::default().solve(target.clone(), Budget::default());
+        assert_eq!(result.solution, Some("2*3".to_string()));
+    }
+}
@@ -0,0 +1,100 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use rand::{Rng, thread_rng};
+use genetic::{Chromosome, Counted, Encoding, Fitness, GaResult, HallOfFame, LengthDistribution,
+              Neighbourhood, StopReason, Termination};
+
+const ITERATIONS: usize = 100_000;
+const TEMPERATURE: f64 = 0.1;
+const COOLING: f64 = 0.9999;
+const HALL_OF_FAME: usize = 5;
+
+/// Parameters for simulated annealing.
+#[derive(Debug,Clone)]
+pub struct AnnealingConfig {
+    /// Number of moves after which the search gives up.
+    pub iterations: usize,
+    /// The starting temperature. A move that lowers fitness by `d` is taken
+    /// with probability `exp(-d/T)` at temperature `T`.
+    pub temperature: f64,
+    /// The temperature is multiplied by this after every move.
+    pub cooling: f64,
+    /// How neighbouring chromosomes are found.
+    pub neighbourhood: Neighbourhood,
+    pub encoding: Encoding,
+    /// How the length of the random starting chromosome is picked.
+    pub lengths: LengthDistribution,
+    /// Number of the fittest distinct chromosomes kept and returned.
+    pub hall_of_fame: usize,
+    /// If set, the search also gives up once this much time has passed.
+    pub time_limit: Option<Duration>,
+}
+
+impl Default for AnnealingConfig {
+    fn default() -> AnnealingConfig {
+        AnnealingConfig {
+            iterations: ITERATIONS,
+            temperature: TEMPERATURE,
+            cooling: COOLING,
+            neighbourhood: Neighbourhood::Gene,
+            encoding: Encoding::default(),
+            lengths: LengthDistribution::default(),
+            hall_of_fame: HALL_OF_FAME,
+            time_limit: None,
+        }
+    }
+}
+
+/// Search for a chromosome scoring 1 on `fitness` by simulated annealing,
+/// starting from a random well-formed expression. In the result, each move
+/// counts as a generation.
+pub fn anneal<F: Fitness + ?Sized>(config: &AnnealingConfig, fitness: &F) -> GaResult {
+    let started_at = Instant::now();
+    let fitness = &Counted::new(fitness);
+    let mut rng = thread_rng();
+    let mut hall_of_fame = HallOfFame::new(config.hall_of_fame);
+    let genes = config.lengths.length(0, 1);
+    let mut current = Chromosome::well_formed(Arc::new(config.encoding.clone()), genes, fitness);
+    let mut temperature = config.temperature;
+    let mut iteration = 0;
+    let (solution, reason) = loop {
+        hall_of_fame.update(&[current.clone()]);
+        if current.is_solution() {
+            break (Some(current), Termination::Solved);
+        }
+        if iteration >= config.iterations {
+            break (None, Termination::Generations(config.iterations));
+        }
+        if let Some(limit) = config.time_limit.filter(|&l| started_at.elapsed() >= l) {
+            break (None, Termination::Time(limit));
+        }
+        let candidate = config.neighbourhood.neighbour(&current, fitness);
+        let delta = candidate.fitness - current.fitness;
+        if delta >= 0f64 || rng.gen_range(0f64, 1f64) < (delta / temperature).exp() {
+            current = candidate;
+        }
+        temperature *= config.cooling;
+        iteration += 1;
+    };
+    GaResult::new(iteration, fitness.count(), started_at.elapsed(), solution, 0, hall_of_fame,
+                  StopReason::Met(reason))
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use genetic::Closeness;
+
+    #[test]
+    pub fn test_anneal() {
+        let config = AnnealingConfig { iterations: 2000, ..Default::default() };
+        let result = anneal(&config, &Closeness::new(42.0));
+        assert_eq!(result.evaluations, result.generations + 1);
+        match result.solution {
+            Some(ref c) => assert!(c.is_solution()),
+            None => assert_eq!(result.generations, 2000),
+        }
+        assert!(result.best.is_some());
+    }
+}
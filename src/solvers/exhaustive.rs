@@ -0,0 +1,123 @@
+use std::sync::Arc;
+use rayon::prelude::*;
+use genetic::{self, Chromosome, Counted, Encoding, Fitness, SymbolTable};
+
+const MAX_SYMBOLS: usize = 5;
+
+/// Parameters for exhaustive enumeration.
+#[derive(Debug,Clone)]
+pub struct EnumerationConfig {
+    /// The longest expressions tried, in symbols.
+    pub max_symbols: usize,
+    /// The symbols expressions are made of.
+    pub symbols: SymbolTable,
+    /// If set, give up after scoring this many expressions.
+    pub max_evaluations: Option<usize>,
+}
+
+impl Default for EnumerationConfig {
+    fn default() -> EnumerationConfig {
+        EnumerationConfig {
+            max_symbols: MAX_SYMBOLS,
+            symbols: SymbolTable::default(),
+            max_evaluations: None,
+        }
+    }
+}
+
+/// Try every expression of up to `config.max_symbols` symbols, shortest
+/// first, and return one scoring 1 on `fitness`, if there is any.
+///
+/// Expressions starting or ending with an operator, or with two operators
+/// in a row, are skipped, as are empty symbols. Even so, the number of
+/// expressions tried grows exponentially with `max_symbols`, so this is
+/// only practical for short expressions; it does settle whether a solution
+/// that short exists at all, unless `config.max_evaluations` cuts it short.
+pub fn enumerate<F: Fitness + ?Sized>(config: &EnumerationConfig, fitness: &F)
+        -> Option<Chromosome> {
+    let symbols = &config.symbols;
+    let counted = &Counted::new(fitness);
+    let max = config.max_evaluations.unwrap_or(usize::MAX);
+    let mut alphabet: Vec<usize> = Vec::new();
+    for g in 0..symbols.len() {
+        let s = symbols.symbol(g);
+        if !s.is_empty() && alphabet.iter().all(|&a| symbols.symbol(a) != s) {
+            alphabet.push(g);
+        }
+    }
+    let encoding = Arc::new(Encoding { symbols: symbols.clone(), ..Default::default() });
+    for n in 1..config.max_symbols + 1 {
+        let found = alphabet.par_iter()
+                            .filter(|&&g| !symbols.is_operator(g))
+                            .find_map_first(|&g| search(&mut vec![g], n, symbols, &alphabet,
+                                                        counted, max));
+        if let Some(genes) = found {
+            let bits = encoding.from_genes(&genes);
+            return Some(Chromosome::with_encoding(bits, encoding, fitness));
+        }
+        if counted.count() >= max {
+            break;
+        }
+    }
+    None
+}
+
+/// Extend `prefix` to every allowed sequence of `n` genes drawn from
+/// `alphabet`, depth first, and return the first solution, giving up once
+/// `fitness` has been called `max` times.
+fn search<F: Fitness + ?Sized>(prefix: &mut Vec<usize>, n: usize, symbols: &SymbolTable,
+                               alphabet: &[usize], fitness: &Counted<F>, max: usize)
+        -> Option<Vec<usize>> {
+    if fitness.count() >= max {
+        return None;
+    }
+    let last_is_operator = prefix.last().is_some_and(|&g| symbols.is_operator(g));
+    if prefix.len() == n {
+        if last_is_operator {
+            return None;
+        }
+        let e = symbols.concat(prefix);
+        return if genetic::is_solution(fitness.fitness(&e)) { Some(prefix.clone()) } else { None };
+    }
+    for &g in alphabet {
+        if last_is_operator && symbols.is_operator(g) {
+            continue;
+        }
+        prefix.push(g);
+        let found = search(prefix, n, symbols, alphabet, fitness, max);
+        prefix.pop();
+        if found.is_some() {
+            return found;
+        }
+    }
+    None
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use expr;
+    use genetic::Closeness;
+
+    #[test]
+    pub fn test_enumerate() {
+        let config = EnumerationConfig { max_symbols: 3, ..Default::default() };
+        let found = enumerate(&config, &Closeness::new(42.0)).unwrap();
+        assert_eq!(found.decode(), "42");
+
+        let symbols = SymbolTable::new(vec!["6", "7", "*"]);
+        let config = EnumerationConfig { max_symbols: 3, symbols, ..Default::default() };
+        let found = enumerate(&config, &Closeness::new(42.0)).unwrap();
+        assert_eq!(expr::eval(&found.decode()), Ok(42.0));
+        assert_eq!(found.genes(), 3);
+
+        // No two symbols of 6, 7 and * make 43.
+        let config = EnumerationConfig { max_symbols: 2, ..config };
+        assert!(enumerate(&config, &Closeness::new(43.0)).is_none());
+
+        // Ten evaluations only get through the single digits.
+        let config = EnumerationConfig { max_evaluations: Some(10), ..Default::default() };
+        assert!(enumerate(&config, &Closeness::new(42.0)).is_none());
+    }
+}
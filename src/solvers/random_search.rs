@@ -0,0 +1,71 @@
+use std::time::Instant;
+use genetic::{self, Counted, Fitness, GaConfig, GaResult, Generation, HallOfFame, SolutionSet,
+              StopReason};
+
+/// Sample random chromosomes until one scores 1 on `fitness` or
+/// `config.termination()` is met, as a baseline for the GA.
+///
+/// Each generation is `config.popsize` fresh chromosomes generated as
+/// configured by `config.initialization` and `config.lengths`, so that, for
+/// the same configuration, the evaluation budget is the same as `ga`'s.
+/// Breeding parameters are ignored.
+pub fn random_search<F: Fitness + ?Sized>(config: &GaConfig, fitness: &F) -> GaResult {
+    let started_at = Instant::now();
+    let counted = &Counted::new(fitness);
+    let fitness = &config.graded(counted);
+    let termination = config.termination();
+    let mut hall_of_fame = HallOfFame::new(config.hall_of_fame);
+    let mut solutions = SolutionSet::new();
+    let (mut best_fitness, mut stagnant) = (0f64, 0);
+    for index in 0.. {
+        let population = genetic::random_chromosomes(config, config.popsize, fitness);
+        hall_of_fame.update(&population);
+        solutions.update(&population);
+        let best = population.iter().fold(0f64, |a, c| a.max(c.fitness));
+        if best > best_fitness {
+            best_fitness = best;
+            stagnant = 0;
+        } else {
+            stagnant += 1;
+        }
+        let gen = Generation {
+            index,
+            population,
+            restarts: 0,
+            evaluations: counted.count(),
+            elapsed: started_at.elapsed(),
+            stagnant,
+            cache: None,
+            solutions: solutions.members().to_vec(),
+        };
+        if let Some(met) = termination.met_by(&gen) {
+            let solution = gen.solutions.first().cloned();
+            let mut result = GaResult::new(index + 1, gen.evaluations, gen.elapsed, solution,
+                                           0, hall_of_fame, StopReason::Met(met.clone()));
+            result.solutions = gen.solutions;
+            return result;
+        }
+    }
+    unreachable!()
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use genetic::Termination;
+
+    #[test]
+    pub fn test_random_search() {
+        let config = GaConfig { popsize: 10, termination: Some(Termination::Evaluations(35)),
+                                ..Default::default() };
+        let result = random_search(&config, &|_: &str| 0.5);
+        assert_eq!((result.generations, result.evaluations), (4, 40));
+        assert!(result.solution.is_none());
+
+        let config = GaConfig { popsize: 10, ..Default::default() };
+        let result = random_search(&config, &|_: &str| 1.0);
+        assert_eq!(result.generations, 1);
+        assert!(result.solution.is_some());
+    }
+}
@@ -0,0 +1,156 @@
+use std::cmp::Ordering;
+use rand::{seq, thread_rng};
+use genetic::{self, Fitness, GaConfig};
+
+/// The values tried for each tuned parameter.
+#[derive(Debug,Clone)]
+pub struct Grid {
+    pub popsizes: Vec<usize>,
+    pub mutation_rates: Vec<f64>,
+    pub crossover_rates: Vec<f64>,
+}
+
+impl Default for Grid {
+    fn default() -> Grid {
+        Grid {
+            popsizes: vec![50, 100, 250, 500],
+            mutation_rates: vec![0.001, 0.005, 0.01, 0.05],
+            crossover_rates: vec![0.5, 0.7, 0.9],
+        }
+    }
+}
+
+impl Grid {
+    /// Return every combination of parameters, as (popsize, mutation rate,
+    /// crossover rate).
+    pub fn points(&self) -> Vec<(usize, f64, f64)> {
+        let mut points = Vec::new();
+        for &popsize in &self.popsizes {
+            for &mutation_rate in &self.mutation_rates {
+                for &crossover_rate in &self.crossover_rates {
+                    points.push((popsize, mutation_rate, crossover_rate));
+                }
+            }
+        }
+        points
+    }
+}
+
+/// Which points of the grid are tried.
+#[derive(Debug,Clone)]
+pub enum Sampling {
+    /// All of them.
+    Exhaustive,
+    /// This many, picked at random.
+    Random(usize),
+}
+
+/// Parameters for `tune()`.
+#[derive(Debug,Clone)]
+pub struct TuneConfig {
+    pub grid: Grid,
+    pub sampling: Sampling,
+    /// Number of GA runs per point.
+    pub runs: usize,
+    /// The configuration the tuned parameters are set on. Keep `max_gens`
+    /// low, as runs that fail go on that long.
+    pub base: GaConfig,
+}
+
+impl Default for TuneConfig {
+    fn default() -> TuneConfig {
+        TuneConfig {
+            grid: Grid::default(),
+            sampling: Sampling::Exhaustive,
+            runs: 5,
+            base: GaConfig { max_gens: 100, ..Default::default() },
+        }
+    }
+}
+
+/// How the GA fared with one point of the grid.
+#[derive(Debug,Clone)]
+pub struct Trial {
+    pub popsize: usize,
+    pub mutation_rate: f64,
+    pub crossover_rate: f64,
+    /// Median number of generations to a solution, where failed runs count
+    /// as one more than `max_gens`.
+    pub median_generations: f64,
+    /// The fraction of runs that found a solution.
+    pub success_rate: f64,
+}
+
+/// Run the GA `config.runs` times for each sampled point of `config.grid` on
+/// `fitness`, and return the trials, best (lowest median generations, then
+/// highest success rate) first.
+pub fn tune<F: Fitness + ?Sized>(config: &TuneConfig, fitness: &F) -> Vec<Trial> {
+    let mut points = config.grid.points();
+    if let Sampling::Random(n) = config.sampling {
+        points = seq::sample_iter(&mut thread_rng(), points, n).unwrap_or_else(|all| all);
+    }
+    let mut trials = points.into_iter().map(|(popsize, mutation_rate, crossover_rate)| {
+        let ga = GaConfig { popsize, mutation_rate, crossover_rate, ..config.base.clone() };
+        let mut generations = Vec::with_capacity(config.runs);
+        let mut successes = 0;
+        for _ in 0..config.runs {
+            let result = genetic::ga_with_observer(&ga, fitness, |_, _, _| true);
+            if result.solution.is_some() {
+                successes += 1;
+                generations.push(result.generations as f64);
+            } else {
+                generations.push((ga.max_gens + 1) as f64);
+            }
+        }
+        Trial {
+            popsize,
+            mutation_rate,
+            crossover_rate,
+            median_generations: median(&mut generations),
+            success_rate: successes as f64 / config.runs.max(1) as f64,
+        }
+    }).collect::<Vec<_>>();
+    trials.sort_by(|a, b| {
+        a.median_generations.partial_cmp(&b.median_generations)
+                            .unwrap_or(Ordering::Equal)
+                            .then(b.success_rate.partial_cmp(&a.success_rate)
+                                                .unwrap_or(Ordering::Equal))
+    });
+    trials
+}
+
+/// Return the median of `xs`, or 0 if there are none.
+pub fn median(xs: &mut [f64]) -> f64 {
+    if xs.is_empty() {
+        return 0f64;
+    }
+    xs.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    let n = xs.len();
+    if n % 2 == 1 { xs[n / 2] } else { (xs[n / 2 - 1] + xs[n / 2]) / 2f64 }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use genetic::Closeness;
+
+    #[test]
+    pub fn test_tune() {
+        assert_eq!(median(&mut [3.0, 1.0, 2.0]), 2.0);
+        assert_eq!(median(&mut [4.0, 1.0, 2.0, 3.0]), 2.5);
+
+        let config = TuneConfig {
+            grid: Grid { popsizes: vec![10, 20], mutation_rates: vec![0.01],
+                         crossover_rates: vec![0.5, 0.7, 0.9] },
+            sampling: Sampling::Random(4),
+            runs: 2,
+            base: GaConfig { max_gens: 5, ..Default::default() },
+        };
+        let trials = tune(&config, &Closeness::new(42.0));
+        assert_eq!(trials.len(), 4);
+        for w in trials.windows(2) {
+            assert!(w[0].median_generations <= w[1].median_generations);
+        }
+    }
+}